@@ -22,14 +22,22 @@ use nrf52_radio::Radio;
 use nrf52_radio::tx_power::TxPower;
 use nrf52_radio::mode::Mode;
 use nrf52_radio::frequency::Frequency;
-use nrf52_radio::rx_addresses::RX_ADDRESS_ALL;
+use nrf52_radio::rx_addresses::RxAddresses;
 use nrf52_radio::base_address::BaseAddresses;
 use nrf52_radio::shortcuts::Shortcuts;
+use nrf52_radio::dma_buffer::DmaBuffer;
+use nrf52_radio::timestamps::Timestamps;
 
-use nrf52_esb::{Esb, protocol::Protocol as EsbProtocol};
+use nrf52_esb::{Esb, protocol::{Protocol as EsbProtocol, buffer_length}};
+use nrf52_esb::hopping::{ChannelHopping, HoppingPolicy};
+use nrf52_esb::timeout::Timeouts;
 
-use mdp_protocols::p905;
+use mdp_protocols::{p905, hopping_channels, RX_WINDOW};
 
+const PAYLOAD_LENGTH: u8 = 32;
+
+const TIMESTAMP_ADDRESS_PPI_CHANNEL: u8 = 0;
+const TIMESTAMP_END_PPI_CHANNEL: u8 = 1;
 
 #[entry]
 fn main() -> ! {
@@ -42,23 +50,31 @@ fn main() -> ! {
 
     let clocks = board.CLOCK.constrain().enable_ext_hfosc();
 
+    // Time base for the timeouts
+    let timestamps = Timestamps::new(&board.TIMER1, &board.RADIO, &board.PPI,
+                                     TIMESTAMP_ADDRESS_PPI_CHANNEL, TIMESTAMP_END_PPI_CHANNEL).unwrap();
+
     let radio = Radio::new(board.RADIO, &clocks);
     radio
-        .set_tx_power(TxPower::Pos8dBm)
+        .set_tx_power(TxPower::Pos8dBm).unwrap()
         .set_mode(Mode::Nrf2Mbit)
-        .set_frequency(Frequency::from_2400mhz_channel(78))
+        .set_frequency(Frequency::from_2400mhz_channel(78).unwrap())
         .set_base_addresses(BaseAddresses::from_same_four_bytes([0xa0, 0xb1, 0xc2, 0xd3]))
         .set_prefixes([0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7])
-        .set_rx_addresses(RX_ADDRESS_ALL)
+        .set_rx_addresses(RxAddresses::all())
         .set_shortcuts(/*Shortcuts::READY_START |*/ Shortcuts::END_DISABLE)
         .enable_power();
 
-    let mut buffer1 = [0x00u8; 34];
-    let mut buffer2 = [0x00u8; 34];
+    let mut buffer1 = [0x00u8; buffer_length(PAYLOAD_LENGTH)];
+    let mut buffer2 = [0x00u8; buffer_length(PAYLOAD_LENGTH)];
+
+    let channels = hopping_channels();
 
-    // TODO EsbProtocol and buffers size must match
-    let esb = Esb::new(radio, EsbProtocol::fixed_payload_length(32), &mut buffer1, &mut buffer2);
+    let mut esb = Esb::new(radio, EsbProtocol::fixed_payload_length(PAYLOAD_LENGTH),
+                           DmaBuffer::new(&mut buffer1).unwrap(), DmaBuffer::new(&mut buffer2).unwrap()).unwrap();
     esb.set_crc_16bits();
+    esb.set_channel_hopping(Some(ChannelHopping::new(&channels, HoppingPolicy::Scan(1)).unwrap())).unwrap();
+    esb.set_timeouts(Some(Timeouts::new(&timestamps).with_rx_window(RX_WINDOW))).unwrap();
 
     drop(board.uart_daplink.write_str("Starting ...\n"));
 
@@ -2,5 +2,29 @@
 
 use nrf52840_hal as hal;
 
+use nrf52_radio::frequency::Frequency;
+
 pub mod m01;
-pub mod p905;
\ No newline at end of file
+pub mod p905;
+
+/// Channels (2400 MHz + n) both sides walk through in the same order,
+/// starting at the one they used to be pinned to
+pub const CHANNELS: [u8; 3] = [78, 2, 40];
+
+/// Transmissions without acknowledgement before the M01 hops to the next channel
+pub const HOP_AFTER_FAILURES: u8 = 2;
+
+/// Microseconds the M01 waits for an acknowledgement
+pub const ACK_TIMEOUT: u32 = 500;
+
+/// Microseconds the P905 listens on a channel before moving to the next one.
+/// It is longer than the M01 takes to go through all the channels, so they meet again.
+pub const RX_WINDOW: u32 = 10_000;
+
+pub fn hopping_channels() -> [Frequency; 3] {
+  [
+    Frequency::from_2400mhz_channel(CHANNELS[0]).unwrap(),
+    Frequency::from_2400mhz_channel(CHANNELS[1]).unwrap(),
+    Frequency::from_2400mhz_channel(CHANNELS[2]).unwrap(),
+  ]
+}
\ No newline at end of file
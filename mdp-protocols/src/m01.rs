@@ -111,6 +111,8 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
 //            self.uarte.write_fmt(format_args!("{:?}: Pairing request sent ...\n", self.state));
             State::ReceivePairingResponse
           },
+          // Not acknowledged on any channel, start over
+          Err(nb::Error::Other(EsbError::Timeout)) => State::SendPairingRequest,
           Err(error) => self.handle_esb_error(error),
         }
       },
@@ -159,6 +161,8 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
 //            self.uarte.write_fmt(format_args!("{:?}: Data request sent ...\n", self.state));
             State::ReceiveDataResponse
           },
+          // Not acknowledged on any channel, start over
+          Err(nb::Error::Other(EsbError::Timeout)) => State::SendDataRequest,
           Err(error) => self.handle_esb_error(error),
         }
       },
@@ -188,7 +192,15 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
           Err(error) => self.handle_esb_error(error),
         }
       },
-      State::Error(_) => self.state,
+      State::Error(error) => {
+        if self.last_state != Some(self.state) {
+          drop(self.uarte.write_fmt(format_args!("{:?}: Resetting ...\n", error)));
+        }
+        match self.esb.reset() {
+          Ok(()) => State::Unpaired,
+          Err(_) => self.state,
+        }
+      },
     };
     self.last_state = Some(self.state);
     self.state = next_state;
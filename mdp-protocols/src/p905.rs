@@ -59,7 +59,7 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
     let next_state = match self.state {
       State::Unpaired => {
         drop(self.uarte.write_fmt(format_args!("{:?}: Listening for pairing request ...\n", self.state)));
-        let rx_config = RxConfig::default();
+        let rx_config = Self::rx_config();
         if let Err(err) = self.esb.start_rx(rx_config) {
           State::Error(Error::EsbError(err))
         }
@@ -107,7 +107,7 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
         if self.last_state.map(|s| s != State::WaitRequest).unwrap_or(true) {
           drop(self.uarte.write_fmt(format_args!("{:?}: Listening for requests ...\n", self.state)));
         }
-        let rx_config = RxConfig::default();
+        let rx_config = Self::rx_config();
         if let Err(err) = self.esb.start_rx(rx_config) {
           State::Error(Error::EsbError(err))
         }
@@ -132,12 +132,25 @@ impl<'a, LFOSC, LFSTAT> Protocol<'a, LFOSC, LFSTAT> {
           Err(error) => self.handle_esb_error(error),
         }
       },
-      State::Error(_) => self.state,
+      State::Error(error) => {
+        if self.last_state != Some(self.state) {
+          drop(self.uarte.write_fmt(format_args!("{:?}: Resetting ...\n", error)));
+        }
+        match self.esb.reset() {
+          Ok(()) => State::Unpaired,
+          Err(_) => self.state,
+        }
+      },
     };
     self.last_state = Some(self.state);
     self.state = next_state;
   }
 
+  /// Keep scanning the channels until the M01 shows up
+  fn rx_config() -> RxConfig {
+    RxConfig::default().with_retries(usize::MAX)
+  }
+
   fn handle_esb_error(&self, error: nb::Error<EsbError>) -> State {
     match error {
       nb::Error::WouldBlock => self.state,
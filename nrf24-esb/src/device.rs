@@ -0,0 +1,119 @@
+/*!
+
+SPI access to the nRF24L01+
+
+Every command selects the chip with CSN, shifts out the command byte, while the chip shifts in
+its STATUS, and then transfers the data bytes.
+
+See [Product Specification](https://www.sparkfun.com/datasheets/Components/SMD/nRF24L01Pluss_Preliminary_Product_Specification_v1_0.pdf):
+8.3 SPI operation
+
+*/
+
+use embedded_hal::blocking::spi::Transfer;
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::{Error, Result};
+use crate::registers::*;
+
+/// Longest address in bytes
+const MAX_ADDRESS_LENGTH: usize = 5;
+
+pub struct Device<SPI, CSN> {
+  spi: SPI,
+  csn: CSN,
+}
+
+impl<SPI, CSN, SpiE, PinE> Device<SPI, CSN>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE> {
+
+  pub fn new(spi: SPI, csn: CSN) -> Result<Self, SpiE, PinE> {
+    let mut csn = csn;
+    csn.set_high().map_err(Error::Pin)?;
+    Ok(Device { spi, csn })
+  }
+
+  pub fn free(self) -> (SPI, CSN) {
+    (self.spi, self.csn)
+  }
+
+  /// Send the command followed by `data`, which is replaced by the bytes from the chip
+  pub fn transfer(&mut self, command: u8, data: &mut [u8]) -> Result<Status, SpiE, PinE> {
+    self.csn.set_low().map_err(Error::Pin)?;
+    let result = self.transfer_selected(command, data);
+    self.csn.set_high().map_err(Error::Pin)?;
+    result
+  }
+
+  fn transfer_selected(&mut self, command: u8, data: &mut [u8]) -> Result<Status, SpiE, PinE> {
+    let mut command = [command];
+    let status = self.spi.transfer(&mut command).map_err(Error::Spi)?[0];
+    if !data.is_empty() {
+      self.spi.transfer(data).map_err(Error::Spi)?;
+    }
+    Ok(Status::from_bits_truncate(status))
+  }
+
+  pub fn command(&mut self, command: u8) -> Result<Status, SpiE, PinE> {
+    self.transfer(command, &mut [])
+  }
+
+  pub fn status(&mut self) -> Result<Status, SpiE, PinE> {
+    self.command(NOP)
+  }
+
+  /// Clear the interrupt flags, they are cleared by writing ones
+  pub fn clear_status(&mut self, status: Status) -> Result<(), SpiE, PinE> {
+    let flags = status & (Status::RX_DR | Status::TX_DS | Status::MAX_RT);
+    self.write_register(STATUS, flags.bits())
+  }
+
+  pub fn read_register(&mut self, register: u8) -> Result<u8, SpiE, PinE> {
+    let mut data = [0];
+    self.transfer(R_REGISTER | register, &mut data)?;
+    Ok(data[0])
+  }
+
+  pub fn write_register(&mut self, register: u8, value: u8) -> Result<(), SpiE, PinE> {
+    self.transfer(W_REGISTER | register, &mut [value])?;
+    Ok(())
+  }
+
+  pub fn modify_register<F>(&mut self, register: u8, f: F) -> Result<(), SpiE, PinE>
+    where F: FnOnce(u8) -> u8 {
+
+    let value = self.read_register(register)?;
+    self.write_register(register, f(value))
+  }
+
+  /// Address registers, least significant byte first
+  pub fn write_address(&mut self, register: u8, address: &[u8]) -> Result<(), SpiE, PinE> {
+    let mut data = [0; MAX_ADDRESS_LENGTH];
+    let data = &mut data[..address.len()];
+    data.copy_from_slice(address);
+    self.transfer(W_REGISTER | register, data)?;
+    Ok(())
+  }
+
+  /// Width of the payload at the top of the RX FIFO, when using dynamic payload length
+  pub fn read_payload_width(&mut self) -> Result<u8, SpiE, PinE> {
+    let mut data = [0];
+    self.transfer(R_RX_PL_WID, &mut data)?;
+    Ok(data[0])
+  }
+
+  pub fn read_payload(&mut self, payload: &mut [u8]) -> Result<(), SpiE, PinE> {
+    self.transfer(R_RX_PAYLOAD, payload)?;
+    Ok(())
+  }
+
+  /// Write a payload with one of W_TX_PAYLOAD, W_TX_PAYLOAD_NO_ACK or W_ACK_PAYLOAD
+  pub fn write_payload(&mut self, command: u8, payload: &[u8]) -> Result<(), SpiE, PinE> {
+    let mut data = [0; MAX_PAYLOAD_LENGTH as usize];
+    let data = &mut data[..payload.len()];
+    data.copy_from_slice(payload);
+    self.transfer(command, data)?;
+    Ok(())
+  }
+}
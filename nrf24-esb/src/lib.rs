@@ -0,0 +1,941 @@
+/*!
+
+Enhanced ShockBurst with an nRF24L01+ over SPI
+
+Its send, receive and acknowledgement methods follow the ones of `nrf52_esb::Esb`, with the same buffer layout,
+so the MDP protocols can be ported to the chip that the P905 uses to cross-check them. The types are its own,
+so the code that needs to run with both has to go through the `radio-traits` traits.
+
+The nRF24L01+ handles the acknowledgements and the retransmissions by itself, so there are
+a few differences with the nrf52 implementation:
+
+- The PID is not available, so it is always 0 in the rx buffer
+- Acknowledgement payloads are queued for a pipe before the packet arrives, and they require dynamic payload length
+- Transmissions fail with `MaxRetries` once the retries are exhausted, rather than waiting for an abort
+
+The driver only needs the SPI bus, the CSN and CE pins, and a delay, from `embedded-hal`.
+The IRQ pin is not used, the status is polled. On the host, it can run against a mocked SPI bus.
+
+```ignore
+let mut esb = Esb::new(spi, csn, ce, Protocol::fixed_payload_length(32).unwrap(), &mut delay)?;
+esb.set_frequency(78)?;
+esb.set_addresses(Addresses::new([0xa0, 0xb1, 0xc2, 0xd3], [0xa0, 0xb1, 0xc2, 0xd3],
+                                 [0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5]))?;
+esb.start_rx(RxConfig::default())?;
+block!(esb.wait_rx())?;
+```
+
+See [Product Specification](https://www.sparkfun.com/datasheets/Components/SMD/nRF24L01Pluss_Preliminary_Product_Specification_v1_0.pdf):
+7 Enhanced ShockBurst
+
+*/
+
+#![no_std]
+
+#[macro_use]
+extern crate bitflags;
+
+pub mod registers;
+pub mod device;
+pub mod protocol;
+pub mod traits;
+
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::spi::Transfer;
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::device::Device;
+use crate::protocol::{Protocol, BUFFER_LENGTH, HEADER_LENGTH};
+use crate::registers::*;
+
+pub type Result<A, SpiE, PinE> = core::result::Result<A, Error<SpiE, PinE>>;
+pub type AsyncResult<A, SpiE, PinE> = nb::Result<A, Error<SpiE, PinE>>;
+
+/// Time for the oscillator to start after PWR_UP
+const POWER_UP_MS: u8 = 2;
+
+/// Auto retransmit delay of 500 us, long enough for acknowledgements with payload at any data rate
+const ARD_500US: u8 = 1 << 4;
+
+/// Address width of 5 bytes
+const AW_5_BYTES: u8 = 0b11;
+
+const ALL_PIPES: u8 = (1 << PIPES) - 1;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error<SpiE, PinE> {
+  /// Standby required before starting a rx/tx transaction
+  StandbyRequired,
+
+  /// wait_rx or wait_tx called without a successful start_rx or start_tx before
+  ReceiveNotStarted,
+
+  /// Payload longer than what the protocol allows
+  PayloadTooLong,
+
+  /// The pipe is not between 0 and 5
+  InvalidPipe,
+
+  /// RF_CH above 125
+  InvalidChannel,
+
+  /// Acknowledgement payloads require dynamic payload length
+  DynamicPayloadRequired,
+
+  /// Dynamic payload length requires the automatic acknowledgement
+  AckRequired,
+
+  /// No acknowledgement after all the retries
+  MaxRetries,
+
+  /// No packet was received since the last reception started
+  NoPacket,
+
+  /// Buffer smaller than the received payload
+  BufferTooSmall,
+
+  /// Error from the SPI bus
+  Spi(SpiE),
+
+  /// Error from the CSN or CE pins
+  Pin(PinE),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataRate {
+  Rate250Kbit,
+  Rate1Mbit,
+  Rate2Mbit,
+}
+
+impl DataRate {
+  /// RF_DR_LOW and RF_DR_HIGH bits in RF_SETUP
+  fn value(&self) -> u8 {
+    match self {
+      DataRate::Rate250Kbit => 1 << 5,
+      DataRate::Rate1Mbit => 0,
+      DataRate::Rate2Mbit => 1 << 3,
+    }
+  }
+}
+
+/// RF_PWR levels in dBm
+const TX_POWER_LEVELS: [i8; 4] = [-18, -12, -6, 0];
+
+const RF_SETUP_DATA_RATE: u8 = (1 << 5) | (1 << 3);
+const RF_SETUP_RF_PWR: u8 = 0b11 << 1;
+
+/// Pipe addresses, as the base and prefix addresses of the nrf52 radio.
+/// The prefix is the least significant byte, as in the Nordic ESB library.
+/// Pipe 0 uses `base0`, and pipes 1 to 5 share `base1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Addresses {
+  base0: [u8; 4],
+  base1: [u8; 4],
+  prefixes: [u8; PIPES as usize],
+}
+
+impl Addresses {
+  pub fn new(base0: [u8; 4], base1: [u8; 4], prefixes: [u8; PIPES as usize]) -> Self {
+    Addresses { base0, base1, prefixes }
+  }
+
+  /// Address of a pipe for the address registers, least significant byte first
+  pub fn pipe(&self, pipe: u8) -> [u8; 5] {
+    let base = if pipe == 0 { self.base0 } else { self.base1 };
+    [self.prefixes[usize::from(pipe)], base[3], base[2], base[1], base[0]]
+  }
+}
+
+impl Default for Addresses {
+  /// Reset values of the nRF24L01+
+  fn default() -> Self {
+    Addresses {
+      base0: [0xe7; 4],
+      base1: [0xc2; 4],
+      prefixes: [0xe7, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RxConfig {
+  skip_ack: bool,
+}
+
+impl RxConfig {
+  pub fn with_skip_ack(self, skip_ack: bool) -> Self {
+    RxConfig { skip_ack }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TxConfig {
+  pipe: u8,
+  skip_ack: bool,
+  retries: u8,
+}
+
+impl Default for TxConfig {
+  fn default() -> Self {
+    TxConfig {
+      pipe: 0,
+      skip_ack: false,
+      retries: 1,
+    }
+  }
+}
+
+impl TxConfig {
+  /// Send to the address of `pipe`
+  pub fn new(pipe: u8) -> Self {
+    TxConfig { pipe, .. TxConfig::default() }
+  }
+
+  pub fn with_skip_ack(self, skip_ack: bool) -> Self {
+    TxConfig { skip_ack, .. self }
+  }
+
+  /// Up to 15 retries
+  pub fn with_retries(self, retries: u8) -> Self {
+    TxConfig { retries: retries.min(15), .. self }
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RxPacket {
+  pub length: u8,
+  pub pipe: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+  Standby,
+  Rx,
+  Tx(TxConfig),
+}
+
+pub struct Esb<SPI, CSN, CE> {
+  device: Device<SPI, CSN>,
+  ce: CE,
+  protocol: Protocol,
+  state: State,
+  addresses: Addresses,
+  rx_addresses: u8,
+  rx_buffer: [u8; BUFFER_LENGTH],
+  tx_buffer: [u8; BUFFER_LENGTH],
+  rx_packet: Option<RxPacket>,
+  ack_packet: Option<RxPacket>,
+  tx_config: TxConfig,
+  rx_config: RxConfig,
+}
+
+impl<SPI, CSN, CE, SpiE, PinE> Esb<SPI, CSN, CE>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE>,
+        CE: OutputPin<Error=PinE> {
+
+  /// Power up the chip with 16 bits CRC, 2 Mbit/s, 0 dBm, and the reset addresses
+  pub fn new<D>(spi: SPI, csn: CSN, ce: CE, protocol: Protocol, delay: &mut D) -> Result<Self, SpiE, PinE>
+    where D: DelayMs<u8> {
+
+    let mut ce = ce;
+    ce.set_low().map_err(Error::Pin)?;
+    let mut esb = Esb {
+      device: Device::new(spi, csn)?,
+      ce,
+      protocol,
+      state: State::Standby,
+      addresses: Addresses::default(),
+      rx_addresses: 0b11,
+      rx_buffer: [0; BUFFER_LENGTH],
+      tx_buffer: [0; BUFFER_LENGTH],
+      rx_packet: None,
+      ack_packet: None,
+      tx_config: TxConfig::default(),
+      rx_config: RxConfig::default(),
+    };
+
+    // The interrupts are masked as the IRQ pin is not used
+    let config = Config::MASK_RX_DR | Config::MASK_TX_DS | Config::MASK_MAX_RT
+        | Config::EN_CRC | Config::CRCO | Config::PWR_UP;
+    esb.device.write_register(CONFIG, config.bits())?;
+    delay.delay_ms(POWER_UP_MS);
+
+    esb.device.write_register(SETUP_AW, AW_5_BYTES)?;
+    esb.device.write_register(RF_SETUP, DataRate::Rate2Mbit.value() | RF_SETUP_RF_PWR)?;
+    esb.setup_protocol()?;
+    esb.write_addresses()?;
+    esb.device.write_register(EN_RXADDR, esb.rx_addresses)?;
+    esb.device.command(FLUSH_TX)?;
+    esb.device.command(FLUSH_RX)?;
+    esb.device.clear_status(Status::all())?;
+    Ok(esb)
+  }
+
+  /// Power down the chip and give back the bus and the pins
+  pub fn free(mut self) -> Result<(SPI, CSN, CE), SpiE, PinE> {
+    self.ce.set_low().map_err(Error::Pin)?;
+    self.device.modify_register(CONFIG, |config| config & !Config::PWR_UP.bits())?;
+    let (spi, csn) = self.device.free();
+    Ok((spi, csn, self.ce))
+  }
+
+  fn setup_protocol(&mut self) -> Result<(), SpiE, PinE> {
+    match self.protocol {
+      Protocol::DynamicPayloadLength(_) => {
+        let feature = Feature::EN_DPL | Feature::EN_ACK_PAY | Feature::EN_DYN_ACK;
+        self.device.write_register(FEATURE, feature.bits())?;
+        self.device.write_register(DYNPD, ALL_PIPES)
+      },
+      Protocol::FixedPayloadLength(length) => {
+        self.device.write_register(FEATURE, Feature::EN_DYN_ACK.bits())?;
+        self.device.write_register(DYNPD, 0)?;
+        for pipe in 0..PIPES {
+          self.device.write_register(RX_PW_P0 + pipe, length)?;
+        }
+        Ok(())
+      },
+    }
+  }
+
+  pub fn set_crc_disabled(&mut self) -> Result<(), SpiE, PinE> {
+    self.device.modify_register(CONFIG, |config| config & !(Config::EN_CRC | Config::CRCO).bits())
+  }
+
+  pub fn set_crc_8bits(&mut self) -> Result<(), SpiE, PinE> {
+    self.device.modify_register(CONFIG, |config| (config | Config::EN_CRC.bits()) & !Config::CRCO.bits())
+  }
+
+  pub fn set_crc_16bits(&mut self) -> Result<(), SpiE, PinE> {
+    self.device.modify_register(CONFIG, |config| config | (Config::EN_CRC | Config::CRCO).bits())
+  }
+
+  /// Channel as RF_CH, at 2400 + `channel` MHz
+  pub fn set_frequency(&mut self, channel: u8) -> Result<(), SpiE, PinE> {
+    if channel > MAX_CHANNEL {
+      return Err(Error::InvalidChannel);
+    }
+    self.device.write_register(RF_CH, channel)
+  }
+
+  pub fn set_data_rate(&mut self, data_rate: DataRate) -> Result<(), SpiE, PinE> {
+    self.device.modify_register(RF_SETUP, |setup| (setup & !RF_SETUP_DATA_RATE) | data_rate.value())
+  }
+
+  /// Output power in dBm, the nearest of -18, -12, -6 and 0 dBm. Returns the actual one.
+  pub fn set_tx_power(&mut self, dbm: i8) -> Result<i8, SpiE, PinE> {
+    let (level, actual) = TX_POWER_LEVELS.iter().enumerate()
+        .min_by_key(|(_, level)| (i16::from(**level) - i16::from(dbm)).abs())
+        .unwrap();
+    self.device.modify_register(RF_SETUP, |setup| (setup & !RF_SETUP_RF_PWR) | ((level as u8) << 1))?;
+    Ok(*actual)
+  }
+
+  /// Standby required
+  pub fn set_addresses(&mut self, addresses: Addresses) -> Result<(), SpiE, PinE> {
+    match self.state {
+      State::Standby => {
+        self.addresses = addresses;
+        self.write_addresses()
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn get_addresses(&self) -> Addresses {
+    self.addresses
+  }
+
+  fn write_addresses(&mut self) -> Result<(), SpiE, PinE> {
+    self.device.write_address(RX_ADDR_P0, &self.addresses.pipe(0))?;
+    self.device.write_address(RX_ADDR_P0 + 1, &self.addresses.pipe(1))?;
+    for pipe in 2..PIPES {
+      self.device.write_register(RX_ADDR_P0 + pipe, self.addresses.pipe(pipe)[0])?;
+    }
+    Ok(())
+  }
+
+  /// Pipes to listen to, one bit per pipe
+  pub fn set_rx_addresses(&mut self, pipes: u8) -> Result<(), SpiE, PinE> {
+    self.rx_addresses = pipes & ALL_PIPES;
+    self.device.write_register(EN_RXADDR, self.rx_addresses)
+  }
+
+  pub fn get_rx_addresses(&self) -> u8 {
+    self.rx_addresses
+  }
+
+  /// Received power above -64 dBm during the last reception
+  pub fn is_power_detected(&mut self) -> Result<bool, SpiE, PinE> {
+    Ok(self.device.read_register(RPD)? & 1 == 1)
+  }
+
+  pub fn get_rx_buffer(&self) -> &[u8] {
+    &self.rx_buffer[..HEADER_LENGTH + usize::from(self.protocol.max_payload_length())]
+  }
+
+  pub fn get_tx_buffer(&mut self) -> &mut [u8] {
+    &mut self.tx_buffer[..HEADER_LENGTH + usize::from(self.protocol.max_payload_length())]
+  }
+
+  pub fn get_last_received_packet(&self) -> Option<RxPacket> {
+    self.rx_packet
+  }
+
+  /// Acknowledgement received for the last transmitted packet.
+  /// Its payload (if any) is available from the rx buffer.
+  pub fn get_last_ack_packet(&self) -> Option<RxPacket> {
+    self.ack_packet
+  }
+
+  /// Copy the payload into the tx buffer and encode its length in the header.
+  /// The PID and NO_ACK bits of the header are left untouched.
+  pub fn set_tx_payload(&mut self, payload: &[u8]) -> Result<(), SpiE, PinE> {
+    let length = self.check_payload_length(payload)?;
+    self.tx_buffer[0] = match self.protocol {
+      Protocol::DynamicPayloadLength(_) => length,
+      Protocol::FixedPayloadLength(_) => 0,
+    };
+    let (data, padding) = self.tx_buffer[HEADER_LENGTH..].split_at_mut(payload.len());
+    data.copy_from_slice(payload);
+    for b in padding.iter_mut() {
+      *b = 0;
+    }
+    Ok(())
+  }
+
+  /// Payload to be sent with the next acknowledgement on `pipe`.
+  /// It is sent only once, following acknowledgements will be empty.
+  pub fn set_ack_payload(&mut self, pipe: u8, payload: &[u8]) -> Result<(), SpiE, PinE> {
+    if pipe >= PIPES {
+      return Err(Error::InvalidPipe);
+    }
+    match self.protocol {
+      Protocol::DynamicPayloadLength(_) => {
+        self.check_payload_length(payload)?;
+        self.device.write_payload(W_ACK_PAYLOAD | pipe, payload)
+      },
+      Protocol::FixedPayloadLength(_) => Err(Error::DynamicPayloadRequired),
+    }
+  }
+
+  /// Configuration for the transmissions started through the `Transmit` trait
+  pub fn set_tx_config(&mut self, tx_config: TxConfig) -> &Self {
+    self.tx_config = tx_config;
+    self
+  }
+
+  /// Configuration for the receptions started through the `Receive` trait
+  pub fn set_rx_config(&mut self, rx_config: RxConfig) -> &Self {
+    self.rx_config = rx_config;
+    self
+  }
+
+  /// Skipping the acknowledgements is only possible with fixed payload length,
+  /// as the nRF24L01+ requires EN_AA for the pipes with dynamic payload length.
+  pub fn start_rx(&mut self, rx_config: RxConfig) -> Result<(), SpiE, PinE> {
+    match self.state {
+      State::Standby => {
+        if let (true, Protocol::DynamicPayloadLength(_)) = (rx_config.skip_ack, self.protocol) {
+          return Err(Error::AckRequired);
+        }
+        // A transmission leaves the address of the destination in pipe 0 to receive the acknowledgement
+        self.device.write_address(RX_ADDR_P0, &self.addresses.pipe(0))?;
+        self.device.write_register(EN_RXADDR, self.rx_addresses)?;
+        self.device.write_register(EN_AA, if rx_config.skip_ack { 0 } else { ALL_PIPES })?;
+        self.device.modify_register(CONFIG, |config| config | Config::PRIM_RX.bits())?;
+        self.rx_packet = None;
+        self.ce.set_high().map_err(Error::Pin)?;
+        self.state = State::Rx;
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn wait_rx(&mut self) -> AsyncResult<(), SpiE, PinE> {
+    match self.state {
+      State::Rx => {
+        let status = self.device.status()?;
+        match status.rx_pipe() {
+          Some(pipe) => match self.read_payload(pipe)? {
+            Some(packet) => {
+              self.device.clear_status(Status::RX_DR)?;
+              self.ce.set_low().map_err(Error::Pin)?;
+              self.rx_packet = Some(packet);
+              self.state = State::Standby;
+              Ok(())
+            },
+            None => Err(nb::Error::WouldBlock),
+          },
+          None => Err(nb::Error::WouldBlock),
+        }
+      },
+      _ => Err(nb::Error::Other(Error::ReceiveNotStarted)),
+    }
+  }
+
+  pub fn start_tx(&mut self, tx_config: TxConfig) -> Result<(), SpiE, PinE> {
+    match self.state {
+      State::Standby => {
+        if tx_config.pipe >= PIPES {
+          return Err(Error::InvalidPipe);
+        }
+        let length = match self.protocol {
+          Protocol::DynamicPayloadLength(max_length) if self.tx_buffer[0] > max_length => {
+            return Err(Error::PayloadTooLong);
+          },
+          Protocol::DynamicPayloadLength(_) => self.tx_buffer[0],
+          Protocol::FixedPayloadLength(length) => length,
+        };
+
+        // The acknowledgement comes back from the destination address on pipe 0
+        let address = self.addresses.pipe(tx_config.pipe);
+        self.device.write_address(TX_ADDR, &address)?;
+        self.device.write_address(RX_ADDR_P0, &address)?;
+        self.device.write_register(EN_RXADDR, self.rx_addresses | 1)?;
+        self.device.write_register(EN_AA, ALL_PIPES)?;
+        self.device.write_register(SETUP_RETR, ARD_500US | tx_config.retries)?;
+        self.device.modify_register(CONFIG, |config| config & !Config::PRIM_RX.bits())?;
+
+        let no_ack = tx_config.skip_ack || self.tx_buffer[1] & 0x01 == 0x01;
+        let command = if no_ack { W_TX_PAYLOAD_NO_ACK } else { W_TX_PAYLOAD };
+        self.device.command(FLUSH_TX)?;
+        let end = HEADER_LENGTH + usize::from(length);
+        self.device.write_payload(command, &self.tx_buffer[HEADER_LENGTH..end])?;
+
+        self.ack_packet = None;
+        self.ce.set_high().map_err(Error::Pin)?;
+        self.state = State::Tx(TxConfig { skip_ack: no_ack, .. tx_config });
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn wait_tx(&mut self) -> AsyncResult<(), SpiE, PinE> {
+    match self.state {
+      State::Tx(config) => {
+        let status = self.device.status()?;
+        if status.contains(Status::TX_DS) {
+          self.ack_packet = match (config.skip_ack, status.rx_pipe()) {
+            (true, _) => None,
+            (false, Some(pipe)) => self.read_payload(pipe)?,
+            (false, None) => Some(RxPacket { length: 0, pipe: 0 }),
+          };
+          self.finish_tx(Status::TX_DS | Status::RX_DR)?;
+          Ok(())
+        }
+        else if status.contains(Status::MAX_RT) {
+          self.device.command(FLUSH_TX)?;
+          self.finish_tx(Status::MAX_RT)?;
+          Err(nb::Error::Other(Error::MaxRetries))
+        }
+        else {
+          Err(nb::Error::WouldBlock)
+        }
+      },
+      _ => Err(nb::Error::Other(Error::ReceiveNotStarted)),
+    }
+  }
+
+  /// Recover from any state by going back to standby and flushing the FIFOs
+  pub fn reset(&mut self) -> Result<(), SpiE, PinE> {
+    self.ce.set_low().map_err(Error::Pin)?;
+    self.device.command(FLUSH_TX)?;
+    self.device.command(FLUSH_RX)?;
+    self.device.clear_status(Status::all())?;
+    self.state = State::Standby;
+    Ok(())
+  }
+
+  fn finish_tx(&mut self, flags: Status) -> Result<(), SpiE, PinE> {
+    self.device.clear_status(flags)?;
+    self.ce.set_low().map_err(Error::Pin)?;
+    self.state = State::Standby;
+    Ok(())
+  }
+
+  /// Read the payload at the top of the RX FIFO into the rx buffer.
+  /// Payloads with an invalid width are flushed, as the datasheet requires.
+  fn read_payload(&mut self, pipe: u8) -> Result<Option<RxPacket>, SpiE, PinE> {
+    let (length, header_length) = match self.protocol {
+      Protocol::DynamicPayloadLength(max_length) => {
+        let width = self.device.read_payload_width()?;
+        if width > max_length {
+          self.device.command(FLUSH_RX)?;
+          self.device.clear_status(Status::RX_DR)?;
+          return Ok(None);
+        }
+        (width, width)
+      },
+      Protocol::FixedPayloadLength(length) => (length, 0),
+    };
+    self.rx_buffer[0] = header_length;
+    self.rx_buffer[1] = 0;
+    let end = HEADER_LENGTH + usize::from(length);
+    self.device.read_payload(&mut self.rx_buffer[HEADER_LENGTH..end])?;
+    Ok(Some(RxPacket { length: header_length, pipe }))
+  }
+
+  fn check_payload_length(&self, payload: &[u8]) -> Result<u8, SpiE, PinE> {
+    if payload.len() <= usize::from(self.protocol.max_payload_length()) {
+      Ok(payload.len() as u8)
+    }
+    else {
+      Err(Error::PayloadTooLong)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  extern crate std;
+
+  use core::cell::RefCell;
+  use core::convert::Infallible;
+  use std::rc::Rc;
+  use std::vec::Vec;
+
+  use super::*;
+
+  /// nRF24L01+ registers and RX FIFO behind the SPI bus, recording every command with the bytes written
+  struct Chip {
+    registers: [u8; 0x20],
+    addresses: [[u8; 5]; 0x20],
+    rx_fifo: Vec<(u8, Vec<u8>)>,
+    ce: bool,
+    command: Option<u8>,
+    data: Vec<u8>,
+    frames: Vec<(u8, Vec<u8>)>,
+  }
+
+  impl Chip {
+    fn new() -> Self {
+      Chip {
+        registers: [0; 0x20],
+        addresses: [[0; 5]; 0x20],
+        rx_fifo: Vec::new(),
+        ce: false,
+        command: None,
+        data: Vec::new(),
+        frames: Vec::new(),
+      }
+    }
+
+    fn status(&self) -> u8 {
+      let pipe = self.rx_fifo.first().map_or(0b111, |(pipe, _)| *pipe);
+      self.registers[usize::from(STATUS)] & 0x70 | pipe << 1
+    }
+
+    /// A packet in the RX FIFO, as received on `pipe`
+    fn receive(&mut self, pipe: u8, payload: &[u8]) {
+      self.rx_fifo.push((pipe, payload.to_vec()));
+      self.registers[usize::from(STATUS)] |= Status::RX_DR.bits();
+    }
+
+    fn set_status(&mut self, status: Status) {
+      self.registers[usize::from(STATUS)] |= status.bits();
+    }
+
+    fn transfer(&mut self, words: &mut [u8]) {
+      let command = match self.command {
+        None => {
+          self.command = Some(words[0]);
+          words[0] = self.status();
+          return;
+        },
+        Some(command) => command,
+      };
+      self.data.extend_from_slice(words);
+      let register = usize::from(command & 0x1f);
+      match command {
+        R_RX_PL_WID => words[0] = self.rx_fifo.first().map_or(0, |(_, payload)| payload.len() as u8),
+        R_RX_PAYLOAD => {
+          let (_, payload) = self.rx_fifo.remove(0);
+          words.copy_from_slice(&payload[..words.len()]);
+        },
+        _ if command & 0xe0 == R_REGISTER => words[0] = self.registers[register],
+        _ if command & 0xe0 == W_REGISTER && register == usize::from(STATUS) =>
+          self.registers[register] &= !words[0],
+        _ if command & 0xe0 == W_REGISTER => {
+          self.registers[register] = words[0];
+          self.addresses[register][..words.len()].copy_from_slice(words);
+        },
+        _ => {},
+      }
+    }
+
+    /// End of the command when CSN goes high
+    fn deselect(&mut self) {
+      if let Some(command) = self.command.take() {
+        if command == FLUSH_RX {
+          self.rx_fifo.clear();
+        }
+        self.frames.push((command, core::mem::take(&mut self.data)));
+      }
+    }
+
+    fn take_frames(&mut self) -> Vec<(u8, Vec<u8>)> {
+      core::mem::take(&mut self.frames)
+    }
+  }
+
+  struct Spi(Rc<RefCell<Chip>>);
+
+  impl Transfer<u8> for Spi {
+    type Error = Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> core::result::Result<&'w [u8], Infallible> {
+      self.0.borrow_mut().transfer(words);
+      Ok(words)
+    }
+  }
+
+  struct Csn(Rc<RefCell<Chip>>);
+
+  impl OutputPin for Csn {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+      Ok(())
+    }
+
+    fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+      self.0.borrow_mut().deselect();
+      Ok(())
+    }
+  }
+
+  struct Ce(Rc<RefCell<Chip>>);
+
+  impl OutputPin for Ce {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+      self.0.borrow_mut().ce = false;
+      Ok(())
+    }
+
+    fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+      self.0.borrow_mut().ce = true;
+      Ok(())
+    }
+  }
+
+  struct Delay;
+
+  impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, _ms: u8) {}
+  }
+
+  type TestEsb = Esb<Spi, Csn, Ce>;
+
+  fn setup(protocol: Protocol) -> (TestEsb, Rc<RefCell<Chip>>) {
+    let chip = Rc::new(RefCell::new(Chip::new()));
+    let esb = Esb::new(Spi(chip.clone()), Csn(chip.clone()), Ce(chip.clone()), protocol, &mut Delay).unwrap();
+    chip.borrow_mut().take_frames();
+    (esb, chip)
+  }
+
+  fn write(register: u8, data: &[u8]) -> (u8, Vec<u8>) {
+    (W_REGISTER | register, data.to_vec())
+  }
+
+  fn read(register: u8) -> (u8, Vec<u8>) {
+    (R_REGISTER | register, [0].to_vec())
+  }
+
+  fn command(command: u8) -> (u8, Vec<u8>) {
+    (command, Vec::new())
+  }
+
+  #[test]
+  fn protocol_lengths() {
+    assert!(Protocol::fixed_payload_length(32).is_ok());
+    assert_eq!(Protocol::fixed_payload_length(33).err(), Some(protocol::Error::InvalidLength));
+    assert_eq!(Protocol::dynamic_payload_length(33).err(), Some(protocol::Error::InvalidLength));
+    assert_eq!(Protocol::fixed_payload_length(0).err(), Some(protocol::Error::InvalidLength));
+  }
+
+  #[test]
+  fn new_configures_the_chip() {
+    let chip = Rc::new(RefCell::new(Chip::new()));
+    let protocol = Protocol::fixed_payload_length(32).unwrap();
+    Esb::new(Spi(chip.clone()), Csn(chip.clone()), Ce(chip.clone()), protocol, &mut Delay).unwrap();
+
+    let config = Config::MASK_RX_DR | Config::MASK_TX_DS | Config::MASK_MAX_RT
+        | Config::EN_CRC | Config::CRCO | Config::PWR_UP;
+    let expected = [
+      write(CONFIG, &[config.bits()]),
+      write(SETUP_AW, &[0b11]),
+      write(RF_SETUP, &[0x0e]),
+      write(FEATURE, &[Feature::EN_DYN_ACK.bits()]),
+      write(DYNPD, &[0]),
+      write(RX_PW_P0, &[32]),
+      write(RX_PW_P0 + 1, &[32]),
+      write(RX_PW_P0 + 2, &[32]),
+      write(RX_PW_P0 + 3, &[32]),
+      write(RX_PW_P0 + 4, &[32]),
+      write(RX_PW_P0 + 5, &[32]),
+      write(RX_ADDR_P0, &[0xe7, 0xe7, 0xe7, 0xe7, 0xe7]),
+      write(RX_ADDR_P0 + 1, &[0xc2, 0xc2, 0xc2, 0xc2, 0xc2]),
+      write(RX_ADDR_P0 + 2, &[0xc3]),
+      write(RX_ADDR_P0 + 3, &[0xc4]),
+      write(RX_ADDR_P0 + 4, &[0xc5]),
+      write(RX_ADDR_P0 + 5, &[0xc6]),
+      write(EN_RXADDR, &[0b11]),
+      command(FLUSH_TX),
+      command(FLUSH_RX),
+      write(STATUS, &[0x70]),
+    ];
+    let chip = chip.borrow();
+    assert_eq!(chip.frames, expected);
+    assert!(!chip.ce);
+  }
+
+  #[test]
+  fn new_with_dynamic_payload_length() {
+    let chip = Rc::new(RefCell::new(Chip::new()));
+    let protocol = Protocol::dynamic_payload_length(32).unwrap();
+    Esb::new(Spi(chip.clone()), Csn(chip.clone()), Ce(chip.clone()), protocol, &mut Delay).unwrap();
+
+    let chip = chip.borrow();
+    let feature = Feature::EN_DPL | Feature::EN_ACK_PAY | Feature::EN_DYN_ACK;
+    assert_eq!(chip.registers[usize::from(FEATURE)], feature.bits());
+    assert_eq!(chip.registers[usize::from(DYNPD)], 0x3f);
+    assert!(!chip.frames.iter().any(|(command, _)| *command == W_REGISTER | RX_PW_P0));
+  }
+
+  #[test]
+  fn start_tx() {
+    let (mut esb, chip) = setup(Protocol::dynamic_payload_length(32).unwrap());
+    esb.set_addresses(Addresses::new([0xa0, 0xb1, 0xc2, 0xd3], [0x10, 0x20, 0x30, 0x40],
+                                     [0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5])).unwrap();
+    esb.set_tx_payload(&[1, 2, 3]).unwrap();
+    chip.borrow_mut().take_frames();
+
+    esb.start_tx(TxConfig::new(1).with_retries(3)).unwrap();
+
+    let address = [0xe1, 0x40, 0x30, 0x20, 0x10];
+    let expected = [
+      write(TX_ADDR, &address),
+      write(RX_ADDR_P0, &address),
+      write(EN_RXADDR, &[0b11]),
+      write(EN_AA, &[0x3f]),
+      write(SETUP_RETR, &[0x13]),
+      read(CONFIG),
+      write(CONFIG, &[0x7e]),
+      command(FLUSH_TX),
+      (W_TX_PAYLOAD, [1, 2, 3].to_vec()),
+    ];
+    let mut chip = chip.borrow_mut();
+    assert_eq!(chip.take_frames(), expected);
+    assert!(chip.ce);
+    assert_eq!(esb.start_tx(TxConfig::default()), Err(Error::StandbyRequired));
+  }
+
+  #[test]
+  fn start_tx_without_ack() {
+    let (mut esb, chip) = setup(Protocol::fixed_payload_length(4).unwrap());
+    esb.set_tx_payload(&[1, 2, 3, 4]).unwrap();
+
+    esb.start_tx(TxConfig::default().with_skip_ack(true)).unwrap();
+    assert_eq!(chip.borrow_mut().take_frames().last(), Some(&(W_TX_PAYLOAD_NO_ACK, [1, 2, 3, 4].to_vec())));
+    esb.reset().unwrap();
+
+    // NO_ACK bit of the header
+    esb.get_tx_buffer()[1] = 0x01;
+    esb.start_tx(TxConfig::default()).unwrap();
+    assert_eq!(chip.borrow_mut().take_frames().last(), Some(&(W_TX_PAYLOAD_NO_ACK, [1, 2, 3, 4].to_vec())));
+    esb.reset().unwrap();
+
+    esb.get_tx_buffer()[1] = 0x00;
+    esb.start_tx(TxConfig::default()).unwrap();
+    assert_eq!(chip.borrow_mut().take_frames().last(), Some(&(W_TX_PAYLOAD, [1, 2, 3, 4].to_vec())));
+  }
+
+  #[test]
+  fn wait_tx_acknowledged() {
+    let (mut esb, chip) = setup(Protocol::dynamic_payload_length(32).unwrap());
+    esb.set_tx_payload(&[1]).unwrap();
+    esb.start_tx(TxConfig::default()).unwrap();
+    assert_eq!(esb.wait_tx(), Err(nb::Error::WouldBlock));
+
+    chip.borrow_mut().set_status(Status::TX_DS);
+    assert_eq!(esb.wait_tx(), Ok(()));
+    let ack = esb.get_last_ack_packet().unwrap();
+    assert_eq!((ack.length, ack.pipe), (0, 0));
+    assert_eq!(chip.borrow().status() & 0x70, 0);
+    assert!(!chip.borrow().ce);
+
+    // Acknowledgement with payload
+    esb.start_tx(TxConfig::default()).unwrap();
+    chip.borrow_mut().receive(0, &[9, 8]);
+    chip.borrow_mut().set_status(Status::TX_DS);
+    assert_eq!(esb.wait_tx(), Ok(()));
+    let ack = esb.get_last_ack_packet().unwrap();
+    assert_eq!((ack.length, ack.pipe), (2, 0));
+    assert_eq!(&esb.get_rx_buffer()[..4], &[2, 0, 9, 8]);
+    assert_eq!(chip.borrow().status() & 0x70, 0);
+  }
+
+  #[test]
+  fn wait_tx_max_retries() {
+    let (mut esb, chip) = setup(Protocol::dynamic_payload_length(32).unwrap());
+    esb.set_tx_payload(&[1]).unwrap();
+    esb.start_tx(TxConfig::default()).unwrap();
+    chip.borrow_mut().take_frames();
+
+    chip.borrow_mut().set_status(Status::MAX_RT);
+    assert_eq!(esb.wait_tx(), Err(nb::Error::Other(Error::MaxRetries)));
+    assert!(esb.get_last_ack_packet().is_none());
+    let mut chip = chip.borrow_mut();
+    let frames = chip.take_frames();
+    assert!(frames.contains(&command(FLUSH_TX)));
+    assert!(frames.contains(&write(STATUS, &[Status::MAX_RT.bits()])));
+    assert!(!chip.ce);
+    drop(chip);
+    assert_eq!(esb.start_tx(TxConfig::default()), Ok(()));
+  }
+
+  #[test]
+  fn wait_rx_flushes_invalid_width() {
+    let (mut esb, chip) = setup(Protocol::dynamic_payload_length(8).unwrap());
+    esb.start_rx(RxConfig::default()).unwrap();
+    assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+
+    chip.borrow_mut().receive(1, &[0; 20]);
+    chip.borrow_mut().take_frames();
+    assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+    {
+      let mut chip = chip.borrow_mut();
+      let frames = chip.take_frames();
+      assert!(frames.contains(&command(FLUSH_RX)));
+      assert!(!frames.iter().any(|(command, _)| *command == R_RX_PAYLOAD));
+      assert!(chip.rx_fifo.is_empty());
+      assert_eq!(chip.status() & Status::RX_DR.bits(), 0);
+    }
+
+    chip.borrow_mut().receive(2, &[5, 6, 7]);
+    assert_eq!(esb.wait_rx(), Ok(()));
+    let packet = esb.get_last_received_packet().unwrap();
+    assert_eq!((packet.length, packet.pipe), (3, 2));
+    assert_eq!(&esb.get_rx_buffer()[..5], &[3, 0, 5, 6, 7]);
+    assert!(!chip.borrow().ce);
+  }
+
+  #[test]
+  fn start_rx_skip_ack() {
+    let (mut esb, _) = setup(Protocol::dynamic_payload_length(32).unwrap());
+    assert_eq!(esb.start_rx(RxConfig::default().with_skip_ack(true)), Err(Error::AckRequired));
+
+    let (mut esb, chip) = setup(Protocol::fixed_payload_length(32).unwrap());
+    esb.start_rx(RxConfig::default().with_skip_ack(true)).unwrap();
+    let chip = chip.borrow();
+    assert_eq!(chip.registers[usize::from(EN_AA)], 0);
+    assert_eq!(chip.registers[usize::from(CONFIG)] & Config::PRIM_RX.bits(), Config::PRIM_RX.bits());
+    assert!(chip.ce);
+  }
+}
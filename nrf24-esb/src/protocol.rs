@@ -0,0 +1,60 @@
+
+use crate::registers::MAX_PAYLOAD_LENGTH;
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// Payload length above the 32 bytes of the nRF24L01+, or a fixed length of 0
+  InvalidLength,
+}
+
+/// Number of bytes in the buffers that precede the payload: LENGTH and PID + NO_ACK,
+/// as in the `nrf52-esb` buffers
+pub const HEADER_LENGTH: usize = 2;
+
+/// Size in bytes of the buffers, they hold the largest payload
+pub const BUFFER_LENGTH: usize = HEADER_LENGTH + MAX_PAYLOAD_LENGTH as usize;
+
+#[derive(Clone, Copy)]
+pub enum Protocol {
+  /// Dynamic Payload up to a maximum number of bytes
+  DynamicPayloadLength(u8),
+
+  /// Fixed Payload of a given number of bytes
+  FixedPayloadLength(u8),
+}
+
+impl Protocol {
+  /// Dynamic Payload up to a maximum number of bytes
+  pub fn dynamic_payload_length(max_length: u8) -> Result<Self> {
+    check_length(max_length)?;
+    Ok(Protocol::DynamicPayloadLength(max_length))
+  }
+
+  /// Fixed Payload of a given number of bytes, at least 1 as a width of 0 disables the pipe
+  pub fn fixed_payload_length(length: u8) -> Result<Self> {
+    if length == 0 {
+      return Err(Error::InvalidLength);
+    }
+    check_length(length)?;
+    Ok(Protocol::FixedPayloadLength(length))
+  }
+
+  /// Maximum number of payload bytes that a packet can carry
+  pub fn max_payload_length(&self) -> u8 {
+    match self {
+      Protocol::DynamicPayloadLength(max_length) => *max_length,
+      Protocol::FixedPayloadLength(length) => *length,
+    }
+  }
+}
+
+fn check_length(length: u8) -> Result<()> {
+  if length <= MAX_PAYLOAD_LENGTH {
+    Ok(())
+  }
+  else {
+    Err(Error::InvalidLength)
+  }
+}
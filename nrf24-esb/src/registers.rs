@@ -0,0 +1,87 @@
+/*!
+
+SPI commands and registers of the nRF24L01+
+
+See [Product Specification](https://www.sparkfun.com/datasheets/Components/SMD/nRF24L01Pluss_Preliminary_Product_Specification_v1_0.pdf):
+- 8.3.1 SPI commands
+- 9 Register Map
+
+*/
+
+pub const R_REGISTER: u8 = 0x00;
+pub const W_REGISTER: u8 = 0x20;
+pub const R_RX_PAYLOAD: u8 = 0x61;
+pub const W_TX_PAYLOAD: u8 = 0xa0;
+pub const FLUSH_TX: u8 = 0xe1;
+pub const FLUSH_RX: u8 = 0xe2;
+pub const R_RX_PL_WID: u8 = 0x60;
+pub const W_ACK_PAYLOAD: u8 = 0xa8;
+pub const W_TX_PAYLOAD_NO_ACK: u8 = 0xb0;
+pub const NOP: u8 = 0xff;
+
+pub const CONFIG: u8 = 0x00;
+pub const EN_AA: u8 = 0x01;
+pub const EN_RXADDR: u8 = 0x02;
+pub const SETUP_AW: u8 = 0x03;
+pub const SETUP_RETR: u8 = 0x04;
+pub const RF_CH: u8 = 0x05;
+pub const RF_SETUP: u8 = 0x06;
+pub const STATUS: u8 = 0x07;
+pub const RPD: u8 = 0x09;
+pub const RX_ADDR_P0: u8 = 0x0a;
+pub const TX_ADDR: u8 = 0x10;
+pub const RX_PW_P0: u8 = 0x11;
+pub const DYNPD: u8 = 0x1c;
+pub const FEATURE: u8 = 0x1d;
+
+/// Number of data pipes
+pub const PIPES: u8 = 6;
+
+/// Highest RF_CH
+pub const MAX_CHANNEL: u8 = 125;
+
+/// Largest payload
+pub const MAX_PAYLOAD_LENGTH: u8 = 32;
+
+bitflags! {
+    /// 9 Register Map: CONFIG
+    pub struct Config: u8 {
+        const MASK_RX_DR = 1 << 6;
+        const MASK_TX_DS = 1 << 5;
+        const MASK_MAX_RT = 1 << 4;
+        const EN_CRC = 1 << 3;
+        const CRCO = 1 << 2;
+        const PWR_UP = 1 << 1;
+        const PRIM_RX = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// 9 Register Map: STATUS, it is also shifted out with the first byte of every command
+    pub struct Status: u8 {
+        const RX_DR = 1 << 6;
+        const TX_DS = 1 << 5;
+        const MAX_RT = 1 << 4;
+        const RX_P_NO = 0b111 << 1;
+        const TX_FULL = 1 << 0;
+    }
+}
+
+bitflags! {
+    /// 9 Register Map: FEATURE
+    pub struct Feature: u8 {
+        const EN_DPL = 1 << 2;
+        const EN_ACK_PAY = 1 << 1;
+        const EN_DYN_ACK = 1 << 0;
+    }
+}
+
+impl Status {
+  /// Pipe of the payload at the top of the RX FIFO, None when it is empty
+  pub fn rx_pipe(&self) -> Option<u8> {
+    match (self.bits() & Status::RX_P_NO.bits()) >> 1 {
+      pipe if pipe < PIPES => Some(pipe),
+      _ => None,
+    }
+  }
+}
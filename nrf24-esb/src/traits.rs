@@ -0,0 +1,119 @@
+/*!
+
+Generic radio traits
+
+The transactions started through the traits use the configurations given by `Esb::set_tx_config`
+and `Esb::set_rx_config`. The channel is the RF_CH.
+
+The nRF24L01+ only tells whether the received power was above -64 dBm, so the RSSI of the
+received packets is either -64 dBm or the sensitivity at 2 Mbit/s, -82 dBm.
+
+*/
+
+use embedded_hal::blocking::spi::Transfer;
+use embedded_hal::digital::v2::OutputPin;
+
+use radio_traits::{Channel, Power, Receive, ReceiveInfo, Transmit};
+
+use crate::{Esb, Error, Result, RxPacket};
+use crate::protocol::{Protocol, HEADER_LENGTH};
+
+const RPD_DBM: i16 = -64;
+const SENSITIVITY_DBM: i16 = -82;
+
+/// Information about the last received packet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RxInfo {
+  pub rssi: i16,
+  pub packet: Option<RxPacket>,
+}
+
+impl ReceiveInfo for RxInfo {
+  fn rssi(&self) -> i16 {
+    self.rssi
+  }
+}
+
+impl<SPI, CSN, CE, SpiE, PinE> Transmit for Esb<SPI, CSN, CE>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE>,
+        CE: OutputPin<Error=PinE> {
+
+  type Error = Error<SpiE, PinE>;
+
+  fn start_transmit(&mut self, data: &[u8]) -> Result<(), SpiE, PinE> {
+    self.set_tx_payload(data)?;
+    self.start_tx(self.tx_config)
+  }
+
+  fn check_transmit(&mut self) -> Result<bool, SpiE, PinE> {
+    check(self.wait_tx())
+  }
+}
+
+impl<SPI, CSN, CE, SpiE, PinE> Receive for Esb<SPI, CSN, CE>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE>,
+        CE: OutputPin<Error=PinE> {
+
+  type Error = Error<SpiE, PinE>;
+  type Info = RxInfo;
+
+  fn start_receive(&mut self) -> Result<(), SpiE, PinE> {
+    self.start_rx(self.rx_config)
+  }
+
+  /// Packets with an invalid CRC never reach the RX FIFO, so `restart` has no effect
+  fn check_receive(&mut self, _restart: bool) -> Result<bool, SpiE, PinE> {
+    check(self.wait_rx())
+  }
+
+  fn get_received(&mut self, info: &mut RxInfo, buffer: &mut [u8]) -> Result<usize, SpiE, PinE> {
+    let packet = self.rx_packet.ok_or(Error::NoPacket)?;
+    let length = match self.protocol {
+      Protocol::DynamicPayloadLength(_) => usize::from(packet.length),
+      Protocol::FixedPayloadLength(length) => usize::from(length),
+    };
+    if buffer.len() < length {
+      return Err(Error::BufferTooSmall);
+    }
+    buffer[..length].copy_from_slice(&self.rx_buffer[HEADER_LENGTH..HEADER_LENGTH + length]);
+    info.rssi = if self.is_power_detected()? { RPD_DBM } else { SENSITIVITY_DBM };
+    info.packet = Some(packet);
+    Ok(length)
+  }
+}
+
+impl<SPI, CSN, CE, SpiE, PinE> Channel for Esb<SPI, CSN, CE>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE>,
+        CE: OutputPin<Error=PinE> {
+
+  type Channel = u8;
+  type Error = Error<SpiE, PinE>;
+
+  fn set_channel(&mut self, channel: &u8) -> Result<(), SpiE, PinE> {
+    self.set_frequency(*channel)
+  }
+}
+
+impl<SPI, CSN, CE, SpiE, PinE> Power for Esb<SPI, CSN, CE>
+  where SPI: Transfer<u8, Error=SpiE>,
+        CSN: OutputPin<Error=PinE>,
+        CE: OutputPin<Error=PinE> {
+
+  type Error = Error<SpiE, PinE>;
+
+  fn set_power(&mut self, power: i8) -> Result<(), SpiE, PinE> {
+    self.set_tx_power(power)?;
+    Ok(())
+  }
+}
+
+fn check<SpiE, PinE>(result: nb::Result<(), Error<SpiE, PinE>>) -> Result<bool, SpiE, PinE> {
+  match result {
+    Ok(()) => Ok(true),
+    Err(nb::Error::WouldBlock) => Ok(false),
+    Err(nb::Error::Other(error)) => Err(error),
+  }
+}
@@ -0,0 +1,416 @@
+/*!
+
+Software encoder and decoder for complete on-air ESB frames
+
+```text
+| Preamble | Address  | PCF                          | Payload     | CRC      |
+| 1 byte   | 3-5 bytes| Length (6) PID (2) NO_ACK (1)| 0-32 bytes  | 0-2 bytes|
+```
+
+All the fields are sent most significant bit first. The address is given in on-air order,
+that is, the base address followed by the prefix. The CRC covers the address, the PCF and the payload,
+and as the PCF is 9 bits long the payload and the CRC are not byte aligned within the frame.
+
+See [nRF24L01+ Product Specification](https://infocenter.nordicsemi.com/pdf/nRF24L01P_PS_v1.0.pdf): 7.3 Enhanced ShockBurst packet format
+
+*/
+
+use crate::protocol::Protocol;
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The output buffer can not hold the frame
+  BufferTooSmall,
+
+  /// Address length out of the 3 to 5 bytes range
+  InvalidAddressLength,
+
+  /// Payload longer than what the protocol allows, or not matching the PCF length
+  InvalidPayloadLength,
+
+  /// The preamble does not match the first bit of the address
+  InvalidPreamble,
+
+  /// The frame ends before all its fields could be read
+  Truncated,
+
+  /// The CRC does not match the contents of the frame
+  CrcMismatch,
+}
+
+/// Length of the CRC field
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Crc {
+  Disabled,
+  OneByte,
+  TwoBytes,
+}
+
+impl Crc {
+  fn length(&self) -> usize {
+    match self {
+      Crc::Disabled => 0,
+      Crc::OneByte => 1,
+      Crc::TwoBytes => 2,
+    }
+  }
+}
+
+/// Packet Control Field
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct Pcf {
+  pub length: u8,
+  pub pid: u8,
+  pub no_ack: bool,
+}
+
+impl Pcf {
+  pub fn new(length: u8, pid: u8, no_ack: bool) -> Self {
+    Pcf { length: length & 0x3f, pid: pid & 0x03, no_ack }
+  }
+
+  fn value(&self) -> u32 {
+    u32::from(self.length) << 3 | u32::from(self.pid) << 1 | if self.no_ack { 1 } else { 0 }
+  }
+
+  fn from_value(value: u32) -> Self {
+    Pcf::new((value >> 3) as u8, (value >> 1) as u8, value & 0x01 == 0x01)
+  }
+}
+
+/// Frame layout parameters that are not part of the frame itself
+#[derive(Clone, Copy)]
+pub struct FrameConfig {
+  pub address_length: usize,
+  pub protocol: Protocol,
+  pub crc: Crc,
+}
+
+impl FrameConfig {
+  pub fn new(address_length: usize, protocol: Protocol, crc: Crc) -> Self {
+    FrameConfig { address_length, protocol, crc }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame<'a> {
+  pub address: &'a [u8],
+  pub pcf: Pcf,
+  pub payload: &'a [u8],
+}
+
+impl<'a> Frame<'a> {
+  pub fn new(address: &'a [u8], pcf: Pcf, payload: &'a [u8]) -> Self {
+    Frame { address, pcf, payload }
+  }
+}
+
+const PREAMBLE_BITS: usize = 8;
+const PCF_BITS: usize = 9;
+
+/// Preamble for an address, alternating bits starting with the first bit of the address
+pub fn preamble(address: &[u8]) -> u8 {
+  match address.first() {
+    Some(byte) if byte & 0x80 != 0 => 0xaa,
+    _ => 0x55,
+  }
+}
+
+/// Number of bytes required to hold a frame
+pub fn frame_length(address_length: usize, payload_length: usize, crc: Crc) -> usize {
+  let bits = PREAMBLE_BITS + address_length * 8 + PCF_BITS + payload_length * 8 + crc.length() * 8;
+  (bits + 7) / 8
+}
+
+/// Encode a frame into `output` and return the number of bytes written.
+pub fn encode(frame: &Frame, config: &FrameConfig, output: &mut [u8]) -> Result<usize> {
+  check_address_length(frame.address.len())?;
+  if frame.address.len() != config.address_length {
+    return Err(Error::InvalidAddressLength);
+  }
+  let payload_length = frame.payload.len();
+  let valid_payload_length = match config.protocol {
+    Protocol::DynamicPayloadLength(max_length) =>
+      payload_length <= usize::from(max_length) && payload_length == usize::from(frame.pcf.length),
+    Protocol::FixedPayloadLength(length) =>
+      payload_length == usize::from(length),
+  };
+  if !valid_payload_length {
+    return Err(Error::InvalidPayloadLength);
+  }
+  let length = frame_length(frame.address.len(), payload_length, config.crc);
+  if output.len() < length {
+    return Err(Error::BufferTooSmall);
+  }
+
+  let mut writer = BitWriter::new(&mut output[..length]);
+  writer.write_bits(u32::from(preamble(frame.address)), PREAMBLE_BITS);
+  for byte in frame.address.iter() {
+    writer.write_bits(u32::from(*byte), 8);
+  }
+  writer.write_bits(frame.pcf.value(), PCF_BITS);
+  for byte in frame.payload.iter() {
+    writer.write_bits(u32::from(*byte), 8);
+  }
+  let crc_start = writer.position;
+  let crc = crc(writer.buffer, PREAMBLE_BITS, crc_start, config.crc);
+  writer.write_bits(crc, config.crc.length() * 8);
+  writer.pad();
+
+  Ok(length)
+}
+
+/// Decode a frame starting at the preamble.
+/// The payload is copied into `payload_buffer` as it is not byte aligned within the frame.
+pub fn decode<'a>(input: &'a [u8], config: &FrameConfig, payload_buffer: &'a mut [u8]) -> Result<Frame<'a>> {
+  check_address_length(config.address_length)?;
+  let address_end = 1 + config.address_length;
+  if input.len() < address_end {
+    return Err(Error::Truncated);
+  }
+  let address = &input[1..address_end];
+  if input[0] != preamble(address) {
+    return Err(Error::InvalidPreamble);
+  }
+
+  let reader = BitReader::new(input);
+  let pcf_start = address_end * 8;
+  if reader.len() < pcf_start + PCF_BITS {
+    return Err(Error::Truncated);
+  }
+  let pcf = Pcf::from_value(reader.read_bits(pcf_start, PCF_BITS));
+  let payload_length = match config.protocol {
+    Protocol::DynamicPayloadLength(max_length) if pcf.length > max_length =>
+      return Err(Error::InvalidPayloadLength),
+    Protocol::DynamicPayloadLength(_) => usize::from(pcf.length),
+    Protocol::FixedPayloadLength(length) => usize::from(length),
+  };
+
+  let payload_start = pcf_start + PCF_BITS;
+  let crc_start = payload_start + payload_length * 8;
+  let crc_length = config.crc.length() * 8;
+  if reader.len() < crc_start + crc_length {
+    return Err(Error::Truncated);
+  }
+  if payload_buffer.len() < payload_length {
+    return Err(Error::BufferTooSmall);
+  }
+  let received_crc = reader.read_bits(crc_start, crc_length);
+  if received_crc != crc(input, PREAMBLE_BITS, crc_start, config.crc) {
+    return Err(Error::CrcMismatch);
+  }
+
+  let payload = &mut payload_buffer[..payload_length];
+  for (index, byte) in payload.iter_mut().enumerate() {
+    *byte = reader.read_bits(payload_start + index * 8, 8) as u8;
+  }
+
+  Ok(Frame { address, pcf, payload })
+}
+
+/// CRC of the bits in the range `[start, end)`, as computed by the radio
+pub fn crc(buffer: &[u8], start: usize, end: usize, crc: Crc) -> u32 {
+  let reader = BitReader::new(buffer);
+  let (initial, polynomial, bits) = match crc {
+    Crc::Disabled => return 0,
+    Crc::OneByte => (0xff, 0x07, 8),
+    Crc::TwoBytes => (0xffff, 0x1021, 16),
+  };
+  let msb = 1 << (bits - 1);
+  let mask = (1 << bits) - 1;
+  (start..end).fold(initial, |value: u32, position| {
+    let feedback = (value & msb != 0) ^ reader.read_bit(position);
+    let value = (value << 1) & mask;
+    if feedback { value ^ polynomial } else { value }
+  })
+}
+
+fn check_address_length(length: usize) -> Result<()> {
+  if length >= 3 && length <= 5 {
+    Ok(())
+  }
+  else {
+    Err(Error::InvalidAddressLength)
+  }
+}
+
+/// Writes bits most significant first
+pub(crate) struct BitWriter<'a> {
+  pub(crate) buffer: &'a mut [u8],
+  pub(crate) position: usize,
+}
+
+impl<'a> BitWriter<'a> {
+  pub(crate) fn new(buffer: &'a mut [u8]) -> Self {
+    BitWriter { buffer, position: 0 }
+  }
+
+  fn write_bit(&mut self, bit: bool) {
+    let mask = 0x80 >> (self.position % 8);
+    let byte = &mut self.buffer[self.position / 8];
+    if bit { *byte |= mask } else { *byte &= !mask }
+    self.position += 1;
+  }
+
+  pub(crate) fn write_bits(&mut self, value: u32, count: usize) {
+    for index in (0..count).rev() {
+      self.write_bit((value >> index) & 0x01 == 0x01);
+    }
+  }
+
+  /// Fill with zeros up to the end of the current byte
+  pub(crate) fn pad(&mut self) {
+    while self.position % 8 != 0 {
+      self.write_bit(false);
+    }
+  }
+}
+
+/// Reads bits most significant first
+pub(crate) struct BitReader<'a> {
+  buffer: &'a [u8],
+}
+
+impl<'a> BitReader<'a> {
+  pub(crate) fn new(buffer: &'a [u8]) -> Self {
+    BitReader { buffer }
+  }
+
+  fn len(&self) -> usize {
+    self.buffer.len() * 8
+  }
+
+  fn read_bit(&self, position: usize) -> bool {
+    self.buffer[position / 8] & (0x80 >> (position % 8)) != 0
+  }
+
+  pub(crate) fn read_bits(&self, position: usize, count: usize) -> u32 {
+    (position..position + count).fold(0, |value, position| {
+      value << 1 | if self.read_bit(position) { 1 } else { 0 }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Reference frames computed bit by bit from the nRF24L01+ packet format, independently of this encoder
+
+  /// Dynamic payload, CRC16, address e7e7e7e7e7, PID 1
+  const ADDRESS_A: [u8; 5] = [0xe7, 0xe7, 0xe7, 0xe7, 0xe7];
+  const PAYLOAD_A: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+  const FRAME_A: [u8; 14] = [0xaa, 0xe7, 0xe7, 0xe7, 0xe7, 0xe7, 0x11, 0x00, 0x81, 0x01, 0x82, 0x71, 0xb9, 0x00];
+
+  /// Fixed payload, CRC8, 3 bytes address, PID 2, no ack
+  const ADDRESS_B: [u8; 3] = [0xc2, 0xc2, 0xc2];
+  const PAYLOAD_B: [u8; 4] = [0x10, 0x20, 0x30, 0x40];
+  const FRAME_B: [u8; 11] = [0xaa, 0xc2, 0xc2, 0xc2, 0x02, 0x88, 0x10, 0x18, 0x20, 0x5b, 0x80];
+
+  /// Dynamic payload, CRC8, address starting with a 0 bit, PID 3, no ack
+  const ADDRESS_C: [u8; 5] = [0x12, 0x34, 0x56, 0x78, 0x9a];
+  const PAYLOAD_C: [u8; 2] = [0xab, 0xcd];
+  const FRAME_C: [u8; 11] = [0x55, 0x12, 0x34, 0x56, 0x78, 0x9a, 0x0b, 0xd5, 0xe6, 0xb6, 0x80];
+
+  /// Empty ACK, CRC16, PID 0
+  const ADDRESS_D: [u8; 5] = [0xa0, 0xb1, 0xc2, 0xd3, 0xe0];
+  const FRAME_D: [u8; 10] = [0xaa, 0xa0, 0xb1, 0xc2, 0xd3, 0xe0, 0x00, 0x19, 0xed, 0x00];
+
+  fn check_encode(frame: &Frame, config: &FrameConfig, expected: &[u8]) {
+    let mut output = [0xffu8; 64];
+    let length = encode(frame, config, &mut output).unwrap();
+    assert_eq!(length, frame_length(frame.address.len(), frame.payload.len(), config.crc));
+    assert_eq!(&output[..length], expected);
+  }
+
+  fn check_decode(input: &[u8], config: &FrameConfig, expected: &Frame) {
+    let mut payload = [0u8; 32];
+    assert_eq!(decode(input, config, &mut payload).as_ref(), Ok(expected));
+  }
+
+  #[test]
+  fn crc_check_values() {
+    assert_eq!(crc(b"123456789", 0, 72, Crc::TwoBytes), 0x29b1);
+    assert_eq!(crc(b"123456789", 0, 72, Crc::OneByte), 0xfb);
+    assert_eq!(crc(b"123456789", 0, 72, Crc::Disabled), 0);
+  }
+
+  #[test]
+  fn dynamic_payload_crc16() {
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+    let frame = Frame::new(&ADDRESS_A, Pcf::new(4, 1, false), &PAYLOAD_A);
+    check_encode(&frame, &config, &FRAME_A);
+    check_decode(&FRAME_A, &config, &frame);
+  }
+
+  #[test]
+  fn fixed_payload_crc8() {
+    let config = FrameConfig::new(3, Protocol::fixed_payload_length(4), Crc::OneByte);
+    let frame = Frame::new(&ADDRESS_B, Pcf::new(0, 2, true), &PAYLOAD_B);
+    check_encode(&frame, &config, &FRAME_B);
+    check_decode(&FRAME_B, &config, &frame);
+  }
+
+  #[test]
+  fn dynamic_payload_crc8() {
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(32), Crc::OneByte);
+    let frame = Frame::new(&ADDRESS_C, Pcf::new(2, 3, true), &PAYLOAD_C);
+    check_encode(&frame, &config, &FRAME_C);
+    check_decode(&FRAME_C, &config, &frame);
+  }
+
+  #[test]
+  fn empty_ack() {
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+    let frame = Frame::new(&ADDRESS_D, Pcf::new(0, 0, false), &[]);
+    check_encode(&frame, &config, &FRAME_D);
+    check_decode(&FRAME_D, &config, &frame);
+  }
+
+  #[test]
+  fn pcf_is_not_byte_aligned() {
+    // PCF right after the address, then the payload shifted by 9 bits
+    let reader = BitReader::new(&FRAME_A);
+    assert_eq!(reader.read_bits(48, 9), Pcf::new(4, 1, false).value());
+    for (index, byte) in PAYLOAD_A.iter().enumerate() {
+      assert_eq!(reader.read_bits(57 + index * 8, 8), u32::from(*byte));
+    }
+    assert_eq!(reader.read_bits(89, 16), crc(&FRAME_A, 8, 89, Crc::TwoBytes));
+  }
+
+  #[test]
+  fn decode_errors() {
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+    let mut payload = [0u8; 32];
+
+    let mut corrupted = FRAME_A;
+    corrupted[8] ^= 0x10;
+    assert_eq!(decode(&corrupted, &config, &mut payload), Err(Error::CrcMismatch));
+
+    let mut preamble = FRAME_A;
+    preamble[0] = 0x55;
+    assert_eq!(decode(&preamble, &config, &mut payload), Err(Error::InvalidPreamble));
+
+    assert_eq!(decode(&FRAME_A[..12], &config, &mut payload), Err(Error::Truncated));
+
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(3), Crc::TwoBytes);
+    assert_eq!(decode(&FRAME_A, &config, &mut payload), Err(Error::InvalidPayloadLength));
+  }
+
+  #[test]
+  fn encode_errors() {
+    let config = FrameConfig::new(5, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+    let mut output = [0u8; 64];
+
+    let frame = Frame::new(&ADDRESS_A, Pcf::new(3, 1, false), &PAYLOAD_A);
+    assert_eq!(encode(&frame, &config, &mut output), Err(Error::InvalidPayloadLength));
+
+    let frame = Frame::new(&ADDRESS_B, Pcf::new(4, 1, false), &PAYLOAD_A);
+    assert_eq!(encode(&frame, &config, &mut output), Err(Error::InvalidAddressLength));
+
+    let frame = Frame::new(&ADDRESS_A, Pcf::new(4, 1, false), &PAYLOAD_A);
+    assert_eq!(encode(&frame, &config, &mut output[..13]), Err(Error::BufferTooSmall));
+  }
+}
@@ -0,0 +1,195 @@
+/*!
+
+Channel hopping
+
+Both sides walk the same list of channels in the same order. The PTX hops to the next channel
+after a number of consecutive transmissions without acknowledgement, while the PRX scans the list
+moving to the next channel every few receive windows without packets. As long as the PRX dwells on
+each channel longer than it takes the PTX to go through the whole list, they will meet again.
+
+A transaction fails when its window ends without a packet (see `Esb::set_timeouts`), or when it is aborted.
+For the PTX, an acknowledgement received with a CRC error is a failure too. When a failure makes the PTX hop,
+the packet is sent again right away on the next channel, as long as there are retries left.
+
+*/
+
+use nrf52_radio::frequency::Frequency;
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The list of channels is empty
+  NoChannels,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HoppingPolicy {
+  /// For the PTX: hop after a number of consecutive failed transmissions
+  HopOnFailure(u8),
+
+  /// For the PRX: hop after a number of consecutive receive windows without packets
+  Scan(u8),
+}
+
+pub struct ChannelHopping<'a> {
+  channels: &'a [Frequency],
+  policy: HoppingPolicy,
+  index: usize,
+  failures: u8,
+}
+
+impl<'a> ChannelHopping<'a> {
+  pub fn new(channels: &'a [Frequency], policy: HoppingPolicy) -> Result<Self> {
+    if channels.is_empty() {
+      return Err(Error::NoChannels);
+    }
+    Ok(ChannelHopping {
+      channels,
+      policy,
+      index: 0,
+      failures: 0,
+    })
+  }
+
+  /// Channel currently in use
+  pub fn channel(&self) -> Frequency {
+    self.channels[self.index]
+  }
+
+  /// Position of the current channel in the list
+  pub fn index(&self) -> usize {
+    self.index
+  }
+
+  pub fn policy(&self) -> HoppingPolicy {
+    self.policy
+  }
+
+  /// Go back to the first channel of the list
+  pub fn restart(&mut self) {
+    self.index = 0;
+    self.failures = 0;
+  }
+
+  /// A transmission (`tx == true`) or a reception finished successfully
+  pub(crate) fn success(&mut self, tx: bool) {
+    if self.applies_to(tx) {
+      self.failures = 0;
+    }
+  }
+
+  /// A transmission (`tx == true`) or a reception failed.
+  /// Returns whether the channel changed.
+  pub(crate) fn failure(&mut self, tx: bool) -> bool {
+    if !self.applies_to(tx) {
+      return false;
+    }
+    let max_failures = match self.policy {
+      HoppingPolicy::HopOnFailure(failures) | HoppingPolicy::Scan(failures) => failures.max(1),
+    };
+    self.failures += 1;
+    if self.failures >= max_failures {
+      self.failures = 0;
+      self.index = (self.index + 1) % self.channels.len();
+      true
+    }
+    else {
+      false
+    }
+  }
+
+  fn applies_to(&self, tx: bool) -> bool {
+    match self.policy {
+      HoppingPolicy::HopOnFailure(_) => tx,
+      HoppingPolicy::Scan(_) => !tx,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn channels() -> [Frequency; 3] {
+    [
+      Frequency::from_2400mhz_channel(78).unwrap(),
+      Frequency::from_2400mhz_channel(2).unwrap(),
+      Frequency::from_2400mhz_channel(40).unwrap(),
+    ]
+  }
+
+  #[test]
+  fn no_channels() {
+    assert_eq!(ChannelHopping::new(&[], HoppingPolicy::Scan(1)).err(), Some(Error::NoChannels));
+  }
+
+  #[test]
+  fn hop_on_failure() {
+    let channels = channels();
+    let mut hopping = ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(2)).unwrap();
+    assert_eq!(hopping.channel(), channels[0]);
+    assert!(!hopping.failure(true));
+    assert!(hopping.failure(true));
+    assert_eq!(hopping.index(), 1);
+    assert_eq!(hopping.channel(), channels[1]);
+  }
+
+  #[test]
+  fn success_resets_failures() {
+    let channels = channels();
+    let mut hopping = ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(2)).unwrap();
+    assert!(!hopping.failure(true));
+    hopping.success(true);
+    assert!(!hopping.failure(true));
+    assert_eq!(hopping.index(), 0);
+    assert!(hopping.failure(true));
+    assert_eq!(hopping.index(), 1);
+  }
+
+  #[test]
+  fn wraps_around() {
+    let channels = channels();
+    let mut hopping = ChannelHopping::new(&channels, HoppingPolicy::Scan(1)).unwrap();
+    assert!(hopping.failure(false));
+    assert!(hopping.failure(false));
+    assert_eq!(hopping.channel(), channels[2]);
+    assert!(hopping.failure(false));
+    assert_eq!(hopping.index(), 0);
+  }
+
+  #[test]
+  fn zero_failures_hops_every_time() {
+    let channels = channels();
+    let mut hopping = ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(0)).unwrap();
+    assert!(hopping.failure(true));
+    assert!(hopping.failure(true));
+    assert_eq!(hopping.index(), 2);
+  }
+
+  #[test]
+  fn policy_applies_to_one_side() {
+    let channels = channels();
+    let mut ptx = ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(1)).unwrap();
+    assert!(!ptx.failure(false));
+    assert_eq!(ptx.index(), 0);
+
+    let mut prx = ChannelHopping::new(&channels, HoppingPolicy::Scan(2)).unwrap();
+    assert!(!prx.failure(false));
+    // Transmissions, i.e. the acknowledgements, neither count nor reset the receive failures
+    assert!(!prx.failure(true));
+    prx.success(true);
+    assert!(prx.failure(false));
+    assert_eq!(prx.index(), 1);
+  }
+
+  #[test]
+  fn restart_at_first_channel() {
+    let channels = channels();
+    let mut hopping = ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(1)).unwrap();
+    hopping.failure(true);
+    hopping.restart();
+    assert_eq!(hopping.index(), 0);
+    assert_eq!(hopping.channel(), channels[0]);
+  }
+}
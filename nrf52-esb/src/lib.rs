@@ -1,19 +1,35 @@
 #![no_std]
 
 pub mod protocol;
+pub mod frame;
+pub mod hopping;
+pub mod power;
+pub mod promiscuous;
+pub mod timeout;
+pub mod traits;
+
+use core::ops::Deref;
 
 use cortex_m_semihosting::hprintln;
 
+use nrf52_radio::hal::target::{RADIO, radio::RegisterBlock};
+
 use nrf52_radio::Radio;
 use nrf52_radio::{Result as RadioResult, AsyncResult as RadioAsyncResult};
 use nrf52_radio::Error as RadioError;
 use nrf52_radio::logical_address::LogicalAddress;
-use nrf52_radio::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+use nrf52_radio::rx_addresses::RxAddresses;
 use nrf52_radio::states::State as RadioState;
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::timestamps::{Timestamps, Timestamp};
+use nrf52_radio::dma_buffer::DmaBuffer;
 
 use nb;
 
-use crate::protocol::Protocol;
+use crate::protocol::{Protocol, HEADER_LENGTH};
+use crate::hopping::ChannelHopping;
+use crate::power::PowerControl;
+use crate::timeout::Timeouts;
 
 pub type Result<A> = core::result::Result<A, Error>;
 pub type AsyncResult<A> = nb::Result<A, Error>;
@@ -32,6 +48,30 @@ pub enum Error {
   /// wait_rx called without a successful start_rx was called before
   ReceiveNotStarted,
 
+  /// A previous error left the radio in an unknown state, reset required
+  ResetRequired,
+
+  /// Buffer smaller than what the protocol requires for a packet
+  BufferTooSmall,
+
+  /// Payload longer than what the protocol allows
+  PayloadTooLong,
+
+  /// No packet was received since the last reception started
+  NoPacket,
+
+  /// Protocol or CRC that the promiscuous mode can not handle
+  UnsupportedProtocol,
+
+  /// The channel hopping chooses the channel
+  HoppingEnabled,
+
+  /// The power control chooses the output power
+  PowerControlEnabled,
+
+  /// No packet or acknowledgement within the windows of all the retries
+  Timeout,
+
   /// Unexpected error from the radio
   RadioError(RadioError),
 }
@@ -40,6 +80,7 @@ pub enum Error {
 pub struct RxConfig {
   skip_ack: bool,
   retries: usize,
+  crc_errors: bool,
 }
 
 impl Default for RxConfig {
@@ -47,6 +88,7 @@ impl Default for RxConfig {
     RxConfig {
       skip_ack: false,
       retries: 1,
+      crc_errors: false,
     }
   }
 }
@@ -56,9 +98,16 @@ impl RxConfig {
     RxConfig { skip_ack, .. self }
   }
 
+  /// Receive windows after the first one before giving up, see `Esb::set_timeouts`
   pub fn with_retries(self, retries: usize) -> Self {
     RxConfig { retries, .. self }
   }
+
+  /// Also receive the packets with a wrong CRC, as sniffers do. They are flagged
+  /// with `RxPacket::crc_ok` and never acknowledged.
+  pub fn with_crc_errors(self, crc_errors: bool) -> Self {
+    RxConfig { crc_errors, .. self }
+  }
 }
 
 
@@ -88,6 +137,7 @@ impl TxConfig {
     TxConfig { skip_ack, .. self }
   }
 
+  /// Transmissions after the first one without acknowledgement before giving up, see `Esb::set_timeouts`
   pub fn with_retries(self, retries: usize) -> Self {
     TxConfig { retries, .. self }
   }
@@ -100,6 +150,9 @@ pub struct RxPacket {
   pub no_ack: bool,
   pub address: LogicalAddress,
   pub crc: u32,
+  pub crc_ok: bool,
+  /// Captured by hardware when timestamps are enabled with `Esb::set_timestamps`
+  pub timestamp: Option<Timestamp>,
 }
 
 pub struct TxPacket {
@@ -131,31 +184,48 @@ enum State {
   RxAck(TxConfig, Step),
   /// Disable radio
   Disable,
+  /// Disable radio after the last window ran out
+  Timeout,
   /// Unexpected error
   Error,
 }
 
-// TODO save the start timestamp for waiting states, and consider timeouts
-pub struct Esb<'a, LFOSC, LFSTAT> {
+pub struct Esb<'a, LFOSC, LFSTAT, R = RADIO> {
   protocol: Protocol,
-  pub radio: Radio<'a, LFOSC, LFSTAT>,
+  pub radio: Radio<'a, LFOSC, LFSTAT, R>,
   state: State,
-  rx_buffer: Option<&'a mut [u8]>,
-  tx_buffer: Option<&'a mut [u8]>,
+  rx_buffer: Option<DmaBuffer<'a>>,
+  tx_buffer: Option<DmaBuffer<'a>>,
   rx_packet: Option<RxPacket>,
   tx_packet: Option<TxPacket>,
+  ack_packet: Option<RxPacket>,
+  ack_payload_length: u8,
+  hopping: Option<ChannelHopping<'a>>,
+  power_control: Option<PowerControl>,
+  timestamps: Option<Timestamps<'a>>,
+  timeouts: Option<Timeouts<'a>>,
+  window_start: u32,
+  retries: usize,
+  tx_config: TxConfig,
+  rx_config: RxConfig,
 }
 
-impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
-  pub fn new(mut radio: Radio<'a, LFOSC, LFSTAT>,
+impl<'a, LFOSC, LFSTAT, R> Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  pub fn new(mut radio: Radio<'a, LFOSC, LFSTAT, R>,
              protocol: Protocol,
-             read_buffer: &'a mut [u8],
-             write_buffer: &'a mut [u8]) -> Esb<'a, LFOSC, LFSTAT> {
+             read_buffer: DmaBuffer<'a>,
+             write_buffer: DmaBuffer<'a>) -> Result<Esb<'a, LFOSC, LFSTAT, R>> {
+
+    // The radio writes up to `max_bytes` of payload after the header through EasyDMA
+    let buffer_length = protocol.buffer_length();
+    if read_buffer.len() < buffer_length || write_buffer.len() < buffer_length {
+      return Err(Error::BufferTooSmall);
+    }
 
     // TODO check Radio state, stop, disable
     Self::setup_protocol(&radio, &protocol);
-    drop(radio.swap_buffer(None));
-    Esb {
+    radio.swap_buffer(&mut None).map_err(Error::RadioError)?;
+    Ok(Esb {
       protocol,
       radio,
       state: State::Standby,
@@ -163,40 +233,42 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
       tx_buffer: Some(write_buffer),
       rx_packet: None,
       tx_packet: None,
+      ack_packet: None,
+      ack_payload_length: 0,
+      hopping: None,
+      power_control: None,
+      timestamps: None,
+      timeouts: None,
+      window_start: 0,
+      retries: 0,
+      tx_config: TxConfig::default(),
+      rx_config: RxConfig::default(),
+    })
+  }
+
+  fn setup_protocol(radio: &Radio<'a, LFOSC, LFSTAT, R>, protocol: &Protocol) {
+    radio.set_packet_config(protocol.packet_config());
+  }
+
+  /// Change the protocol, the buffers need to be large enough for it. Standby required.
+  pub fn set_protocol(&mut self, protocol: Protocol) -> Result<()> {
+    match self.state {
+      State::Standby => {
+        let buffer_length = protocol.buffer_length();
+        let fits = |buffer: &Option<DmaBuffer<'a>>| buffer.as_ref().map_or(false, |buffer| buffer.len() >= buffer_length);
+        if !fits(&self.rx_buffer) || !fits(&self.tx_buffer) {
+          return Err(Error::BufferTooSmall);
+        }
+        Self::setup_protocol(&self.radio, &protocol);
+        self.protocol = protocol;
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
     }
   }
 
-  fn setup_protocol(radio: &Radio<'a, LFOSC, LFSTAT>, protocol: &Protocol) {
-    let pcfn = match protocol {
-      Protocol::FixedPayloadLength(length) =>
-        PacketConfig::default()
-//            .with_length_bits(0)
-//            .with_s0_byte_included(true)
-            .with_length_bits(6)
-            .with_s0_byte_included(false)
-//            .with_s1_len(S1Length::Of1Bits)
-            .with_s1_len(S1Length::Of3Bits)
-            .with_s1_include_in_ram(S1IncludeInRam::Automatic)
-            .with_preamble_len(PreambleLength::Of8Bits)
-            .with_max_bytes(*length)
-            .with_static_bytes(*length)
-            .with_endianess(Endianess::BigEndian)
-            .with_whitening_enabled(false),
-      Protocol::DynamicPayloadLength(max_length) => {
-        let length_bits = if *max_length <= 32 { 6 } else { 8 };
-        PacketConfig::default()
-            .with_length_bits(length_bits)
-            .with_s0_byte_included(false)
-            .with_s1_len(S1Length::Of3Bits)
-            .with_s1_include_in_ram(S1IncludeInRam::Automatic)
-            .with_preamble_len(PreambleLength::Of8Bits)
-            .with_max_bytes(*max_length)
-            .with_static_bytes(0)
-            .with_endianess(Endianess::BigEndian)
-            .with_whitening_enabled(false)
-      }
-    };
-    radio.set_packet_config(pcfn);
+  pub fn get_protocol(&self) -> Protocol {
+    self.protocol
   }
 
   pub fn set_crc_disabled(&self) -> &Self {
@@ -214,18 +286,28 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
     self
   }
 
+  /// Logical addresses (pipes) to listen to
+  pub fn set_rx_addresses(&self, addresses: RxAddresses) -> &Self {
+    self.radio.set_rx_addresses(addresses);
+    self
+  }
+
+  pub fn get_rx_addresses(&self) -> RxAddresses {
+    self.radio.get_rx_addresses()
+  }
+
   // TODO expose the packet rather than the raw buffer ?
 
   pub fn get_rx_buffer(&self) -> &[u8] {
     match self.rx_buffer.as_ref() {
-      Some(buffer) => *buffer,
+      Some(buffer) => buffer,
       None => &[],
     }
   }
 
   pub fn get_tx_buffer(&mut self) -> &mut [u8] {
     match self.tx_buffer.as_mut() {
-      Some(buffer) => *buffer,
+      Some(buffer) => buffer,
       None => &mut [],
     }
   }
@@ -234,13 +316,125 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
     self.rx_packet
   }
 
+  /// Acknowledgement received for the last transmitted packet.
+  /// Its payload (if any) is available from the rx buffer.
+  pub fn get_last_ack_packet(&self) -> Option<RxPacket> {
+    self.ack_packet
+  }
+
+  /// Copy the payload into the tx buffer and encode its length in the header.
+  /// The PID and NO_ACK bits of the header are left untouched.
+  pub fn set_tx_payload(&mut self, payload: &[u8]) -> Result<()> {
+    self.check_payload_length(payload)?;
+    match self.tx_buffer.as_mut() {
+      Some(buffer) => {
+        protocol::write_payload(&self.protocol, buffer, payload);
+        Ok(())
+      },
+      None => Err(Error::TxBufferBusy),
+    }
+  }
+
+  /// Payload to be sent with the next acknowledgement.
+  /// It is sent only once, following acknowledgements will be empty,
+  /// and it stays queued while packets are received without acknowledgement.
+  pub fn set_ack_payload(&mut self, payload: &[u8]) -> Result<()> {
+    let length = self.check_payload_length(payload)?;
+    match self.tx_buffer.as_mut() {
+      Some(buffer) => {
+        protocol::write_payload(&self.protocol, buffer, payload);
+        self.ack_payload_length = length;
+        Ok(())
+      },
+      None => Err(Error::TxBufferBusy),
+    }
+  }
+
+  /// Hop between a list of channels rather than using the radio frequency.
+  /// Standby required.
+  pub fn set_channel_hopping(&mut self, hopping: Option<ChannelHopping<'a>>) -> Result<()> {
+    match self.state {
+      State::Standby => {
+        self.hopping = hopping;
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn get_channel_hopping(&self) -> Option<&ChannelHopping<'a>> {
+    self.hopping.as_ref()
+  }
+
+  /// Channel that will be used for the next transaction when hopping
+  pub fn get_channel(&self) -> Option<Frequency> {
+    self.hopping.as_ref().map(|hopping| hopping.channel())
+  }
+
+  /// Adapt the output power to the acknowledgements rather than using the radio one.
+  /// Standby required.
+  pub fn set_power_control(&mut self, power_control: Option<PowerControl>) -> Result<()> {
+    match self.state {
+      State::Standby => {
+        self.power_control = power_control;
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn get_power_control(&self) -> Option<&PowerControl> {
+    self.power_control.as_ref()
+  }
+
   // TODO ack option as a parameter or as a different method ?
 
+  /// Attach hardware timestamps to the received packets, it gives back the previous ones
+  /// so they can be stopped.
+  pub fn set_timestamps(&mut self, timestamps: Option<Timestamps<'a>>) -> Option<Timestamps<'a>> {
+    core::mem::replace(&mut self.timestamps, timestamps)
+  }
+
+  pub fn get_timestamps(&self) -> Option<&Timestamps<'a>> {
+    self.timestamps.as_ref()
+  }
+
+  /// Windows for the acknowledgements and the receptions, without them it waits forever.
+  /// Standby required.
+  pub fn set_timeouts(&mut self, timeouts: Option<Timeouts<'a>>) -> Result<()> {
+    match self.state {
+      State::Standby => {
+        self.timeouts = timeouts;
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+
+  pub fn get_timeouts(&self) -> Option<&Timeouts<'a>> {
+    self.timeouts.as_ref()
+  }
+
+  /// Configuration for the transmissions started through the `Transmit` trait
+  pub fn set_tx_config(&mut self, tx_config: TxConfig) -> &Self {
+    self.tx_config = tx_config;
+    self
+  }
+
+  /// Configuration for the receptions started through the `Receive` trait
+  pub fn set_rx_config(&mut self, rx_config: RxConfig) -> &Self {
+    self.rx_config = rx_config;
+    self
+  }
+
   pub fn start_rx(&mut self, rx_config: RxConfig) -> Result<()> {
     match self.state {
       State::Standby => {
         if self.rx_buffer.is_some() {
+          self.apply_channel();
           self.rx_packet = None;
+          self.retries = 0;
+          self.start_window();
           self.state = State::Rx(rx_config, self.rx_step_from_radio_state());
           Ok(())
         }
@@ -255,8 +449,6 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
   pub fn wait_rx(&mut self) -> AsyncResult<()> {
     match self.state {
       State::Rx(config, ref step) => {
-        // TODO check timeout
-
         let (next_state, result) = match step {
           Step::Disable => {
             self.radio.disable();
@@ -267,55 +459,49 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
             Err(error) => self.handle_async_radio_error(error),
           },
           Step::Enable => {
-            self.ensure_rx_buffer();
-            match self.radio.enable_rx() {
+            match self.ensure_rx_buffer().and_then(|()| self.radio.enable_rx()) {
               Ok(()) => self.next_state(State::Rx(config, Step::WaitingIdle)),
               Err(error) => self.handle_radio_error(error),
             }
           },
-          Step::WaitingIdle => {
-            self.ensure_rx_buffer();
-            match self.radio.wait_idle() {
+          Step::WaitingIdle => match self.ensure_rx_buffer() {
+            Ok(()) => match self.radio.wait_idle() {
               Ok(()) => self.next_state(State::Rx(config, Step::Start)),
               Err(error) => self.handle_async_radio_error(error),
-            }
+            },
+            Err(error) => self.handle_radio_error(error),
           },
           Step::Start => {
-            self.ensure_rx_buffer();
-            match self.radio.start() {
+            match self.ensure_rx_buffer().and_then(|()| self.radio.start()) {
               Ok(()) => self.next_state(State::Rx(config, Step::WaitingEnd)),
               Err(error) => self.handle_radio_error(error),
             }
           },
           Step::WaitingEnd => match self.radio.wait_end_or_disable() {
             Ok(()) => {
-              if self.radio.is_crc_ok() {
-                self.rx_buffer = self.radio.swap_buffer(self.tx_buffer.take());
-                let mut rx_buffer = self.get_rx_buffer().iter();
-                let length = *rx_buffer.next().unwrap();
-                let pid_noack = *rx_buffer.next().unwrap();
-                let pid = (pid_noack >> 1) & 0x03;
-                // TODO check PID and skip repeated packet
-                let packet = RxPacket {
-                  length,
-                  pid,
-                  no_ack: (pid_noack & 0x01) == 0x01,
-                  address: self.radio.get_received_address(),
-                  crc: self.radio.get_received_crc(),
-                };
-                if config.skip_ack || packet.no_ack {
-                  self.rx_packet = Some(packet);
-                  self.tx_buffer = self.radio.swap_buffer(None);
-                  self.disable()
-                }
-                else {
-                  self.next_state(State::TxAck(packet, self.tx_step_from_radio_state()))
-                }
-              }
-              else {
-                self.next_state(State::Rx(config, self.rx_step_from_radio_state()))
+              match self.received_packet(config.crc_errors) {
+                Some(packet) => match Self::swap_buffer(&mut self.radio, &mut self.tx_buffer, &mut self.rx_buffer) {
+                  // TODO check PID and skip repeated packet
+                  Ok(()) => if config.skip_ack || packet.no_ack || !packet.crc_ok {
+                    if packet.crc_ok {
+                      self.hopping_success(false);
+                    }
+                    self.rx_packet = Some(packet);
+                    match Self::swap_buffer(&mut self.radio, &mut None, &mut self.tx_buffer) {
+                      Ok(()) => self.disable(),
+                      Err(error) => self.handle_radio_error(error),
+                    }
+                  }
+                  else {
+                    self.next_state(State::TxAck(packet, self.tx_step_from_radio_state()))
+                  },
+                  Err(error) => self.handle_radio_error(error),
+                },
+                None => self.next_state(State::Rx(config, self.rx_step_from_radio_state())),
               }
             },
+            Err(nb::Error::WouldBlock) if self.window_ended(self.timeouts.and_then(|t| t.rx_window())) =>
+              self.rx_window_ended(config),
             Err(error) => self.handle_async_radio_error(error),
           }
         };
@@ -349,9 +535,13 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
           },
           Step::WaitingEnd => match self.radio.wait_end_or_disable() {
             Ok(()) => {
+              self.hopping_success(false);
               self.rx_packet = Some(packet);
-              self.tx_buffer = self.radio.swap_buffer(None);
-              self.disable()
+              self.ack_payload_length = 0;
+              match Self::swap_buffer(&mut self.radio, &mut None, &mut self.tx_buffer) {
+                Ok(()) => self.disable(),
+                Err(error) => self.handle_radio_error(error),
+              }
             },
             Err(error) => self.handle_async_radio_error(error),
           }
@@ -367,6 +557,11 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
         self.state = next_state;
         result
       },
+      State::Timeout => {
+        let (next_state, result) = self.wait_timeout();
+        self.state = next_state;
+        result
+      },
       _ => Err(nb::Error::Other(Error::ReceiveNotStarted)),
     }
   }
@@ -374,9 +569,18 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
   pub fn start_tx(&mut self, tx_config: TxConfig) -> Result<()> {
     match self.state {
       State::Standby => {
-        if self.tx_buffer.is_some() {
+        if let Some(buffer) = self.tx_buffer.as_ref() {
+          if let Protocol::DynamicPayloadLength(max_length) = self.protocol {
+            if buffer[0] > max_length {
+              return Err(Error::PayloadTooLong);
+            }
+          }
+          self.apply_channel();
+          self.apply_tx_power();
+          self.ack_packet = None;
+          self.retries = 0;
           self.radio.set_tx_address(tx_config.address);
-          drop(self.radio.swap_buffer(self.tx_buffer.take()));
+          Self::swap_buffer(&mut self.radio, &mut self.tx_buffer, &mut None).map_err(Error::RadioError)?;
           self.state = State::Tx(tx_config, self.tx_step_from_radio_state());
           Ok(())
         }
@@ -400,7 +604,7 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
             Ok(()) => self.next_state(State::Tx(config, Step::Enable)),
             Err(error) => self.handle_async_radio_error(error),
           },
-          Step::Enable => match self.radio.enable_tx() {
+          Step::Enable => match self.retransmit_buffer().and_then(|()| self.radio.enable_tx()) {
             Ok(()) => self.next_state(State::Tx(config, Step::WaitingIdle)),
             Err(error) => self.handle_radio_error(error),
           },
@@ -415,12 +619,20 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
           Step::WaitingEnd => match self.radio.wait_end_or_disable() {
             Ok(()) => {
               if config.skip_ack {
-                self.tx_buffer = self.radio.swap_buffer(None);
-                self.disable()
+                self.hopping_success(true);
+                match Self::swap_buffer(&mut self.radio, &mut None, &mut self.tx_buffer) {
+                  Ok(()) => self.disable(),
+                  Err(error) => self.handle_radio_error(error),
+                }
               }
               else {
-                self.tx_buffer = self.radio.swap_buffer(self.rx_buffer.take());
-                self.next_state(State::RxAck(config, self.rx_step_from_radio_state()))
+                match Self::swap_buffer(&mut self.radio, &mut self.rx_buffer, &mut self.tx_buffer) {
+                  Ok(()) => {
+                    self.start_window();
+                    self.next_state(State::RxAck(config, self.rx_step_from_radio_state()))
+                  },
+                  Err(error) => self.handle_radio_error(error),
+                }
               }
             },
             Err(error) => self.handle_async_radio_error(error),
@@ -430,7 +642,6 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
         result
       },
       State::RxAck(config, ref step) => {
-        // TODO check timeout
         let (next_state, result) = match step {
           Step::Disable => {
             self.radio.disable();
@@ -453,15 +664,25 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
             Err(error) => self.handle_radio_error(error),
           },
           Step::WaitingEnd => match self.radio.wait_end_or_disable() {
-            Ok(()) => {
-              if self.radio.is_crc_ok() {
+            Ok(()) => match self.received_packet(false) {
+              Some(packet) => {
                 // TODO check PID
-                self.rx_buffer = self.radio.swap_buffer(None);
-                self.disable()
-              }
-              else {
-                self.next_state(State::RxAck(config, self.rx_step_from_radio_state()))
-              }
+                self.hopping_success(true);
+                self.power_success();
+                self.ack_packet = Some(packet);
+                match Self::swap_buffer(&mut self.radio, &mut None, &mut self.rx_buffer) {
+                  Ok(()) => self.disable(),
+                  Err(error) => self.handle_radio_error(error),
+                }
+              },
+              // A corrupted acknowledgement, send the packet again on the next channel when hopping
+              None if self.hopping_failure(true) => self.retransmit(config),
+              None => self.next_state(State::RxAck(config, self.rx_step_from_radio_state())),
+            },
+            Err(nb::Error::WouldBlock) if self.window_ended(self.timeouts.and_then(|t| t.ack())) => {
+              self.hopping_failure(true);
+              self.power_failure();
+              self.retransmit(config)
             },
             Err(error) => self.handle_async_radio_error(error),
           },
@@ -477,10 +698,74 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
         self.state = next_state;
         result
       },
+      State::Timeout => {
+        let (next_state, result) = self.wait_timeout();
+        self.state = next_state;
+        result
+      },
       _ => Err(nb::Error::Other(Error::ReceiveNotStarted)),
     }
   }
 
+  /// Abort the transaction in progress, if any, and go back to standby.
+  /// It needs to be polled until the radio is disabled.
+  /// When hopping, an aborted transaction counts as a failure.
+  /// After an error it gives `Error::ResetRequired` and nothing changes, use `reset` instead.
+  pub fn abort(&mut self) -> AsyncResult<()> {
+    match self.state {
+      State::Error => Err(nb::Error::Other(Error::ResetRequired)),
+      State::Tx(config, _) | State::RxAck(config, _) => {
+        self.hopping_failure(true);
+        if !config.skip_ack {
+          self.power_failure();
+        }
+        self.reset()
+      },
+      State::Rx(_, _) => {
+        self.hopping_failure(false);
+        self.reset()
+      },
+      _ => self.reset(),
+    }
+  }
+
+  /// Recover from any state, including errors, by disabling the radio and reclaiming the buffers.
+  /// It needs to be polled until the radio is disabled.
+  pub fn reset(&mut self) -> AsyncResult<()> {
+    let (next_state, result) = match self.state {
+      State::Standby => (State::Standby, Ok(())),
+      State::Disable => match self.radio.wait_disabled() {
+        Ok(()) => match self.reclaim_buffers() {
+          Ok(()) => (State::Standby, Ok(())),
+          Err(error) => self.handle_radio_error(error),
+        },
+        Err(error) => self.handle_async_radio_error(error),
+      },
+      _ => match self.radio.get_state() {
+        RadioState::Disabled => match self.reclaim_buffers() {
+          Ok(()) => (State::Standby, Ok(())),
+          Err(error) => self.handle_radio_error(error),
+        },
+        _ => {
+          self.radio.disable();
+          self.next_state(State::Disable)
+        }
+      },
+    };
+    self.state = next_state;
+    result
+  }
+
+  /// Disable the radio and give it back with the buffers
+  pub fn free(mut self) -> (Radio<'a, LFOSC, LFSTAT, R>, Option<DmaBuffer<'a>>, Option<DmaBuffer<'a>>) {
+    if !matches!(self.radio.get_state(), RadioState::Disabled) {
+      self.radio.disable();
+      while self.radio.wait_disabled().is_err() {}
+    }
+    drop(self.reclaim_buffers());
+    (self.radio, self.rx_buffer, self.tx_buffer)
+  }
+
   fn next_state<T>(&self, state: State) -> (State, AsyncResult<T>) {
     (state, Err(nb::Error::WouldBlock))
   }
@@ -506,16 +791,150 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
     }
   }
 
-  fn ensure_rx_buffer(&mut self) {
+  /// Send the packet again while there are retries left, otherwise time out
+  fn retransmit(&mut self, config: TxConfig) -> (State, AsyncResult<()>) {
+    if self.retries < config.retries {
+      self.retries += 1;
+      self.apply_channel();
+      self.apply_tx_power();
+      self.next_state(State::Tx(config, Step::Disable))
+    }
+    else {
+      self.radio.disable();
+      self.next_state(State::Timeout)
+    }
+  }
+
+  /// Listen for another window while there are retries left, otherwise time out.
+  /// The radio is restarted when the window ends on a different channel.
+  fn rx_window_ended(&mut self, config: RxConfig) -> (State, AsyncResult<()>) {
+    let hopped = self.hopping_failure(false);
+    if self.retries < config.retries {
+      self.retries += 1;
+      self.start_window();
+      if hopped {
+        self.apply_channel();
+        self.next_state(State::Rx(config, Step::Disable))
+      }
+      else {
+        self.next_state(State::Rx(config, Step::WaitingEnd))
+      }
+    }
+    else {
+      self.radio.disable();
+      self.next_state(State::Timeout)
+    }
+  }
+
+  fn wait_timeout(&mut self) -> (State, AsyncResult<()>) {
+    match self.radio.wait_disabled() {
+      Ok(()) => match self.reclaim_buffers() {
+        Ok(()) => (State::Standby, Err(nb::Error::Other(Error::Timeout))),
+        Err(error) => self.handle_radio_error(error),
+      },
+      Err(error) => self.handle_async_radio_error(error),
+    }
+  }
+
+  fn start_window(&mut self) {
+    if let Some(timeouts) = self.timeouts.as_ref() {
+      self.window_start = timeouts.now();
+    }
+  }
+
+  /// Whether the current window is over, never without timeouts
+  fn window_ended(&self, window: Option<u32>) -> bool {
+    match (self.timeouts.as_ref(), window) {
+      (Some(timeouts), Some(window)) => timeouts.now().wrapping_sub(self.window_start) >= window,
+      _ => false,
+    }
+  }
+
+  fn apply_channel(&self) {
+    if let Some(hopping) = self.hopping.as_ref() {
+      self.radio.set_frequency(hopping.channel());
+    }
+  }
+
+  fn apply_tx_power(&self) {
+    if let Some(power_control) = self.power_control.as_ref() {
+      // The controller only steps through supported levels
+      drop(self.radio.set_tx_power(power_control.tx_power()));
+    }
+  }
+
+  fn power_success(&mut self) {
+    if let Some(power_control) = self.power_control.as_mut() {
+      power_control.success();
+    }
+  }
+
+  fn power_failure(&mut self) {
+    if let Some(power_control) = self.power_control.as_mut() {
+      power_control.failure();
+    }
+  }
+
+  fn hopping_success(&mut self, tx: bool) {
+    if let Some(hopping) = self.hopping.as_mut() {
+      hopping.success(tx);
+    }
+  }
+
+  /// Whether the channel changed
+  fn hopping_failure(&mut self, tx: bool) -> bool {
+    self.hopping.as_mut().map_or(false, |hopping| hopping.failure(tx))
+  }
+
+  /// Take back the buffer owned by the radio, if any, into the slot that is missing it
+  fn reclaim_buffers(&mut self) -> core::result::Result<(), RadioError> {
+    let mut buffer = None;
+    Self::swap_buffer(&mut self.radio, &mut None, &mut buffer)?;
+    if let Some(buffer) = buffer {
+      if self.rx_buffer.is_none() {
+        self.rx_buffer = Some(buffer);
+      }
+      else {
+        self.tx_buffer = Some(buffer);
+      }
+    }
+    Ok(())
+  }
+
+  /// Give the buffer in `from` to the radio, and put the one the radio had into `to`.
+  /// The radio refuses the buffers while a transfer may be running,
+  /// and then nothing moves.
+  fn swap_buffer(
+    radio: &mut Radio<'a, LFOSC, LFSTAT, R>,
+    from: &mut Option<DmaBuffer<'a>>,
+    to: &mut Option<DmaBuffer<'a>>,
+  ) -> core::result::Result<(), RadioError> {
+    let mut buffer = from.take();
+    match radio.swap_buffer(&mut buffer) {
+      Ok(()) => {
+        *to = buffer;
+        Ok(())
+      },
+      Err(error) => {
+        *from = buffer;
+        Err(error)
+      },
+    }
+  }
+
+  fn ensure_rx_buffer(&mut self) -> core::result::Result<(), RadioError> {
     if self.rx_buffer.is_some() {
-      drop(self.radio.swap_buffer(self.rx_buffer.take()));
+      Self::swap_buffer(&mut self.radio, &mut self.rx_buffer, &mut None)?;
     }
+    Ok(())
   }
 
-  fn set_tx_buffer(&mut self) {
+  /// Give the packet back to the radio for a retransmission, taking back the acknowledgement buffer
+  fn retransmit_buffer(&mut self) -> core::result::Result<(), RadioError> {
     if self.tx_buffer.is_some() {
-      drop(self.radio.swap_buffer(self.tx_buffer.take()));
+      Self::swap_buffer(&mut self.radio, &mut self.tx_buffer, &mut self.rx_buffer)?;
     }
+    Ok(())
   }
 
   fn rx_step_from_radio_state(&self) -> Step {
@@ -540,12 +959,48 @@ impl<'a, LFOSC, LFSTAT> Esb<'a, LFOSC, LFSTAT> {
     }
   }
 
+  /// Packet received by the radio if the CRC is valid, unless `crc_errors` is set,
+  /// and its length is within the protocol bounds
+  fn received_packet(&self, crc_errors: bool) -> Option<RxPacket> {
+    let crc_ok = self.radio.is_crc_ok();
+    if !crc_ok && !crc_errors {
+      return None;
+    }
+    let pcf = protocol::read_header(self.radio.get_buffer());
+    match self.protocol {
+      Protocol::DynamicPayloadLength(max_length) if pcf.length > max_length => None,
+      _ => Some(RxPacket {
+        length: pcf.length,
+        pid: pcf.pid,
+        no_ack: pcf.no_ack,
+        address: self.radio.get_received_address(),
+        crc: self.radio.get_received_crc(),
+        crc_ok,
+        timestamp: self.timestamps.as_ref().map(|timestamps| timestamps.get_last()),
+      })
+    }
+  }
+
+  fn check_payload_length(&self, payload: &[u8]) -> Result<u8> {
+    if payload.len() <= usize::from(self.protocol.max_payload_length()) {
+      Ok(payload.len() as u8)
+    }
+    else {
+      Err(Error::PayloadTooLong)
+    }
+  }
+
+  /// Give the acknowledgement the PID of the packet and send it to the address the packet came from.
+  /// Without ACK payload, the LENGTH and the payload are zeroed so the acknowledgement goes out empty.
   fn prepare_tx_ack(&mut self, packet: &RxPacket) {
+    let ack_payload_length = self.ack_payload_length;
     let ack_buffer = self.radio.get_buffer_mut();
-    ack_buffer[0] = 0;
     ack_buffer[1] = packet.pid << 1;
-    for b in ack_buffer[2..].iter_mut() {
-      *b = 0;
+    if ack_payload_length == 0 {
+      ack_buffer[0] = 0;
+      for b in ack_buffer[HEADER_LENGTH..].iter_mut() {
+        *b = 0;
+      }
     }
     self.radio.set_tx_address(packet.address);
   }
@@ -0,0 +1,168 @@
+/*!
+
+Adaptive output power
+
+The PTX starts at the highest level and goes down one level after a number of consecutive
+acknowledged transmissions. As soon as a transmission is not acknowledged, it goes back up one level.
+This way the output power settles around the lowest level that still reaches the PRX, which keeps
+the band clean for the devices nearby.
+
+A transmission fails when the ACK window ends without acknowledgement (see `Esb::set_timeouts`),
+or when it is aborted.
+Transmissions without acknowledgement do not count, as there is no way to know whether they arrived.
+
+*/
+
+use nrf52_radio::tx_power::TxPower;
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The minimum level is above the maximum one, once both are supported levels
+  InvalidRange,
+}
+
+pub struct PowerControl {
+  min: TxPower,
+  max: TxPower,
+  successes_to_lower: u8,
+  tx_power: TxPower,
+  successes: u8,
+}
+
+impl PowerControl {
+  /// Adapt the power between `min` and `max`, lowering it after `successes_to_lower`
+  /// consecutive acknowledged transmissions. Unsupported levels are replaced by the nearest ones.
+  pub fn new(min: TxPower, max: TxPower, successes_to_lower: u8) -> Result<Self> {
+    let min = min.to_supported();
+    let max = max.to_supported();
+    if min.dbm() > max.dbm() {
+      return Err(Error::InvalidRange);
+    }
+    Ok(PowerControl {
+      min,
+      max,
+      successes_to_lower: successes_to_lower.max(1),
+      tx_power: max,
+      successes: 0,
+    })
+  }
+
+  /// Level for the next transmission
+  pub fn tx_power(&self) -> TxPower {
+    self.tx_power
+  }
+
+  pub fn min(&self) -> TxPower {
+    self.min
+  }
+
+  pub fn max(&self) -> TxPower {
+    self.max
+  }
+
+  /// Go back to the highest level
+  pub fn restart(&mut self) {
+    self.tx_power = self.max;
+    self.successes = 0;
+  }
+
+  /// A transmission was acknowledged.
+  /// Returns whether the level changed.
+  pub(crate) fn success(&mut self) -> bool {
+    self.successes += 1;
+    if self.successes < self.successes_to_lower {
+      return false;
+    }
+    self.successes = 0;
+    match self.tx_power.lower() {
+      Some(lower) if lower.dbm() >= self.min.dbm() => {
+        self.tx_power = lower;
+        true
+      },
+      _ => false,
+    }
+  }
+
+  /// A transmission was aborted without acknowledgement.
+  /// Returns whether the level changed.
+  pub(crate) fn failure(&mut self) -> bool {
+    self.successes = 0;
+    match self.tx_power.higher() {
+      Some(higher) if higher.dbm() <= self.max.dbm() => {
+        self.tx_power = higher;
+        true
+      },
+      _ => false,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn invalid_range() {
+    assert_eq!(PowerControl::new(TxPower::ZerodBm, TxPower::Neg4dBm, 1).err(), Some(Error::InvalidRange));
+  }
+
+  #[test]
+  fn unsupported_levels_replaced() {
+    let power_control = PowerControl::new(TxPower::from(0xe4), TxPower::from(0xff), 1).unwrap();
+    assert_eq!(power_control.min(), TxPower::Neg20dBm);
+    assert_eq!(power_control.max(), TxPower::ZerodBm);
+    assert_eq!(power_control.tx_power(), TxPower::ZerodBm);
+  }
+
+  #[test]
+  fn lower_after_successes() {
+    let mut power_control = PowerControl::new(TxPower::Neg8dBm, TxPower::ZerodBm, 3).unwrap();
+    assert!(!power_control.success());
+    assert!(!power_control.success());
+    assert!(power_control.success());
+    assert_eq!(power_control.tx_power(), TxPower::Neg4dBm);
+  }
+
+  #[test]
+  fn failure_resets_successes() {
+    let mut power_control = PowerControl::new(TxPower::Neg8dBm, TxPower::ZerodBm, 2).unwrap();
+    assert!(!power_control.success());
+    assert!(!power_control.failure());
+    assert!(!power_control.success());
+    assert_eq!(power_control.tx_power(), TxPower::ZerodBm);
+  }
+
+  #[test]
+  fn stays_within_range() {
+    let mut power_control = PowerControl::new(TxPower::Neg8dBm, TxPower::ZerodBm, 1).unwrap();
+    assert!(power_control.success());
+    assert!(power_control.success());
+    assert_eq!(power_control.tx_power(), TxPower::Neg8dBm);
+    assert!(!power_control.success());
+    assert_eq!(power_control.tx_power(), TxPower::Neg8dBm);
+
+    assert!(power_control.failure());
+    assert_eq!(power_control.tx_power(), TxPower::Neg4dBm);
+    assert!(power_control.failure());
+    assert!(!power_control.failure());
+    assert_eq!(power_control.tx_power(), TxPower::ZerodBm);
+  }
+
+  #[test]
+  fn zero_successes_lowers_every_time() {
+    let mut power_control = PowerControl::new(TxPower::Neg4dBm, TxPower::ZerodBm, 0).unwrap();
+    assert!(power_control.success());
+    assert_eq!(power_control.tx_power(), TxPower::Neg4dBm);
+  }
+
+  #[test]
+  fn restart_at_max() {
+    let mut power_control = PowerControl::new(TxPower::Neg8dBm, TxPower::ZerodBm, 1).unwrap();
+    power_control.success();
+    power_control.success();
+    power_control.restart();
+    assert_eq!(power_control.tx_power(), TxPower::ZerodBm);
+  }
+}
@@ -0,0 +1,334 @@
+/*!
+
+Promiscuous sniffing of ESB frames with unknown addresses
+
+The radio only receives packets that match one of its addresses, so to hear any address it listens
+for a 2 bytes address made of a zero byte and the preamble pattern. The preamble of the radio, plus the
+zero bits that the noise often produces before a frame, plus the preamble of the real frame, match it.
+What follows is received as a raw payload without CRC: the real address, the PCF, the payload and the CRC.
+
+```text
+on air   | noise 00 | preamble aa | address ... | PCF | payload | CRC |
+radio    | 00       | aa          | raw payload ................................. |
+```
+
+Most of the raw payloads are noise, so the candidate frames are validated with the ESB CRC in software,
+for each address length between 3 and 5 bytes, and for a few bit offsets in case the sync was off.
+Even then, a 16 bits CRC lets through some noise every now and then, so an address is only reliable
+once it has been seen a few times. The CRC can not be disabled, as it is the only way to tell frames from noise.
+
+This is the same trick that is used with the nRF24L01+, see Travis Goodspeed's
+[Promiscuity is the nRF24L01+'s Duty](http://travisgoodspeed.blogspot.com/2011/02/promiscuity-is-nrf24l01s-duty.html)
+
+*/
+
+use core::ops::Deref;
+
+use nrf52_radio::hal::target::{RADIO, radio::RegisterBlock};
+
+use nrf52_radio::Radio;
+use nrf52_radio::base_address::BaseAddresses;
+use nrf52_radio::rx_addresses::RxAddresses;
+use nrf52_radio::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+use nrf52_radio::shortcuts::Shortcuts;
+use nrf52_radio::states::State as RadioState;
+use nrf52_radio::dma_buffer::DmaBuffer;
+
+use crate::{Error, Result, AsyncResult};
+use crate::frame::{self, Crc, FrameConfig, Pcf};
+use crate::protocol::Protocol;
+
+/// Longest address
+pub const MAX_ADDRESS_LENGTH: usize = 5;
+
+/// Largest payload
+pub const MAX_PAYLOAD_LENGTH: usize = 32;
+
+/// Bit offsets tried for each raw payload
+pub const MAX_BIT_OFFSET: usize = 16;
+
+/// Raw payload with room for the largest frame after the largest bit offset:
+/// address, PCF, payload, CRC and offset
+pub const RAW_LENGTH: usize = MAX_ADDRESS_LENGTH + 2 + MAX_PAYLOAD_LENGTH + 2 + MAX_BIT_OFFSET / 8;
+
+/// The address that the radio listens to, as base and prefix for each preamble
+const BASE_ADDRESS: u8 = 0x00;
+const PREFIXES: [u8; 8] = [0xaa, 0x55, 0, 0, 0, 0, 0, 0];
+
+/// A frame with a valid CRC found in a raw payload
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+  address: [u8; MAX_ADDRESS_LENGTH],
+  address_length: usize,
+  pub pcf: Pcf,
+
+  /// Bits skipped from the start of the raw payload
+  pub bit_offset: usize,
+}
+
+impl Candidate {
+  /// Address in on-air order, the base address followed by the prefix
+  pub fn address(&self) -> &[u8] {
+    &self.address[..self.address_length]
+  }
+}
+
+/// Look for a frame with a valid CRC in a raw payload received after the preamble,
+/// and copy its payload into `payload`
+pub fn find_frame(raw: &[u8], protocol: Protocol, crc: Crc, payload: &mut [u8]) -> Option<Candidate> {
+  if crc == Crc::Disabled {
+    return None;
+  }
+  let raw_bits = raw.len() * 8;
+  let mut aligned = [0u8; 1 + RAW_LENGTH];
+  for bit_offset in 0..MAX_BIT_OFFSET.min(raw_bits) {
+    let length = ((raw_bits - bit_offset) / 8).min(RAW_LENGTH);
+    for (index, byte) in aligned[1..=length].iter_mut().enumerate() {
+      *byte = read_byte(raw, bit_offset + index * 8);
+    }
+    for address_length in (3..=MAX_ADDRESS_LENGTH).rev() {
+      // The decoder expects the preamble that the address implies
+      aligned[0] = frame::preamble(&aligned[1..]);
+      let config = FrameConfig::new(address_length, protocol, crc);
+      if let Ok(frame) = frame::decode(&aligned[..=length], &config, payload) {
+        let mut address = [0; MAX_ADDRESS_LENGTH];
+        address[..address_length].copy_from_slice(frame.address);
+        return Some(Candidate { address, address_length, pcf: frame.pcf, bit_offset });
+      }
+    }
+  }
+  None
+}
+
+/// The frames need a CRC to be told from the noise, and their payload has to fit in the raw payload
+pub fn check_protocol(protocol: Protocol, crc: Crc) -> Result<()> {
+  if crc == Crc::Disabled || usize::from(protocol.max_payload_length()) > MAX_PAYLOAD_LENGTH {
+    Err(Error::UnsupportedProtocol)
+  }
+  else {
+    Ok(())
+  }
+}
+
+fn read_byte(buffer: &[u8], position: usize) -> u8 {
+  let (index, shift) = (position / 8, position % 8);
+  match shift {
+    0 => buffer[index],
+    _ => buffer[index] << shift | buffer.get(index + 1).map_or(0, |next| next >> (8 - shift)),
+  }
+}
+
+pub struct Promiscuous<'a, LFOSC, LFSTAT, R = RADIO> {
+  pub radio: Radio<'a, LFOSC, LFSTAT, R>,
+  protocol: Protocol,
+  crc: Crc,
+  payload: [u8; MAX_PAYLOAD_LENGTH],
+  candidate: Option<Candidate>,
+}
+
+impl<'a, LFOSC, LFSTAT, R> Promiscuous<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  /// The radio needs to be disabled, the frequency and the mode are left as they are.
+  /// The frames are expected to follow `protocol`, with a `crc` that can not be disabled.
+  pub fn new(mut radio: Radio<'a, LFOSC, LFSTAT, R>,
+             protocol: Protocol,
+             crc: Crc,
+             buffer: DmaBuffer<'a>) -> Result<Promiscuous<'a, LFOSC, LFSTAT, R>> {
+
+    check_protocol(protocol, crc)?;
+    if buffer.len() < RAW_LENGTH {
+      return Err(Error::BufferTooSmall);
+    }
+
+    let pcfn = PacketConfig::default()
+        .with_length_bits(0)
+        .with_s0_byte_included(false)
+        .with_s1_len(S1Length::Of0Bits)
+        .with_s1_include_in_ram(S1IncludeInRam::Automatic)
+        .with_preamble_len(PreambleLength::Of8Bits)
+        .with_max_bytes(RAW_LENGTH as u8)
+        .with_static_bytes(RAW_LENGTH as u8)
+        .with_endianess(Endianess::BigEndian)
+        .with_whitening_enabled(false);
+    radio
+        .set_packet_config(pcfn)
+        .set_crc_disabled()
+        .set_base_addresses(BaseAddresses::OneByte(BASE_ADDRESS, BASE_ADDRESS))
+        .set_prefixes(PREFIXES)
+        .set_rx_addresses(RxAddresses::ADDR0 | RxAddresses::ADDR1)
+        .set_shortcuts(Shortcuts::READY_START);
+    radio.swap_buffer(&mut Some(buffer)).map_err(Error::RadioError)?;
+
+    Ok(Promiscuous {
+      radio,
+      protocol,
+      crc,
+      payload: [0; MAX_PAYLOAD_LENGTH],
+      candidate: None,
+    })
+  }
+
+  /// The frames expected from now on, with a `crc` that can not be disabled
+  pub fn set_protocol(&mut self, protocol: Protocol, crc: Crc) -> Result<()> {
+    check_protocol(protocol, crc)?;
+    self.protocol = protocol;
+    self.crc = crc;
+    Ok(())
+  }
+
+  /// Start listening, the radio needs to be disabled or idle after a previous frame
+  pub fn start_rx(&mut self) -> Result<()> {
+    self.candidate = None;
+    match self.radio.get_state() {
+      RadioState::Disabled => self.radio.enable_rx().map_err(Error::RadioError),
+      RadioState::RxIdle => self.radio.start().map_err(Error::RadioError),
+      _ => Err(Error::StandbyRequired),
+    }
+  }
+
+  /// Wait for a candidate frame, the raw payloads without one are skipped
+  pub fn wait_rx(&mut self) -> AsyncResult<Candidate> {
+    match self.radio.wait_end_or_disable() {
+      Ok(()) => {},
+      Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+      Err(nb::Error::Other(error)) => return Err(nb::Error::Other(Error::RadioError(error))),
+    }
+    let candidate = find_frame(self.radio.get_buffer(), self.protocol, self.crc, &mut self.payload);
+    match candidate {
+      Some(candidate) => {
+        self.candidate = Some(candidate);
+        Ok(candidate)
+      },
+      None => {
+        self.radio.start().map_err(Error::RadioError)?;
+        Err(nb::Error::WouldBlock)
+      },
+    }
+  }
+
+  /// Payload of the last candidate frame
+  pub fn get_payload(&self) -> &[u8] {
+    match (self.candidate, self.protocol) {
+      (Some(candidate), Protocol::DynamicPayloadLength(_)) => &self.payload[..usize::from(candidate.pcf.length)],
+      (Some(_), Protocol::FixedPayloadLength(length)) => &self.payload[..usize::from(length)],
+      (None, _) => &[],
+    }
+  }
+
+  pub fn get_last_candidate(&self) -> Option<Candidate> {
+    self.candidate
+  }
+
+  /// Disable the radio and give it back with the buffer
+  pub fn free(mut self) -> (Radio<'a, LFOSC, LFSTAT, R>, Option<DmaBuffer<'a>>) {
+    if !self.radio.is_disabled() {
+      self.radio.disable();
+      while self.radio.wait_disabled().is_err() {}
+    }
+    let mut buffer = None;
+    drop(self.radio.swap_buffer(&mut buffer));
+    (self.radio, buffer)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::frame::{BitWriter, Frame};
+
+  const ADDRESS_3: [u8; 3] = [0xc2, 0xc2, 0xc2];
+  const ADDRESS_4: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+  const ADDRESS_5: [u8; 5] = [0xa0, 0xb1, 0xc2, 0xd3, 0xe7];
+  const PAYLOAD: [u8; 6] = [0x01, 0x02, 0x03, 0x04, 0xfe, 0xff];
+
+  /// Raw payload as the radio receives it: `shift` bits of the preamble pattern,
+  /// the frame without its preamble, and the noise that follows it.
+  /// Zeros instead of noise would let through longer addresses, that take the frame CRC as payload,
+  /// as the CRC of a frame followed by its own CRC is zero.
+  fn raw_payload(frame: &Frame, config: &FrameConfig, shift: usize) -> [u8; RAW_LENGTH] {
+    let mut encoded = [0u8; 64];
+    let length = frame::encode(frame, config, &mut encoded).unwrap();
+    let mut raw = [0u8; RAW_LENGTH];
+    let mut writer = BitWriter::new(&mut raw);
+    writer.write_bits(0x5555 >> (16 - shift), shift);
+    for byte in encoded[1..length].iter() {
+      writer.write_bits(u32::from(*byte), 8);
+    }
+    let mut noise = 0x1234_5678u32;
+    while writer.position < RAW_LENGTH * 8 {
+      noise = noise.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+      writer.write_bits(noise >> 31, 1);
+    }
+    raw
+  }
+
+  fn check_found(address: &[u8], protocol: Protocol, crc: Crc) {
+    let pcf = Pcf::new(PAYLOAD.len() as u8, 2, false);
+    let frame = Frame::new(address, pcf, &PAYLOAD);
+    let config = FrameConfig::new(address.len(), protocol, crc);
+    for shift in 0..MAX_BIT_OFFSET {
+      let raw = raw_payload(&frame, &config, shift);
+      let mut payload = [0u8; MAX_PAYLOAD_LENGTH];
+      let candidate = find_frame(&raw, protocol, crc, &mut payload).unwrap();
+      assert_eq!(candidate.address(), address);
+      assert_eq!(candidate.pcf, pcf);
+      assert_eq!(candidate.bit_offset, shift);
+      assert_eq!(&payload[..PAYLOAD.len()], &PAYLOAD[..]);
+    }
+  }
+
+  #[test]
+  fn find_frame_three_bytes_address() {
+    check_found(&ADDRESS_3, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+  }
+
+  #[test]
+  fn find_frame_four_bytes_address() {
+    check_found(&ADDRESS_4, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+  }
+
+  #[test]
+  fn find_frame_five_bytes_address() {
+    check_found(&ADDRESS_5, Protocol::dynamic_payload_length(32), Crc::TwoBytes);
+  }
+
+  #[test]
+  fn find_frame_fixed_payload() {
+    let protocol = Protocol::fixed_payload_length(PAYLOAD.len() as u8);
+    let pcf = Pcf::new(0, 1, true);
+    let frame = Frame::new(&ADDRESS_5, pcf, &PAYLOAD);
+    let raw = raw_payload(&frame, &FrameConfig::new(5, protocol, Crc::TwoBytes), 7);
+    let mut payload = [0u8; MAX_PAYLOAD_LENGTH];
+    let candidate = find_frame(&raw, protocol, Crc::TwoBytes, &mut payload).unwrap();
+    assert_eq!(candidate.address(), &ADDRESS_5[..]);
+    assert_eq!(candidate.pcf, pcf);
+    assert_eq!(&payload[..PAYLOAD.len()], &PAYLOAD[..]);
+  }
+
+  #[test]
+  fn find_frame_rejects_corrupted_frame() {
+    let protocol = Protocol::dynamic_payload_length(32);
+    let frame = Frame::new(&ADDRESS_5, Pcf::new(PAYLOAD.len() as u8, 0, false), &PAYLOAD);
+    let mut raw = raw_payload(&frame, &FrameConfig::new(5, protocol, Crc::TwoBytes), 3);
+    raw[8] ^= 0x10;
+    let mut payload = [0u8; MAX_PAYLOAD_LENGTH];
+    assert_eq!(find_frame(&raw, protocol, Crc::TwoBytes, &mut payload), None);
+  }
+
+  #[test]
+  fn find_frame_needs_crc() {
+    let protocol = Protocol::dynamic_payload_length(32);
+    let frame = Frame::new(&ADDRESS_5, Pcf::new(PAYLOAD.len() as u8, 0, false), &PAYLOAD);
+    let raw = raw_payload(&frame, &FrameConfig::new(5, protocol, Crc::Disabled), 0);
+    let mut payload = [0u8; MAX_PAYLOAD_LENGTH];
+    assert_eq!(find_frame(&raw, protocol, Crc::Disabled, &mut payload), None);
+  }
+
+  #[test]
+  fn check_protocol_limits() {
+    assert_eq!(check_protocol(Protocol::dynamic_payload_length(32), Crc::TwoBytes), Ok(()));
+    assert_eq!(check_protocol(Protocol::fixed_payload_length(32), Crc::OneByte), Ok(()));
+    assert_eq!(check_protocol(Protocol::dynamic_payload_length(32), Crc::Disabled), Err(Error::UnsupportedProtocol));
+    assert_eq!(check_protocol(Protocol::dynamic_payload_length(33), Crc::TwoBytes), Err(Error::UnsupportedProtocol));
+    assert_eq!(check_protocol(Protocol::dynamic_payload_length(252), Crc::TwoBytes), Err(Error::UnsupportedProtocol));
+  }
+}
@@ -1,4 +1,16 @@
 
+use nrf52_radio::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+
+use crate::frame::Pcf;
+
+/// Number of bytes in RAM that precede the payload: LENGTH and S1 (PID + NO_ACK)
+pub const HEADER_LENGTH: usize = 2;
+
+/// Size in bytes of the buffers required to hold packets with up to `payload_length` bytes
+pub const fn buffer_length(payload_length: u8) -> usize {
+  HEADER_LENGTH + payload_length as usize
+}
+
 #[derive(Clone, Copy)]
 pub enum Protocol {
   /// Dynamic Payload up to a maximum number of bytes
@@ -20,4 +32,197 @@ impl Protocol {
     assert!(length <= 32);
     Protocol::FixedPayloadLength(length)
   }
+
+  /// Maximum number of payload bytes that a packet can carry
+  pub fn max_payload_length(&self) -> u8 {
+    match self {
+      Protocol::DynamicPayloadLength(max_length) => *max_length,
+      Protocol::FixedPayloadLength(length) => *length,
+    }
+  }
+
+  /// Minimum size in bytes of the buffers used for this protocol
+  pub fn buffer_length(&self) -> usize {
+    buffer_length(self.max_payload_length())
+  }
+
+  /// Radio packet configuration that puts the frames of this protocol on air.
+  /// The PCF is sent as a 6 bits LENGTH followed by a 3 bits S1 with the PID and NO_ACK,
+  /// and with fixed payloads LENGTH is 0 and the payload is made of static bytes.
+  pub fn packet_config(&self) -> PacketConfig {
+    let (length_bits, max_bytes, static_bytes) = match self {
+      Protocol::FixedPayloadLength(length) => (6, *length, *length),
+      Protocol::DynamicPayloadLength(max_length) => (if *max_length <= 32 { 6 } else { 8 }, *max_length, 0),
+    };
+    PacketConfig::default()
+        .with_length_bits(length_bits)
+        .with_s0_byte_included(false)
+        .with_s1_len(S1Length::Of3Bits)
+        .with_s1_include_in_ram(S1IncludeInRam::Automatic)
+        .with_preamble_len(PreambleLength::Of8Bits)
+        .with_max_bytes(max_bytes)
+        .with_static_bytes(static_bytes)
+        .with_endianess(Endianess::BigEndian)
+        .with_whitening_enabled(false)
+  }
+}
+
+/// Copy the payload after the header, with its length in LENGTH for dynamic payloads.
+/// The length needs to be checked against the protocol, and S1 (PID + NO_ACK) is left untouched.
+pub(crate) fn write_payload(protocol: &Protocol, buffer: &mut [u8], payload: &[u8]) {
+  buffer[0] = match protocol {
+    Protocol::DynamicPayloadLength(_) => payload.len() as u8,
+    Protocol::FixedPayloadLength(_) => 0,
+  };
+  let (data, padding) = buffer[HEADER_LENGTH..].split_at_mut(payload.len());
+  data.copy_from_slice(payload);
+  for b in padding.iter_mut() {
+    *b = 0;
+  }
+}
+
+/// PCF of a packet in RAM
+pub(crate) fn read_header(buffer: &[u8]) -> Pcf {
+  Pcf {
+    length: buffer[0],
+    pid: (buffer[1] >> 1) & 0x03,
+    no_ack: (buffer[1] & 0x01) == 0x01,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use nrf52_radio::packet_config::{S1Length, PreambleLength, Endianess, PacketConfig};
+
+  use super::*;
+  use crate::frame::{self, BitReader, BitWriter, Crc};
+
+  // Same reference frames as the frame tests, computed bit by bit from the nRF24L01+ packet format
+
+  /// Dynamic payload, CRC16, address e7e7e7e7e7, PID 1
+  const FRAME_A: [u8; 14] = [0xaa, 0xe7, 0xe7, 0xe7, 0xe7, 0xe7, 0x11, 0x00, 0x81, 0x01, 0x82, 0x71, 0xb9, 0x00];
+
+  /// Fixed payload, CRC8, 3 bytes address, PID 2, no ack
+  const FRAME_B: [u8; 11] = [0xaa, 0xc2, 0xc2, 0xc2, 0x02, 0x88, 0x10, 0x18, 0x20, 0x5b, 0x80];
+
+  /// Dynamic payload, CRC8, address starting with a 0 bit, PID 3, no ack
+  const FRAME_C: [u8; 11] = [0x55, 0x12, 0x34, 0x56, 0x78, 0x9a, 0x0b, 0xd5, 0xe6, 0xb6, 0x80];
+
+  /// Empty ACK, CRC16, PID 0
+  const FRAME_D: [u8; 10] = [0xaa, 0xa0, 0xb1, 0xc2, 0xd3, 0xe0, 0x00, 0x19, 0xed, 0x00];
+
+  /// Serialize a packet from RAM the way the radio does with the given packet configuration
+  fn on_air(config: &PacketConfig, address: &[u8], ram: &[u8], crc: Crc, output: &mut [u8]) -> usize {
+    assert_eq!(config.s0_byte_included, Some(false));
+    assert_eq!(config.preamble_len, Some(PreambleLength::Of8Bits));
+    assert_eq!(config.endianess, Some(Endianess::BigEndian));
+    assert_eq!(config.whitening_enabled, Some(false));
+    let payload_length = usize::from(ram[0]) + usize::from(config.static_bytes.unwrap());
+    assert!(payload_length <= usize::from(config.max_bytes.unwrap()));
+
+    let mut writer = BitWriter::new(output);
+    writer.write_bits(u32::from(frame::preamble(address)), 8);
+    for byte in address.iter() {
+      writer.write_bits(u32::from(*byte), 8);
+    }
+    writer.write_bits(u32::from(ram[0]), usize::from(config.length_bits.unwrap()));
+    writer.write_bits(u32::from(ram[1]), config.s1_len.unwrap().value() as usize);
+    for byte in ram[HEADER_LENGTH..HEADER_LENGTH + payload_length].iter() {
+      writer.write_bits(u32::from(*byte), 8);
+    }
+    let crc_start = writer.position;
+    let crc_length = match crc {
+      Crc::Disabled => 0,
+      Crc::OneByte => 8,
+      Crc::TwoBytes => 16,
+    };
+    let value = frame::crc(writer.buffer, 8, crc_start, crc);
+    writer.write_bits(value, crc_length);
+    writer.pad();
+    writer.position / 8
+  }
+
+  /// Store the header of a received frame into RAM the way the radio does with the given packet configuration
+  fn header_in_ram(config: &PacketConfig, address_length: usize, input: &[u8], ram: &mut [u8]) {
+    let reader = BitReader::new(input);
+    let length_start = (1 + address_length) * 8;
+    let length_bits = usize::from(config.length_bits.unwrap());
+    ram[0] = reader.read_bits(length_start, length_bits) as u8;
+    ram[1] = reader.read_bits(length_start + length_bits, config.s1_len.unwrap().value() as usize) as u8;
+  }
+
+  #[test]
+  fn packet_config() {
+    let config = Protocol::dynamic_payload_length(32).packet_config();
+    assert_eq!(config.length_bits, Some(6));
+    assert_eq!(config.s1_len, Some(S1Length::Of3Bits));
+    assert_eq!(config.max_bytes, Some(32));
+    assert_eq!(config.static_bytes, Some(0));
+
+    let config = Protocol::dynamic_payload_length(252).packet_config();
+    assert_eq!(config.length_bits, Some(8));
+    assert_eq!(config.max_bytes, Some(252));
+
+    let config = Protocol::fixed_payload_length(10).packet_config();
+    assert_eq!(config.length_bits, Some(6));
+    assert_eq!(config.max_bytes, Some(10));
+    assert_eq!(config.static_bytes, Some(10));
+  }
+
+  #[test]
+  fn dynamic_payload_on_air() {
+    let protocol = Protocol::dynamic_payload_length(32);
+    let address = [0xe7, 0xe7, 0xe7, 0xe7, 0xe7];
+    let payload = [0x01, 0x02, 0x03, 0x04];
+    let mut ram = [0xffu8; buffer_length(32)];
+    write_payload(&protocol, &mut ram, &payload);
+    ram[1] = 1 << 1;
+    assert_eq!(read_header(&ram), Pcf::new(4, 1, false));
+
+    let mut sent = [0u8; 64];
+    let length = on_air(&protocol.packet_config(), &address, &ram, Crc::TwoBytes, &mut sent);
+    assert_eq!(&sent[..length], &FRAME_A[..]);
+  }
+
+  #[test]
+  fn fixed_payload_on_air() {
+    let protocol = Protocol::fixed_payload_length(4);
+    let address = [0xc2, 0xc2, 0xc2];
+    let payload = [0x10, 0x20, 0x30, 0x40];
+    let mut ram = [0xffu8; buffer_length(4)];
+    write_payload(&protocol, &mut ram, &payload);
+    ram[1] = 2 << 1 | 1;
+    assert_eq!(read_header(&ram), Pcf::new(0, 2, true));
+
+    let mut sent = [0u8; 64];
+    let length = on_air(&protocol.packet_config(), &address, &ram, Crc::OneByte, &mut sent);
+    assert_eq!(&sent[..length], &FRAME_B[..]);
+  }
+
+  #[test]
+  fn empty_ack_payload_on_air() {
+    let protocol = Protocol::dynamic_payload_length(32);
+    let address = [0xa0, 0xb1, 0xc2, 0xd3, 0xe0];
+    let mut ram = [0xffu8; buffer_length(32)];
+    write_payload(&protocol, &mut ram, &[]);
+    ram[1] = 0;
+    assert_eq!(&ram[HEADER_LENGTH..], &[0u8; 32][..]);
+
+    let mut sent = [0u8; 64];
+    let length = on_air(&protocol.packet_config(), &address, &ram, Crc::TwoBytes, &mut sent);
+    assert_eq!(&sent[..length], &FRAME_D[..]);
+  }
+
+  #[test]
+  fn received_header() {
+    let mut ram = [0u8; buffer_length(32)];
+    header_in_ram(&Protocol::dynamic_payload_length(32).packet_config(), 5, &FRAME_C, &mut ram);
+    assert_eq!(read_header(&ram), Pcf::new(2, 3, true));
+
+    header_in_ram(&Protocol::fixed_payload_length(4).packet_config(), 3, &FRAME_B, &mut ram);
+    assert_eq!(read_header(&ram), Pcf::new(0, 2, true));
+
+    header_in_ram(&Protocol::dynamic_payload_length(32).packet_config(), 5, &FRAME_D, &mut ram);
+    assert_eq!(read_header(&ram), Pcf::new(0, 0, false));
+  }
 }
@@ -0,0 +1,66 @@
+/*!
+
+Timeouts
+
+The PTX waits for the acknowledgement during the ACK window after each transmission, and the PRX
+listens for packets during receive windows. A window that ends without a valid packet is a failure
+for the channel hopping and the power control, and the transaction is tried again up to the number
+of retries in `TxConfig` and `RxConfig`. After the last one, `wait_tx` and `wait_rx` give `Error::Timeout`.
+
+The windows include the time to ramp up the radio, around 130 us, and they are only checked while
+the state machine is polled, so a window lasts at least as long as the time between two polls.
+
+*/
+
+use nrf52_radio::timestamps::Timestamps;
+
+/// Free running time in microseconds, wrapping around at 32 bits
+pub trait Clock {
+  fn now(&self) -> u32;
+}
+
+impl<'a> Clock for Timestamps<'a> {
+  fn now(&self) -> u32 {
+    Timestamps::now(self)
+  }
+}
+
+#[derive(Clone, Copy)]
+pub struct Timeouts<'a> {
+  clock: &'a dyn Clock,
+  ack: Option<u32>,
+  rx_window: Option<u32>,
+}
+
+impl<'a> Timeouts<'a> {
+  /// No timeouts until the windows are given
+  pub fn new(clock: &'a dyn Clock) -> Self {
+    Timeouts {
+      clock,
+      ack: None,
+      rx_window: None,
+    }
+  }
+
+  /// Microseconds to wait for the acknowledgement after a transmission
+  pub fn with_ack(self, ack: u32) -> Self {
+    Timeouts { ack: Some(ack), .. self }
+  }
+
+  /// Microseconds of a receive window
+  pub fn with_rx_window(self, rx_window: u32) -> Self {
+    Timeouts { rx_window: Some(rx_window), .. self }
+  }
+
+  pub fn ack(&self) -> Option<u32> {
+    self.ack
+  }
+
+  pub fn rx_window(&self) -> Option<u32> {
+    self.rx_window
+  }
+
+  pub(crate) fn now(&self) -> u32 {
+    self.clock.now()
+  }
+}
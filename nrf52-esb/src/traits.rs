@@ -0,0 +1,124 @@
+/*!
+
+Generic radio traits
+
+The transactions started through the traits use the configurations given by `Esb::set_tx_config`
+and `Esb::set_rx_config`, so the acknowledgements and retries work as usual.
+
+The reception already skips the packets with a wrong CRC or length, so it always restarts after
+an invalid packet, whatever `restart` says.
+
+*/
+
+use core::ops::Deref;
+
+use nrf52_radio::hal::target::radio::RegisterBlock;
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::tx_power::TxPower;
+
+use radio_traits::{Channel, Power, Receive, ReceiveInfo, Rssi, Transmit};
+
+use crate::{Esb, Error, Result, RxPacket, State};
+use crate::protocol::{Protocol, HEADER_LENGTH};
+
+/// Information about the last received packet
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RxInfo {
+  /// Only meaningful with the `ADDRESS_RSSISTART` shortcut enabled
+  pub rssi: i16,
+  pub packet: Option<RxPacket>,
+}
+
+impl ReceiveInfo for RxInfo {
+  fn rssi(&self) -> i16 {
+    self.rssi
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Transmit for Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+
+  fn start_transmit(&mut self, data: &[u8]) -> Result<()> {
+    self.set_tx_payload(data)?;
+    self.start_tx(self.tx_config)
+  }
+
+  fn check_transmit(&mut self) -> Result<bool> {
+    check(self.wait_tx())
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Receive for Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+  type Info = RxInfo;
+
+  fn start_receive(&mut self) -> Result<()> {
+    self.start_rx(self.rx_config)
+  }
+
+  fn check_receive(&mut self, _restart: bool) -> Result<bool> {
+    check(self.wait_rx())
+  }
+
+  fn get_received(&mut self, info: &mut RxInfo, buffer: &mut [u8]) -> Result<usize> {
+    let packet = self.rx_packet.ok_or(Error::NoPacket)?;
+    let length = match self.protocol {
+      Protocol::DynamicPayloadLength(_) => usize::from(packet.length),
+      Protocol::FixedPayloadLength(length) => usize::from(length),
+    };
+    if buffer.len() < length {
+      return Err(Error::BufferTooSmall);
+    }
+    buffer[..length].copy_from_slice(&self.get_rx_buffer()[HEADER_LENGTH..HEADER_LENGTH + length]);
+    info.rssi = -i16::from(self.radio.get_rssi_sample());
+    info.packet = Some(packet);
+    Ok(length)
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Rssi for Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+
+  /// The radio needs to be receiving
+  fn poll_rssi(&mut self) -> Result<i16> {
+    self.radio.poll_rssi().map_err(Error::RadioError)
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Channel for Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Channel = Frequency;
+  type Error = Error;
+
+  /// Standby required, and not available when hopping
+  fn set_channel(&mut self, channel: &Frequency) -> Result<()> {
+    match self.state {
+      State::Standby if self.hopping.is_some() => Err(Error::HoppingEnabled),
+      State::Standby => {
+        self.radio.set_frequency(*channel);
+        Ok(())
+      },
+      _ => Err(Error::StandbyRequired)
+    }
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Power for Esb<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+
+  /// Not available when the power is adapted with `Esb::set_power_control`
+  fn set_power(&mut self, power: i8) -> Result<()> {
+    if self.power_control.is_some() {
+      return Err(Error::PowerControlEnabled);
+    }
+    self.radio.set_tx_power(TxPower::from_dbm(power)).map_err(Error::RadioError)?;
+    Ok(())
+  }
+}
+
+fn check(result: nb::Result<(), Error>) -> Result<bool> {
+  match result {
+    Ok(()) => Ok(true),
+    Err(nb::Error::WouldBlock) => Ok(false),
+    Err(nb::Error::Other(error)) => Err(error),
+  }
+}
@@ -0,0 +1,417 @@
+#![no_std]
+
+/*!
+
+Register-level emulator of the nRF52840 RADIO peripheral
+
+It models the registers, tasks, events, states, shortcuts and the EasyDMA accesses through PACKETPTR,
+so the [`nrf52_radio::Radio`] and the crates built on top of it can run on the host:
+
+```ignore
+let emulator = Emulator::new();
+let mut buffer = [0u8; 34];
+unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+let mut radio = unsafe { Radio::new_unchecked(emulator.registers()) };
+// Host buffers are not in the Data RAM of the chip
+radio.swap_buffer(&mut Some(unsafe { DmaBuffer::new_unchecked(&mut buffer) })).unwrap();
+radio.enable_tx().unwrap();
+
+emulator.step().unwrap();            // TXEN: DISABLED -> TXRU
+emulator.step().unwrap();            // READY: TXRU -> TXIDLE
+radio.wait_idle().unwrap();
+```
+
+The emulator only advances when `step` is called. Every step first moves the state machine one
+stage forward, firing the events for it, and then runs the tasks triggered by the software or
+by the shortcuts:
+
+```text
+DISABLED --TXEN--> TXRU --step--> TXIDLE --START--> TX --step--> TXIDLE
+DISABLED --RXEN--> RXRU --step--> RXIDLE --START--> RX --step with a packet on air--> RXIDLE
+any other state --DISABLE--> TXDISABLE/RXDISABLE --step--> DISABLED
+```
+
+RSSI, energy detection and clear channel assessment finish as soon as their tasks run, with the
+values given to `set_rssi`, `set_energy_level` and `set_channel_busy`. The bit counter, the device
+address match, whitening, the CRC computation and the timing are not modelled.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20 RADIO
+
+*/
+
+pub mod registers;
+mod packet;
+
+pub use packet::{AirPacket, MAX_PACKET_LENGTH};
+
+use packet::MAX_HEADER_LENGTH;
+
+use core::cell::Cell;
+use core::ops::Deref;
+use core::ptr;
+
+use nrf52_radio::hal::target::radio::RegisterBlock;
+
+use nrf52_radio::frequency::{Frequency, Map};
+use nrf52_radio::logical_address::LogicalAddress;
+use nrf52_radio::shortcuts::Shortcuts;
+use nrf52_radio::states::State;
+
+use crate::registers::*;
+
+const STATE_DISABLED: u32 = 0;
+const STATE_RX_RU: u32 = 1;
+const STATE_RX_IDLE: u32 = 2;
+const STATE_RX: u32 = 3;
+const STATE_RX_DISABLE: u32 = 4;
+const STATE_TX_RU: u32 = 9;
+const STATE_TX_IDLE: u32 = 10;
+const STATE_TX: u32 = 11;
+const STATE_TX_DISABLE: u32 = 12;
+
+/// Shortcuts as (shortcut, event, task)
+const SHORTCUTS: [(Shortcuts, usize, usize); 19] = [
+  (Shortcuts::READY_START, EVENTS_READY, TASKS_START),
+  (Shortcuts::END_DISABLE, EVENTS_END, TASKS_DISABLE),
+  (Shortcuts::DISABLED_TXEN, EVENTS_DISABLED, TASKS_TXEN),
+  (Shortcuts::DISABLED_RXEN, EVENTS_DISABLED, TASKS_RXEN),
+  (Shortcuts::ADDRESS_RSSISTART, EVENTS_ADDRESS, TASKS_RSSISTART),
+  (Shortcuts::END_START, EVENTS_END, TASKS_START),
+  (Shortcuts::ADDRESS_BCSTART, EVENTS_ADDRESS, TASKS_BCSTART),
+  (Shortcuts::DISABLED_RSSISTOP, EVENTS_DISABLED, TASKS_RSSISTOP),
+  (Shortcuts::RXREADY_CCASTART, EVENTS_RXREADY, TASKS_CCASTART),
+  (Shortcuts::CCAIDLE_TXEN, EVENTS_CCAIDLE, TASKS_TXEN),
+  (Shortcuts::CCABUSY_DISABLE, EVENTS_CCABUSY, TASKS_DISABLE),
+  (Shortcuts::FRAMESTART_BCSTART, EVENTS_FRAMESTART, TASKS_BCSTART),
+  (Shortcuts::READY_EDSTART, EVENTS_READY, TASKS_EDSTART),
+  (Shortcuts::EDEND_DISABLE, EVENTS_EDEND, TASKS_DISABLE),
+  (Shortcuts::CCAIDLE_STOP, EVENTS_CCAIDLE, TASKS_CCASTOP),
+  (Shortcuts::TXREADY_START, EVENTS_TXREADY, TASKS_START),
+  (Shortcuts::RXREADY_START, EVENTS_RXREADY, TASKS_START),
+  (Shortcuts::PHYEND_DISABLE, EVENTS_PHYEND, TASKS_DISABLE),
+  (Shortcuts::PHYEND_START, EVENTS_PHYEND, TASKS_START),
+];
+
+/// Rounds of tasks run in a single step before giving up on a loop of shortcuts
+const MAX_TASK_ROUNDS: usize = 16;
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The shortcuts kept triggering tasks after `MAX_TASK_ROUNDS` rounds in a single step
+  ShortcutLoop,
+}
+
+/// Handle to the emulated registers, to be given to `Radio::new_unchecked`
+pub struct Registers<'e> {
+  memory: &'e Memory,
+}
+
+impl<'e> Deref for Registers<'e> {
+  type Target = RegisterBlock;
+
+  fn deref(&self) -> &RegisterBlock {
+    self.memory.register_block()
+  }
+}
+
+pub struct Emulator {
+  memory: Memory,
+  interrupts: Cell<u32>,
+  dma_region: Cell<Option<(usize, usize)>>,
+  rssi: Cell<u8>,
+  energy_level: Cell<u8>,
+  channel_busy: Cell<bool>,
+  on_air: Cell<Option<AirPacket>>,
+  transmitted: Cell<Option<AirPacket>>,
+}
+
+impl Default for Emulator {
+  fn default() -> Self {
+    Emulator {
+      memory: Memory::new(),
+      interrupts: Cell::new(0),
+      dma_region: Cell::new(None),
+      rssi: Cell::new(0x7f),
+      energy_level: Cell::new(0),
+      channel_busy: Cell::new(false),
+      on_air: Cell::new(None),
+      transmitted: Cell::new(None),
+    }
+  }
+}
+
+impl Emulator {
+  /// Emulator with the registers at their reset values
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn registers(&self) -> Registers<'_> {
+    Registers { memory: &self.memory }
+  }
+
+  /// Make the RAM between `start` and `start + length` reachable through PACKETPTR.
+  ///
+  /// PACKETPTR only holds 32 bits, so the rest of the host address is taken from this region,
+  /// which needs to contain all the buffers given to the radio.
+  ///
+  /// # Safety
+  ///
+  /// The region must stay valid while the emulator is used, and the radio must be the only one
+  /// accessing the buffers in it while the emulator steps.
+  pub unsafe fn map_dma_region(&self, start: *mut u8, length: usize) {
+    self.dma_region.set(Some((start as usize, length)));
+  }
+
+  /// Current state of the radio (STATE register)
+  pub fn state(&self) -> State {
+    State::from_value(self.memory.read(STATE) as u8)
+  }
+
+  /// Put a packet on air, it stays there until a step finds the radio in RX.
+  /// Then it is received if the frequency matches and its logical address is enabled in RXADDRESSES,
+  /// otherwise it is lost.
+  /// A new packet replaces the one on air.
+  pub fn receive(&self, packet: AirPacket) {
+    self.on_air.set(Some(packet));
+  }
+
+  /// Last packet transmitted by the radio
+  pub fn take_transmitted(&self) -> Option<AirPacket> {
+    self.transmitted.take()
+  }
+
+  /// Value for RSSISAMPLE, as the magnitude of the received signal strength (-dBm)
+  pub fn set_rssi(&self, rssi: u8) {
+    self.rssi.set(rssi);
+  }
+
+  /// Value for EDSAMPLE
+  pub fn set_energy_level(&self, level: u8) {
+    self.energy_level.set(level);
+  }
+
+  /// Result for the next clear channel assessments
+  pub fn set_channel_busy(&self, busy: bool) {
+    self.channel_busy.set(busy);
+  }
+
+  /// Whether any of the events with the interrupt enabled is set
+  pub fn is_interrupt_pending(&self) -> bool {
+    self.update_interrupts();
+    let interrupts = self.interrupts.get();
+    // The INTENSET bits follow the order of the event registers
+    (0..32).any(|bit| interrupts & 1 << bit != 0 && self.memory.is_set(EVENTS_READY + 4 * bit))
+  }
+
+  /// Move the state machine one stage forward, and run all the triggered tasks
+  pub fn step(&self) -> Result<()> {
+    self.update_interrupts();
+    self.advance();
+    let result = self.run_tasks();
+    self.update_interrupts();
+    result
+  }
+
+  /// Writes to INTENSET and INTENCLR only carry the bits that change, so the enabled interrupts
+  /// are kept apart and written back to INTENSET. Reading INTENCLR gives zero.
+  fn update_interrupts(&self) {
+    let interrupts = (self.interrupts.get() | self.memory.read(INTENSET)) & !self.memory.read(INTENCLR);
+    self.interrupts.set(interrupts);
+    self.memory.write(INTENSET, interrupts);
+    self.memory.write(INTENCLR, 0);
+  }
+
+  fn advance(&self) {
+    match self.memory.read(STATE) {
+      STATE_TX_RU => {
+        self.memory.write(STATE, STATE_TX_IDLE);
+        self.fire(EVENTS_READY);
+        self.fire(EVENTS_TXREADY);
+      },
+      STATE_RX_RU => {
+        self.memory.write(STATE, STATE_RX_IDLE);
+        self.fire(EVENTS_READY);
+        self.fire(EVENTS_RXREADY);
+      },
+      STATE_TX => self.transmit(),
+      STATE_RX => self.listen(),
+      STATE_TX_DISABLE | STATE_RX_DISABLE => {
+        self.memory.write(STATE, STATE_DISABLED);
+        self.fire(EVENTS_DISABLED);
+      },
+      _ => (),
+    }
+  }
+
+  fn run_tasks(&self) -> Result<()> {
+    for _ in 0..MAX_TASK_ROUNDS {
+      let mut triggered = false;
+      for task in TASKS.iter() {
+        if self.memory.is_set(*task) {
+          self.memory.write(*task, 0);
+          self.run_task(*task);
+          triggered = true;
+        }
+      }
+      if !triggered {
+        return Ok(());
+      }
+    }
+    Err(Error::ShortcutLoop)
+  }
+
+  fn run_task(&self, task: usize) {
+    let state = self.memory.read(STATE);
+    let receiving = state == STATE_RX_IDLE || state == STATE_RX;
+    match (task, state) {
+      (TASKS_TXEN, STATE_DISABLED) => self.memory.write(STATE, STATE_TX_RU),
+      (TASKS_RXEN, STATE_DISABLED) => self.memory.write(STATE, STATE_RX_RU),
+      (TASKS_START, STATE_TX_IDLE) => self.memory.write(STATE, STATE_TX),
+      (TASKS_START, STATE_RX_IDLE) => self.memory.write(STATE, STATE_RX),
+      (TASKS_STOP, STATE_TX) => self.memory.write(STATE, STATE_TX_IDLE),
+      (TASKS_STOP, STATE_RX) => self.memory.write(STATE, STATE_RX_IDLE),
+      (TASKS_DISABLE, STATE_DISABLED) => self.fire(EVENTS_DISABLED),
+      (TASKS_DISABLE, STATE_TX_RU) | (TASKS_DISABLE, STATE_TX_IDLE) | (TASKS_DISABLE, STATE_TX) =>
+        self.memory.write(STATE, STATE_TX_DISABLE),
+      (TASKS_DISABLE, STATE_RX_RU) | (TASKS_DISABLE, STATE_RX_IDLE) | (TASKS_DISABLE, STATE_RX) =>
+        self.memory.write(STATE, STATE_RX_DISABLE),
+      (TASKS_RSSISTART, _) if receiving => {
+        self.memory.write(RSSISAMPLE, u32::from(self.rssi.get() & 0x7f));
+        self.fire(EVENTS_RSSIEND);
+      },
+      (TASKS_EDSTART, _) if receiving => {
+        self.memory.write(EDSAMPLE, u32::from(self.energy_level.get()));
+        self.fire(EVENTS_EDEND);
+      },
+      (TASKS_EDSTOP, _) => self.fire(EVENTS_EDSTOPPED),
+      (TASKS_CCASTART, _) if receiving => {
+        if self.channel_busy.get() {
+          self.fire(EVENTS_CCABUSY);
+        }
+        else {
+          self.fire(EVENTS_CCAIDLE);
+        }
+      },
+      (TASKS_CCASTOP, _) => self.fire(EVENTS_CCASTOPPED),
+      _ => (),
+    }
+  }
+
+  /// Set the event, and trigger the tasks of the enabled shortcuts
+  fn fire(&self, event: usize) {
+    self.memory.write(event, 1);
+    let shortcuts = Shortcuts::from_bits_truncate(self.memory.read(SHORTS));
+    for (shortcut, shortcut_event, task) in SHORTCUTS.iter() {
+      if *shortcut_event == event && shortcuts.contains(*shortcut) {
+        self.memory.write(*task, 1);
+      }
+    }
+  }
+
+  fn transmit(&self) {
+    let mut header = [0u8; MAX_HEADER_LENGTH];
+    let header_length = self.header_length();
+    self.read_dma(&mut header[..header_length]);
+    let length = header_length + self.payload_length(&header);
+
+    let mut data = [0u8; MAX_PACKET_LENGTH];
+    self.read_dma(&mut data[..length]);
+    let address = LogicalAddress::from(self.memory.read(TXADDRESS) & 0x07).unwrap();
+    let mut packet = AirPacket::new(address, &data[..length]);
+    packet.frequency = self.frequency();
+    self.transmitted.set(Some(packet));
+
+    self.memory.write(STATE, STATE_TX_IDLE);
+    self.fire(EVENTS_ADDRESS);
+    self.fire(EVENTS_PAYLOAD);
+    self.fire(EVENTS_END);
+    self.fire(EVENTS_PHYEND);
+  }
+
+  fn listen(&self) {
+    if let Some(packet) = self.on_air.take() {
+      let heard = packet.frequency.map_or(true, |frequency| Some(frequency) == self.frequency());
+      let enabled = self.memory.read(RXADDRESSES) & 1 << packet.address.value() != 0;
+      if heard && enabled {
+        self.receive_packet(&packet);
+      }
+    }
+  }
+
+  fn receive_packet(&self, packet: &AirPacket) {
+    let max_length = self.header_length() + (self.memory.read(PCNF1) & 0xff) as usize;
+    let data = packet.data();
+    self.write_dma(&data[..data.len().min(max_length)]);
+    self.memory.write(RXMATCH, packet.address.value());
+    self.memory.write(RXCRC, packet.crc);
+    self.memory.write(CRCSTATUS, if packet.crc_ok { 1 } else { 0 });
+
+    self.memory.write(STATE, STATE_RX_IDLE);
+    self.fire(EVENTS_ADDRESS);
+    self.fire(EVENTS_PAYLOAD);
+    self.fire(EVENTS_END);
+    self.fire(if packet.crc_ok { EVENTS_CRCOK } else { EVENTS_CRCERROR });
+    self.fire(EVENTS_PHYEND);
+  }
+
+  /// Bytes in RAM before the payload: S0, LENGTH and S1
+  fn header_length(&self) -> usize {
+    let pcnf0 = self.memory.read(PCNF0);
+    let s0_length = (pcnf0 >> 8 & 0x01) as usize;
+    let length_length = ((pcnf0 & 0x0f) as usize + 7) / 8;
+    let s1_length = ((pcnf0 >> 16 & 0x0f) as usize + 7) / 8;
+    let s1_included = (pcnf0 >> 20 & 0x01) as usize;
+    s0_length + length_length + s1_length.max(s1_included)
+  }
+
+  /// Payload bytes of the packet to transmit, given its header in RAM
+  fn payload_length(&self, header: &[u8]) -> usize {
+    let pcnf0 = self.memory.read(PCNF0);
+    let pcnf1 = self.memory.read(PCNF1);
+    // LENGTH follows S0, and above 8 bits it takes a second byte, as in `Radio::required_buffer_length`,
+    // which is taken as the most significant one
+    let length_bits = pcnf0 & 0x0f;
+    let length_start = (pcnf0 >> 8 & 0x01) as usize;
+    let length = match length_bits {
+      0 => 0,
+      1..=8 => u32::from(header[length_start]),
+      _ => u32::from(header[length_start]) | u32::from(header[length_start + 1]) << 8,
+    } & ((1 << length_bits) - 1);
+    let crc_length = if pcnf0 >> 26 & 0x01 == 1 { self.memory.read(CRCCNF) & 0x03 } else { 0 };
+    let max_length = pcnf1 & 0xff;
+    let static_length = pcnf1 >> 8 & 0xff;
+    (length.saturating_sub(crc_length) + static_length).min(max_length) as usize
+  }
+
+  /// None when the channel is out of its map
+  fn frequency(&self) -> Option<Frequency> {
+    let frequency = self.memory.read(FREQUENCY);
+    let map = if frequency & 0x100 != 0 { Map::Low2360MHz } else { Map::Default2400MHz };
+    Frequency::from_channel(map, (frequency & 0x7f) as u8).ok()
+  }
+
+  /// Host address for an EasyDMA access of `length` bytes at PACKETPTR
+  fn dma_address(&self, length: usize) -> usize {
+    let (start, region_length) = self.dma_region.get()
+        .expect("EasyDMA access without a DMA region mapped");
+    let high_bits = (start as u64 & !0xffff_ffff) as usize;
+    let address = high_bits | self.memory.read(PACKETPTR) as usize;
+    assert!(address >= start && address + length <= start + region_length,
+            "EasyDMA access outside of the DMA region");
+    address
+  }
+
+  fn read_dma(&self, output: &mut [u8]) {
+    let address = self.dma_address(output.len());
+    unsafe { ptr::copy_nonoverlapping(address as *const u8, output.as_mut_ptr(), output.len()) }
+  }
+
+  fn write_dma(&self, input: &[u8]) {
+    let address = self.dma_address(input.len());
+    unsafe { ptr::copy_nonoverlapping(input.as_ptr(), address as *mut u8, input.len()) }
+  }
+}
@@ -0,0 +1,58 @@
+
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::logical_address::LogicalAddress;
+
+/// S0 takes up to 1 byte in RAM, while LENGTH and S1 take up to 2 bytes each for their 15 bits
+pub(crate) const MAX_HEADER_LENGTH: usize = 5;
+
+/// S0, LENGTH and S1 bytes followed by up to 255 bytes of payload
+pub const MAX_PACKET_LENGTH: usize = MAX_HEADER_LENGTH + 255;
+
+/// A packet on air, as the radio stores it in RAM (S0, LENGTH, S1 and payload).
+/// The address, the CRC and the frequency are kept apart as they are not stored in RAM.
+#[derive(Clone, Copy)]
+pub struct AirPacket {
+  /// Logical address the packet is sent to
+  pub address: LogicalAddress,
+
+  /// Frequency the packet is sent on, None to be heard on any frequency
+  pub frequency: Option<Frequency>,
+
+  /// Whether the receiver will find the CRC correct
+  pub crc_ok: bool,
+
+  /// CRC field reported to the receiver through RXCRC
+  pub crc: u32,
+
+  data: [u8; MAX_PACKET_LENGTH],
+  length: usize,
+}
+
+impl AirPacket {
+  /// Packet with the contents of `data` as stored in RAM, with a correct CRC
+  pub fn new(address: LogicalAddress, data: &[u8]) -> Self {
+    assert!(data.len() <= MAX_PACKET_LENGTH);
+    let mut packet = AirPacket {
+      address,
+      frequency: None,
+      crc_ok: true,
+      crc: 0,
+      data: [0; MAX_PACKET_LENGTH],
+      length: data.len(),
+    };
+    packet.data[..data.len()].copy_from_slice(data);
+    packet
+  }
+
+  pub fn with_frequency(self, frequency: Frequency) -> Self {
+    Self { frequency: Some(frequency), .. self }
+  }
+
+  pub fn with_crc(self, crc_ok: bool, crc: u32) -> Self {
+    Self { crc_ok, crc, .. self }
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data[..self.length]
+  }
+}
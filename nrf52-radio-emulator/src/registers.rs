@@ -0,0 +1,133 @@
+/*!
+
+Memory backing the RADIO registers
+
+The memory has the same layout as the `RegisterBlock` from the PAC, so the HAL can access it
+as if it was the peripheral, while the emulator reads and writes the registers by offset.
+The offsets come from the nrf52840.svd.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14 Registers
+
+*/
+
+use core::cell::UnsafeCell;
+use core::ptr;
+
+use nrf52_radio::hal::target::radio::RegisterBlock;
+
+/// Size in bytes of the RADIO register block
+pub const LENGTH: usize = 0x1000;
+
+pub const TASKS_TXEN: usize = 0x000;
+pub const TASKS_RXEN: usize = 0x004;
+pub const TASKS_START: usize = 0x008;
+pub const TASKS_STOP: usize = 0x00c;
+pub const TASKS_DISABLE: usize = 0x010;
+pub const TASKS_RSSISTART: usize = 0x014;
+pub const TASKS_RSSISTOP: usize = 0x018;
+pub const TASKS_BCSTART: usize = 0x01c;
+pub const TASKS_BCSTOP: usize = 0x020;
+pub const TASKS_EDSTART: usize = 0x024;
+pub const TASKS_EDSTOP: usize = 0x028;
+pub const TASKS_CCASTART: usize = 0x02c;
+pub const TASKS_CCASTOP: usize = 0x030;
+
+pub const EVENTS_READY: usize = 0x100;
+pub const EVENTS_ADDRESS: usize = 0x104;
+pub const EVENTS_PAYLOAD: usize = 0x108;
+pub const EVENTS_END: usize = 0x10c;
+pub const EVENTS_DISABLED: usize = 0x110;
+pub const EVENTS_DEVMATCH: usize = 0x114;
+pub const EVENTS_DEVMISS: usize = 0x118;
+pub const EVENTS_RSSIEND: usize = 0x11c;
+pub const EVENTS_BCMATCH: usize = 0x128;
+pub const EVENTS_CRCOK: usize = 0x130;
+pub const EVENTS_CRCERROR: usize = 0x134;
+pub const EVENTS_FRAMESTART: usize = 0x138;
+pub const EVENTS_EDEND: usize = 0x13c;
+pub const EVENTS_EDSTOPPED: usize = 0x140;
+pub const EVENTS_CCAIDLE: usize = 0x144;
+pub const EVENTS_CCABUSY: usize = 0x148;
+pub const EVENTS_CCASTOPPED: usize = 0x14c;
+pub const EVENTS_RATEBOOST: usize = 0x150;
+pub const EVENTS_TXREADY: usize = 0x154;
+pub const EVENTS_RXREADY: usize = 0x158;
+pub const EVENTS_MHRMATCH: usize = 0x15c;
+pub const EVENTS_PHYEND: usize = 0x16c;
+
+pub const SHORTS: usize = 0x200;
+pub const INTENSET: usize = 0x304;
+pub const INTENCLR: usize = 0x308;
+pub const CRCSTATUS: usize = 0x400;
+pub const RXMATCH: usize = 0x408;
+pub const RXCRC: usize = 0x40c;
+pub const PACKETPTR: usize = 0x504;
+pub const FREQUENCY: usize = 0x508;
+pub const PCNF0: usize = 0x514;
+pub const PCNF1: usize = 0x518;
+pub const TXADDRESS: usize = 0x52c;
+pub const RXADDRESSES: usize = 0x530;
+pub const CRCCNF: usize = 0x534;
+pub const RSSISAMPLE: usize = 0x548;
+pub const STATE: usize = 0x550;
+pub const DATAWHITEIV: usize = 0x554;
+pub const MODECNF0: usize = 0x650;
+pub const SFD: usize = 0x660;
+pub const EDSAMPLE: usize = 0x668;
+pub const CCACTRL: usize = 0x66c;
+pub const POWER: usize = 0xffc;
+
+/// Tasks in the order they are processed by the emulator
+pub const TASKS: [usize; 13] = [
+  TASKS_DISABLE, TASKS_STOP, TASKS_TXEN, TASKS_RXEN, TASKS_START,
+  TASKS_RSSISTART, TASKS_RSSISTOP, TASKS_BCSTART, TASKS_BCSTOP,
+  TASKS_EDSTART, TASKS_EDSTOP, TASKS_CCASTART, TASKS_CCASTOP,
+];
+
+/// Registers that do not reset to zero, as (offset, value)
+pub const RESET_VALUES: [(usize, u32); 6] = [
+  (FREQUENCY, 0x0000_0002),
+  (DATAWHITEIV, 0x0000_0040),
+  (MODECNF0, 0x0000_0200),
+  (SFD, 0x0000_00a7),
+  (CCACTRL, 0x052d_0000),
+  (POWER, 0x0000_0001),
+];
+
+// The PAC must agree on the size of the register block
+const _: [(); LENGTH] = [(); core::mem::size_of::<RegisterBlock>()];
+
+#[repr(C, align(4))]
+pub(crate) struct Memory(UnsafeCell<[u32; LENGTH / 4]>);
+
+impl Memory {
+  pub fn new() -> Self {
+    let memory = Memory(UnsafeCell::new([0; LENGTH / 4]));
+    for (offset, value) in RESET_VALUES.iter() {
+      memory.write(*offset, *value);
+    }
+    memory
+  }
+
+  pub fn read(&self, offset: usize) -> u32 {
+    unsafe { ptr::read_volatile(self.word(offset)) }
+  }
+
+  pub fn write(&self, offset: usize, value: u32) {
+    unsafe { ptr::write_volatile(self.word(offset), value) }
+  }
+
+  pub fn is_set(&self, offset: usize) -> bool {
+    self.read(offset) != 0
+  }
+
+  pub fn register_block(&self) -> &RegisterBlock {
+    // The registers are made of cells, so they can be shared with the emulator writes
+    unsafe { &*(self.0.get() as *const RegisterBlock) }
+  }
+
+  fn word(&self, offset: usize) -> *mut u32 {
+    assert!(offset % 4 == 0 && offset < LENGTH);
+    unsafe { (self.0.get() as *mut u32).add(offset / 4) }
+  }
+}
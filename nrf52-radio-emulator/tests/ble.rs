@@ -0,0 +1,81 @@
+use nrf52_radio::Radio;
+use nrf52_radio::ble::{Advertiser, AdvertisingData, ADVERTISING_BUFFER_LENGTH, FLAG_LE_GENERAL_DISCOVERABLE};
+use nrf52_radio::dma_buffer::DmaBuffer;
+
+use nrf52_radio_emulator::Emulator;
+
+/// Steps before giving up on the advertisement
+const MAX_STEPS: usize = 64;
+
+/// Advertiser address 66:55:44:33:22:11, least significant byte first
+const ADDRESS: [u8; 6] = [0x11, 0x22, 0x33, 0x44, 0x55, 0x66];
+
+#[test]
+fn advertising_setup() {
+  let emulator = Emulator::new();
+  let mut buffer = [0u8; ADVERTISING_BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  let _advertiser = Advertiser::new(radio, unsafe { DmaBuffer::new_unchecked(&mut buffer) }).unwrap();
+
+  let registers = emulator.registers();
+  // BLE 1 Mbit/s, little endian and whitened
+  assert_eq!(registers.mode.read().bits(), 3);
+  assert_eq!(registers.pcnf1.read().bits() >> 24 & 0x03, 0x02);
+  // Access address 8e89bed6: 3 bytes of base address and the prefix, on logical address 0
+  assert_eq!(registers.pcnf1.read().bits() >> 16 & 0x07, 3);
+  assert_eq!(registers.base0.read().bits(), 0x89be_d600);
+  assert_eq!(registers.prefix0.read().bits() & 0xff, 0x8e);
+  assert_eq!(registers.txaddress.read().bits(), 0);
+  assert_eq!(registers.rxaddresses.read().bits(), 0x01);
+  // 24 bits CRC over the PDU only, x^24 + x^10 + x^9 + x^6 + x^4 + x^3 + x + 1, starting at 555555
+  assert_eq!(registers.crccnf.read().bits(), 0x103);
+  assert_eq!(registers.crcpoly.read().bits(), 0x00_065b);
+  assert_eq!(registers.crcinit.read().bits(), 0x55_5555);
+}
+
+#[test]
+fn advertise_on_every_channel() {
+  let emulator = Emulator::new();
+  let mut buffer = [0u8; ADVERTISING_BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  let mut advertiser = Advertiser::new(radio, unsafe { DmaBuffer::new_unchecked(&mut buffer) }).unwrap();
+  let mut data = AdvertisingData::default();
+  data.add_flags(FLAG_LE_GENERAL_DISCOVERABLE).unwrap();
+  advertiser.set_advertisement(ADDRESS, true, &data).unwrap();
+
+  // Frequency, whitening IV and PDU of every advertisement on air
+  let mut sent = Vec::new();
+  let mut done = false;
+  for _ in 0..MAX_STEPS {
+    match advertiser.advertise() {
+      Ok(()) => {
+        done = true;
+        break;
+      },
+      Err(nb::Error::WouldBlock) => emulator.step().unwrap(),
+      Err(nb::Error::Other(error)) => panic!("{:?}", error),
+    }
+    if let Some(packet) = emulator.take_transmitted() {
+      let whitening_iv = emulator.registers().datawhiteiv.read().bits();
+      sent.push((packet.frequency.unwrap().mhz(), whitening_iv, packet.data().to_vec()));
+    }
+  }
+  assert!(done);
+
+  // ADV_NONCONN_IND with a random address, the address least significant byte first and the flags
+  let pdu = vec![0x42, 9, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x02, 0x01, 0x02];
+  assert_eq!(sent, vec![
+    (2402, 0x40 | 37, pdu.clone()),
+    (2426, 0x40 | 38, pdu.clone()),
+    (2480, 0x40 | 39, pdu),
+  ]);
+
+  // A public address clears TxAdd
+  advertiser.set_advertisement(ADDRESS, false, &data).unwrap();
+  let radio = advertiser.free();
+  assert_eq!(radio.get_buffer()[0], 0x02);
+}
@@ -0,0 +1,397 @@
+use core::cell::Cell;
+
+use nrf52_radio::Radio;
+use nrf52_radio::dma_buffer::DmaBuffer;
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::logical_address::LogicalAddress;
+use nrf52_radio::rx_addresses::RxAddresses;
+use nrf52_radio::states::State;
+
+use nrf52_esb::{Esb, Error, RxConfig, TxConfig};
+use nrf52_esb::hopping::{ChannelHopping, HoppingPolicy};
+use nrf52_esb::protocol::{Protocol, buffer_length};
+use nrf52_esb::timeout::{Clock, Timeouts};
+
+use nrf52_radio_emulator::{AirPacket, Emulator};
+
+const BUFFER_LENGTH: usize = buffer_length(32);
+
+/// Steps before giving up on a transaction
+const MAX_STEPS: usize = 64;
+
+/// Poll `f` until it finishes, stepping the emulator in between
+fn run<T, E: core::fmt::Debug>(emulator: &Emulator, mut f: impl FnMut() -> nb::Result<T, E>) -> T {
+  for _ in 0..MAX_STEPS {
+    match f() {
+      Ok(value) => return value,
+      Err(nb::Error::WouldBlock) => emulator.step().unwrap(),
+      Err(nb::Error::Other(error)) => panic!("{:?}", error),
+    }
+  }
+  panic!("Not finished after {} steps", MAX_STEPS);
+}
+
+/// Poll `f` until it fails, stepping the emulator in between
+fn run_until_error<T: core::fmt::Debug, E>(emulator: &Emulator, mut f: impl FnMut() -> nb::Result<T, E>) -> E {
+  for _ in 0..MAX_STEPS {
+    match f() {
+      Ok(value) => panic!("Unexpected {:?}", value),
+      Err(nb::Error::WouldBlock) => emulator.step().unwrap(),
+      Err(nb::Error::Other(error)) => return error,
+    }
+  }
+  panic!("Not finished after {} steps", MAX_STEPS);
+}
+
+/// Poll `f` until the radio transmits, stepping the emulator in between
+fn run_until_transmitted<T: core::fmt::Debug, E: core::fmt::Debug>(
+  emulator: &Emulator,
+  mut f: impl FnMut() -> nb::Result<T, E>,
+) -> AirPacket {
+  for _ in 0..MAX_STEPS {
+    if let Some(packet) = emulator.take_transmitted() {
+      return packet;
+    }
+    match f() {
+      Err(nb::Error::WouldBlock) => emulator.step().unwrap(),
+      result => panic!("Unexpected {:?}", result),
+    }
+  }
+  panic!("Nothing transmitted after {} steps", MAX_STEPS);
+}
+
+/// Time that only moves when the test says so
+struct TestClock(Cell<u32>);
+
+impl Clock for TestClock {
+  fn now(&self) -> u32 {
+    self.0.get()
+  }
+}
+
+impl TestClock {
+  fn advance(&self, us: u32) {
+    self.0.set(self.0.get().wrapping_add(us));
+  }
+}
+
+#[test]
+fn receive_and_acknowledge() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+  esb.set_crc_16bits();
+  esb.set_ack_payload(&[7]).unwrap();
+
+  esb.start_rx(RxConfig::default()).unwrap();
+  // PID 1, with acknowledgement
+  emulator.receive(AirPacket::new(LogicalAddress::Of2, &[2, 0x02, 0xaa, 0xbb]).with_crc(true, 0x1234));
+  run(&emulator, || esb.wait_rx());
+
+  let packet = esb.get_last_received_packet().unwrap();
+  assert_eq!(packet.length, 2);
+  assert_eq!(packet.pid, 1);
+  assert!(!packet.no_ack);
+  assert_eq!(packet.address, LogicalAddress::Of2);
+  assert_eq!(packet.crc, 0x1234);
+  assert!(packet.crc_ok);
+  assert_eq!(&esb.get_rx_buffer()[..4], &[2, 0x02, 0xaa, 0xbb]);
+
+  // The acknowledgement carries the same PID and the payload
+  let ack = emulator.take_transmitted().unwrap();
+  assert_eq!(ack.address, LogicalAddress::Of2);
+  assert_eq!(ack.data(), &[1, 0x02, 7]);
+}
+
+#[test]
+fn receive_skips_crc_errors() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of0, &[1, 0, 0xff]).with_crc(false, 0));
+  for _ in 0..MAX_STEPS {
+    assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+    emulator.step().unwrap();
+  }
+  assert!(emulator.take_transmitted().is_none());
+
+  emulator.receive(AirPacket::new(LogicalAddress::Of0, &[1, 0x01, 0x55]));
+  run(&emulator, || esb.wait_rx());
+  let packet = esb.get_last_received_packet().unwrap();
+  assert!(packet.no_ack);
+  // No acknowledgement requested
+  for _ in 0..MAX_STEPS {
+    emulator.step().unwrap();
+  }
+  assert!(emulator.take_transmitted().is_none());
+}
+
+#[test]
+fn ack_payload_survives_packets_without_acknowledgement() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+  esb.set_ack_payload(&[7, 8]).unwrap();
+
+  // PID 0, no acknowledgement
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of1, &[1, 0x01, 0x55]));
+  run(&emulator, || esb.wait_rx());
+  assert!(esb.get_last_received_packet().unwrap().no_ack);
+  for _ in 0..MAX_STEPS {
+    emulator.step().unwrap();
+  }
+  assert!(emulator.take_transmitted().is_none());
+
+  // The acknowledgement of the next packet still carries the payload
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of1, &[1, 0x02, 0x66]));
+  run(&emulator, || esb.wait_rx());
+  let ack = emulator.take_transmitted().unwrap();
+  assert_eq!(ack.data(), &[2, 0x02, 7, 8]);
+
+  // And only that one
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of1, &[1, 0x04, 0x77]));
+  run(&emulator, || esb.wait_rx());
+  let ack = emulator.take_transmitted().unwrap();
+  assert_eq!(ack.data(), &[0, 0x04]);
+}
+
+#[test]
+fn transmit_with_acknowledgement() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+  esb.set_tx_payload(&[1, 2, 3]).unwrap();
+
+  esb.start_tx(TxConfig::new(LogicalAddress::Of1)).unwrap();
+  let mut transmitted = None;
+  run(&emulator, || {
+    // The PRX answers as soon as the packet is on air
+    if let Some(packet) = emulator.take_transmitted() {
+      emulator.receive(AirPacket::new(LogicalAddress::Of1, &[2, 0, 9, 8]));
+      transmitted = Some(packet);
+    }
+    esb.wait_tx()
+  });
+
+  let packet = transmitted.unwrap();
+  assert_eq!(packet.address, LogicalAddress::Of1);
+  assert_eq!(packet.data(), &[3, 0, 1, 2, 3]);
+
+  let ack = esb.get_last_ack_packet().unwrap();
+  assert_eq!(ack.length, 2);
+  assert_eq!(ack.address, LogicalAddress::Of1);
+  assert_eq!(&esb.get_rx_buffer()[..4], &[2, 0, 9, 8]);
+}
+
+#[test]
+fn transmit_again_after_ack_timeout() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+
+  let channels = [Frequency::from_2400mhz_channel(78).unwrap(), Frequency::from_2400mhz_channel(2).unwrap()];
+  let clock = TestClock(Cell::new(0));
+  esb.set_channel_hopping(Some(ChannelHopping::new(&channels, HoppingPolicy::HopOnFailure(1)).unwrap())).unwrap();
+  esb.set_timeouts(Some(Timeouts::new(&clock).with_ack(500))).unwrap();
+  esb.set_tx_payload(&[1, 2, 3]).unwrap();
+
+  esb.start_tx(TxConfig::new(LogicalAddress::Of1).with_retries(1)).unwrap();
+  let packet = run_until_transmitted(&emulator, || esb.wait_tx());
+  assert_eq!(packet.frequency, Some(channels[0]));
+
+  // Nothing happens while the ACK window is open
+  for _ in 0..MAX_STEPS {
+    assert_eq!(esb.wait_tx(), Err(nb::Error::WouldBlock));
+    emulator.step().unwrap();
+  }
+  assert!(emulator.take_transmitted().is_none());
+
+  // The same packet again, on the next channel
+  clock.advance(500);
+  let packet = run_until_transmitted(&emulator, || esb.wait_tx());
+  assert_eq!(packet.frequency, Some(channels[1]));
+  assert_eq!(packet.data(), &[3, 0, 1, 2, 3]);
+
+  // No retries left once the ACK window is over
+  for _ in 0..MAX_STEPS {
+    assert_eq!(esb.wait_tx(), Err(nb::Error::WouldBlock));
+    emulator.step().unwrap();
+  }
+  clock.advance(500);
+  assert_eq!(run_until_error(&emulator, || esb.wait_tx()), Error::Timeout);
+  assert!(emulator.take_transmitted().is_none());
+  assert!(matches!(emulator.state(), State::Disabled));
+  assert!(esb.get_last_ack_packet().is_none());
+  assert_eq!(esb.get_channel(), Some(channels[0]));
+
+  // Both buffers are back, with the packet still in the tx one
+  assert_eq!(&esb.get_tx_buffer()[..5], &[3, 0, 1, 2, 3]);
+  esb.start_tx(TxConfig::new(LogicalAddress::Of1)).unwrap();
+  let packet = run_until_transmitted(&emulator, || esb.wait_tx());
+  assert_eq!(packet.frequency, Some(channels[0]));
+  emulator.receive(AirPacket::new(LogicalAddress::Of1, &[0, 0]));
+  run(&emulator, || esb.wait_tx());
+  assert!(esb.get_last_ack_packet().is_some());
+}
+
+#[test]
+fn receive_scans_channels() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+
+  let channels = [Frequency::from_2400mhz_channel(78).unwrap(), Frequency::from_2400mhz_channel(2).unwrap()];
+  let clock = TestClock(Cell::new(0));
+  esb.set_channel_hopping(Some(ChannelHopping::new(&channels, HoppingPolicy::Scan(1)).unwrap())).unwrap();
+  esb.set_timeouts(Some(Timeouts::new(&clock).with_rx_window(1000))).unwrap();
+
+  esb.start_rx(RxConfig::default().with_skip_ack(true).with_retries(1)).unwrap();
+  for _ in 0..MAX_STEPS {
+    assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+    emulator.step().unwrap();
+  }
+  assert!(matches!(emulator.state(), State::Rx));
+
+  // The window ends, and the radio restarts on the next channel
+  clock.advance(1000);
+  assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+  assert_eq!(esb.get_channel(), Some(channels[1]));
+  loop {
+    assert_eq!(esb.wait_rx(), Err(nb::Error::WouldBlock));
+    emulator.step().unwrap();
+    if let State::Rx = emulator.state() {
+      break;
+    }
+  }
+  emulator.receive(AirPacket::new(LogicalAddress::Of0, &[1, 0x01, 0x55]).with_frequency(channels[1]));
+  run(&emulator, || esb.wait_rx());
+  assert_eq!(&esb.get_rx_buffer()[..3], &[1, 0x01, 0x55]);
+
+  // A reception resets the retries, and the last window ends with a timeout
+  esb.start_rx(RxConfig::default().with_retries(0)).unwrap();
+  clock.advance(1000);
+  assert_eq!(run_until_error(&emulator, || esb.wait_rx()), Error::Timeout);
+  assert!(matches!(emulator.state(), State::Disabled));
+  assert_eq!(esb.get_channel(), Some(channels[0]));
+  esb.start_rx(RxConfig::default()).unwrap();
+}
+
+#[test]
+fn reset_after_an_error() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+
+  // The radio ramps up for TX behind the back of the driver, which can not enable RX then
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.registers().tasks_txen.write(|w| w.tasks_txen().set_bit());
+  emulator.step().unwrap();
+  assert!(matches!(esb.wait_rx(), Err(nb::Error::Other(Error::RadioError(_)))));
+
+  // Nothing but a reset gets it out of the error
+  assert_eq!(esb.abort(), Err(nb::Error::Other(Error::ResetRequired)));
+  assert_eq!(esb.start_rx(RxConfig::default()), Err(Error::StandbyRequired));
+  run(&emulator, || esb.reset());
+  assert!(matches!(emulator.state(), State::Disabled));
+
+  esb.start_rx(RxConfig::default()).unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of0, &[1, 0x01, 0x55]));
+  run(&emulator, || esb.wait_rx());
+  assert_eq!(&esb.get_rx_buffer()[..3], &[1, 0x01, 0x55]);
+
+  let (_, rx_buffer, tx_buffer) = esb.free();
+  assert!(rx_buffer.is_some());
+  assert!(tx_buffer.is_some());
+}
+
+#[test]
+fn abort_in_the_middle_of_a_transaction() {
+  let emulator = Emulator::new();
+  let mut ram = [0u8; 2 * BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(ram.as_mut_ptr(), ram.len()) };
+  let (buffer1, buffer2) = ram.split_at_mut(BUFFER_LENGTH);
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio.set_rx_addresses(RxAddresses::all());
+  let mut esb = Esb::new(radio, Protocol::dynamic_payload_length(32),
+                         unsafe { DmaBuffer::new_unchecked(buffer1) },
+                         unsafe { DmaBuffer::new_unchecked(buffer2) }).unwrap();
+  esb.set_tx_payload(&[1, 2, 3]).unwrap();
+
+  // Waiting for the acknowledgement, with the packet and the ACK buffer given to the radio
+  esb.start_tx(TxConfig::new(LogicalAddress::Of1)).unwrap();
+  run_until_transmitted(&emulator, || esb.wait_tx());
+  emulator.step().unwrap();
+  assert!(!matches!(emulator.state(), State::Disabled));
+
+  run(&emulator, || esb.abort());
+  assert!(matches!(emulator.state(), State::Disabled));
+  assert_eq!(esb.wait_tx(), Err(nb::Error::Other(Error::ReceiveNotStarted)));
+
+  // Both buffers are back, with the packet still in the tx one
+  assert_eq!(&esb.get_tx_buffer()[..5], &[3, 0, 1, 2, 3]);
+  esb.start_tx(TxConfig::new(LogicalAddress::Of1)).unwrap();
+  let packet = run_until_transmitted(&emulator, || esb.wait_tx());
+  assert_eq!(packet.data(), &[3, 0, 1, 2, 3]);
+  emulator.receive(AirPacket::new(LogicalAddress::Of1, &[0, 0]));
+  run(&emulator, || esb.wait_tx());
+  assert!(esb.get_last_ack_packet().is_some());
+
+  let (_, rx_buffer, tx_buffer) = esb.free();
+  assert!(rx_buffer.is_some());
+  assert!(tx_buffer.is_some());
+}
@@ -0,0 +1,177 @@
+use nrf52_radio::{Error, Radio};
+use nrf52_radio::dma_buffer::DmaBuffer;
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::logical_address::LogicalAddress;
+use nrf52_radio::packet_config::{PacketConfig, S1Length};
+use nrf52_radio::rx_addresses::RxAddresses;
+use nrf52_radio::shortcuts::Shortcuts;
+use nrf52_radio::states::State;
+use nrf52_radio::typestate::{self, Disabled};
+
+use nrf52_radio_emulator::{AirPacket, Emulator};
+
+/// LENGTH and S1 before up to 32 bytes of payload, as in ESB
+const BUFFER_LENGTH: usize = 2 + 32;
+
+fn packet_config() -> PacketConfig {
+  PacketConfig::default()
+      .with_length_bits(6)
+      .with_s0_byte_included(false)
+      .with_s1_len(S1Length::Of3Bits)
+      .with_max_bytes(32)
+      .with_static_bytes(0)
+}
+
+fn state_name(state: State) -> &'static str {
+  match state {
+    State::Disabled => "Disabled",
+    State::RxRumpUp => "RxRumpUp",
+    State::RxIdle => "RxIdle",
+    State::Rx => "Rx",
+    State::RxDisable => "RxDisable",
+    State::TxRumpUp => "TxRumpUp",
+    State::TxIdle => "TxIdle",
+    State::Tx => "Tx",
+    State::TxDisable => "TxDisable",
+    State::Unknown(_) => "Unknown",
+  }
+}
+
+#[test]
+fn transmit_with_shortcuts() {
+  let emulator = Emulator::new();
+  let mut buffer = [0u8; BUFFER_LENGTH];
+  buffer[..5].copy_from_slice(&[3, 0x02, 1, 2, 3]);
+  unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+  let mut radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio
+      .set_packet_config(packet_config())
+      .set_frequency(Frequency::from_2400mhz_channel(10).unwrap())
+      .set_tx_address(LogicalAddress::Of3)
+      .set_shortcuts(Shortcuts::READY_START | Shortcuts::END_DISABLE);
+  radio.swap_buffer(&mut Some(unsafe { DmaBuffer::new_unchecked(&mut buffer) })).unwrap();
+  radio.enable_tx().unwrap();
+
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "TxRumpUp");
+  // READY_START may start the transfer at any time
+  let mut other = None;
+  assert_eq!(radio.swap_buffer(&mut other), Err(Error::WrongState));
+  assert!(other.is_none());
+  // READY_START
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "Tx");
+  assert!(emulator.take_transmitted().is_none());
+  // END_DISABLE
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "TxDisable");
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "Disabled");
+  assert!(radio.wait_disabled().is_ok());
+
+  let packet = emulator.take_transmitted().unwrap();
+  assert_eq!(packet.address, LogicalAddress::Of3);
+  assert_eq!(packet.frequency, Some(Frequency::from_2400mhz_channel(10).unwrap()));
+  assert_eq!(packet.data(), &[3, 0x02, 1, 2, 3]);
+}
+
+#[test]
+fn receive_packet() {
+  let emulator = Emulator::new();
+  let mut buffer = [0u8; BUFFER_LENGTH];
+  unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+  let mut radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio
+      .set_packet_config(packet_config())
+      .set_frequency(Frequency::from_2400mhz_channel(10).unwrap())
+      .set_rx_addresses(RxAddresses::ADDR1 | RxAddresses::ADDR2)
+      .set_shortcuts(Shortcuts::ADDRESS_RSSISTART);
+  radio.swap_buffer(&mut Some(unsafe { DmaBuffer::new_unchecked(&mut buffer) })).unwrap();
+  emulator.set_rssi(60);
+
+  radio.enable_rx().unwrap();
+  emulator.step().unwrap();
+  emulator.step().unwrap();
+  assert!(radio.wait_idle().is_ok());
+  radio.start().unwrap();
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "Rx");
+
+  // Another channel, and an address that is not enabled
+  let other_channel = AirPacket::new(LogicalAddress::Of1, &[1, 0, 0xff])
+      .with_frequency(Frequency::from_2400mhz_channel(11).unwrap());
+  emulator.receive(other_channel);
+  emulator.step().unwrap();
+  emulator.receive(AirPacket::new(LogicalAddress::Of3, &[1, 0, 0xff]));
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "Rx");
+  assert!(radio.wait_end_or_disable().is_err());
+
+  let packet = AirPacket::new(LogicalAddress::Of2, &[2, 0x03, 0xaa, 0xbb])
+      .with_frequency(Frequency::from_2400mhz_channel(10).unwrap())
+      .with_crc(false, 0x1234);
+  emulator.receive(packet);
+  emulator.step().unwrap();
+  assert_eq!(state_name(emulator.state()), "RxIdle");
+  assert!(radio.wait_end_or_disable().is_ok());
+  assert_eq!(radio.get_received_address(), LogicalAddress::Of2);
+  assert_eq!(radio.get_received_crc(), 0x1234);
+  assert!(!radio.is_crc_ok());
+  // ADDRESS_RSSISTART
+  assert_eq!(radio.get_rssi_sample(), 60);
+  assert_eq!(&radio.get_buffer()[..4], &[2, 0x03, 0xaa, 0xbb]);
+}
+
+#[test]
+fn typestate_transmit() {
+  let emulator = Emulator::new();
+  let mut buffer = [0u8; BUFFER_LENGTH];
+  buffer[..3].copy_from_slice(&[1, 0x00, 0x42]);
+  unsafe { emulator.map_dma_region(buffer.as_mut_ptr(), buffer.len()) };
+
+  let radio = unsafe { Radio::<(), (), _>::new_unchecked(emulator.registers()) };
+  radio
+      .set_packet_config(packet_config())
+      .set_tx_address(LogicalAddress::Of0);
+  let mut radio: typestate::Radio<(), (), Disabled, _> = match typestate::Radio::from_dynamic(radio) {
+    Ok(radio) => radio,
+    Err(_) => panic!("the radio is not disabled"),
+  };
+  radio.swap_buffer(&mut Some(unsafe { DmaBuffer::new_unchecked(&mut buffer) })).unwrap();
+
+  let mut ramping_up = match radio.enable_tx() {
+    Ok(ramping_up) => ramping_up,
+    Err((_, error)) => panic!("{:?}", error),
+  };
+  let idle = loop {
+    emulator.step().unwrap();
+    match ramping_up.try_idle() {
+      Ok(idle) => break idle,
+      Err(radio) => ramping_up = radio,
+    }
+  };
+  let mut transmitting = match idle.start() {
+    Ok(transmitting) => transmitting,
+    Err((_, error)) => panic!("{:?}", error),
+  };
+  let idle = loop {
+    emulator.step().unwrap();
+    match transmitting.try_end() {
+      Ok(idle) => break idle,
+      Err(radio) => transmitting = radio,
+    }
+  };
+  assert_eq!(emulator.take_transmitted().unwrap().data(), &[1, 0x00, 0x42]);
+
+  let mut disabling = idle.disable();
+  loop {
+    emulator.step().unwrap();
+    match disabling.try_disabled() {
+      Ok(_) => break,
+      Err(radio) => disabling = radio,
+    }
+  }
+  assert_eq!(state_name(emulator.state()), "Disabled");
+}
@@ -1,4 +1,7 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BaseAddresses {
+  /// Below the 2 to 4 bytes documented for BALEN, only meant for promiscuous sniffing
+  OneByte(u8, u8),
   TwoBytes(u16, u16),
   ThreeBytes(u32, u32),
   FourBytes(u32, u32),
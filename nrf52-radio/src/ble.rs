@@ -0,0 +1,308 @@
+/*!
+
+BLE legacy advertising
+
+Broadcasts non-connectable undirected advertisements (ADV_NONCONN_IND) on the three
+advertising channels, so any scanner (i.e. a phone) can read them without pairing.
+
+See [Bluetooth Core Specification 5.0](https://www.bluetooth.com/specifications/bluetooth-core-specification/):
+Vol 6, Part B, 2.3 Advertising channel PDU
+
+*/
+
+use core::ops::Deref;
+
+use crate::hal::target::{RADIO, radio::RegisterBlock};
+use crate::values_as_enum;
+use crate::radio::{Radio, Error as RadioError};
+use crate::mode::Mode;
+use crate::frequency::Frequency;
+use crate::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+use crate::shortcuts::Shortcuts;
+use crate::dma_buffer::DmaBuffer;
+
+pub type Result<A> = core::result::Result<A, Error>;
+pub type AsyncResult<A> = nb::Result<A, Error>;
+
+/// Access address used by all the advertising channel packets
+pub const ADVERTISING_ACCESS_ADDRESS: u32 = 0x8e89bed6;
+
+/// CRC initial value for the advertising channel packets
+pub const ADVERTISING_CRC_INIT: u32 = 0x555555;
+
+/// Maximum length of the advertising data
+pub const MAX_ADVERTISING_DATA_LENGTH: usize = 31;
+
+/// Size of the buffer required by the advertiser: header, advertiser address and data
+pub const ADVERTISING_BUFFER_LENGTH: usize = 2 + 6 + MAX_ADVERTISING_DATA_LENGTH;
+
+const ADV_NONCONN_IND: u8 = 0x02;
+const TX_ADD_RANDOM: u8 = 0x40;
+
+/// AD type for flags
+pub const AD_TYPE_FLAGS: u8 = 0x01;
+/// AD type for the complete local name
+pub const AD_TYPE_COMPLETE_LOCAL_NAME: u8 = 0x09;
+/// AD type for manufacturer specific data
+pub const AD_TYPE_MANUFACTURER_SPECIFIC_DATA: u8 = 0xff;
+
+/// LE General Discoverable Mode
+pub const FLAG_LE_GENERAL_DISCOVERABLE: u8 = 0x02;
+/// BR/EDR Not Supported
+pub const FLAG_BR_EDR_NOT_SUPPORTED: u8 = 0x04;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// Buffer smaller than `ADVERTISING_BUFFER_LENGTH`
+  BufferTooSmall,
+
+  /// The advertising data does not fit in `MAX_ADVERTISING_DATA_LENGTH` bytes
+  DataTooLong,
+
+  /// The advertisement can not be changed while it is being sent
+  Busy,
+
+  /// Unexpected error from the radio
+  RadioError(RadioError),
+}
+
+values_as_enum!(
+  AdvertisingChannel, "BLE advertising channels",
+  (37, Ch37, "2402 MHz"),
+  (38, Ch38, "2426 MHz"),
+  (39, Ch39, "2480 MHz")
+);
+
+impl AdvertisingChannel {
+  pub fn frequency(&self) -> Frequency {
+    match self {
+      AdvertisingChannel::Ch37 => Frequency::from_valid_2400mhz_channel(2),
+      AdvertisingChannel::Ch38 => Frequency::from_valid_2400mhz_channel(26),
+      AdvertisingChannel::Ch39 => Frequency::from_valid_2400mhz_channel(80),
+    }
+  }
+
+  /// The channel index is used as the data whitening initial value
+  pub fn whitening_iv(&self) -> u8 {
+    self.value() as u8
+  }
+
+  fn next(&self) -> Option<AdvertisingChannel> {
+    match self {
+      AdvertisingChannel::Ch37 => Some(AdvertisingChannel::Ch38),
+      AdvertisingChannel::Ch38 => Some(AdvertisingChannel::Ch39),
+      AdvertisingChannel::Ch39 => None,
+    }
+  }
+}
+
+/// Advertising data as a sequence of AD structures (length, type, value)
+pub struct AdvertisingData {
+  data: [u8; MAX_ADVERTISING_DATA_LENGTH],
+  length: usize,
+}
+
+impl Default for AdvertisingData {
+  fn default() -> Self {
+    AdvertisingData {
+      data: [0; MAX_ADVERTISING_DATA_LENGTH],
+      length: 0,
+    }
+  }
+}
+
+impl AdvertisingData {
+  pub fn add(&mut self, ad_type: u8, value: &[u8]) -> Result<&mut Self> {
+    self.add_parts(ad_type, &[], value)
+  }
+
+  pub fn add_flags(&mut self, flags: u8) -> Result<&mut Self> {
+    self.add(AD_TYPE_FLAGS, &[flags])
+  }
+
+  pub fn add_complete_local_name(&mut self, name: &str) -> Result<&mut Self> {
+    self.add(AD_TYPE_COMPLETE_LOCAL_NAME, name.as_bytes())
+  }
+
+  /// Manufacturer specific data starting with the company identifier
+  pub fn add_manufacturer_specific_data(&mut self, company_id: u16, data: &[u8]) -> Result<&mut Self> {
+    self.add_parts(AD_TYPE_MANUFACTURER_SPECIFIC_DATA, &company_id.to_le_bytes(), data)
+  }
+
+  pub fn clear(&mut self) {
+    self.length = 0;
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    &self.data[..self.length]
+  }
+
+  fn add_parts(&mut self, ad_type: u8, prefix: &[u8], value: &[u8]) -> Result<&mut Self> {
+    let value_length = prefix.len() + value.len();
+    let start = self.length;
+    let end = start + 2 + value_length;
+    if end > MAX_ADVERTISING_DATA_LENGTH {
+      return Err(Error::DataTooLong);
+    }
+    self.data[start] = (1 + value_length) as u8;
+    self.data[start + 1] = ad_type;
+    self.data[start + 2..start + 2 + prefix.len()].copy_from_slice(prefix);
+    self.data[start + 2 + prefix.len()..end].copy_from_slice(value);
+    self.length = end;
+    Ok(self)
+  }
+}
+
+pub struct Advertiser<'a, LFOSC, LFSTAT, R = RADIO> {
+  radio: Radio<'a, LFOSC, LFSTAT, R>,
+  channel: Option<AdvertisingChannel>,
+}
+
+impl<'a, LFOSC, LFSTAT, R> Advertiser<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  /// The radio needs to be disabled
+  pub fn new(mut radio: Radio<'a, LFOSC, LFSTAT, R>, buffer: DmaBuffer<'a>) -> Result<Self> {
+    if buffer.len() < ADVERTISING_BUFFER_LENGTH {
+      return Err(Error::BufferTooSmall);
+    }
+    Self::setup(&radio);
+    radio.swap_buffer(&mut Some(buffer)).map_err(Error::RadioError)?;
+    Ok(Advertiser {
+      radio,
+      channel: None,
+    })
+  }
+
+  fn setup(radio: &Radio<'a, LFOSC, LFSTAT, R>) {
+    let pcfn = PacketConfig::default()
+        .with_length_bits(8)
+        .with_s0_byte_included(true)
+        .with_s1_len(S1Length::Of0Bits)
+        .with_s1_include_in_ram(S1IncludeInRam::Automatic)
+        .with_preamble_len(PreambleLength::Of8Bits)
+        .with_max_bytes((ADVERTISING_BUFFER_LENGTH - 2) as u8)
+        .with_static_bytes(0)
+        .with_endianess(Endianess::LittleEndian)
+        .with_whitening_enabled(true);
+    radio
+        .set_mode(Mode::Ble1Mbit)
+        .set_packet_config(pcfn)
+        .set_crc_ble(ADVERTISING_CRC_INIT)
+        .set_ble_access_address(ADVERTISING_ACCESS_ADDRESS)
+        .set_shortcuts(Shortcuts::READY_START | Shortcuts::END_DISABLE);
+  }
+
+  /// Set the advertiser address (least significant byte first) and the advertising data
+  pub fn set_advertisement(&mut self, address: [u8; 6], random_address: bool, data: &AdvertisingData) -> Result<()> {
+    if self.channel.is_some() {
+      return Err(Error::Busy);
+    }
+    let data = data.as_bytes();
+    let buffer = self.radio.get_buffer_mut();
+    buffer[0] = ADV_NONCONN_IND | if random_address { TX_ADD_RANDOM } else { 0 };
+    buffer[1] = (address.len() + data.len()) as u8;
+    buffer[2..8].copy_from_slice(&address);
+    buffer[8..8 + data.len()].copy_from_slice(data);
+    Ok(())
+  }
+
+  /// Send the advertisement once on every advertising channel.
+  /// It needs to be polled until the advertisement has been sent on the last channel.
+  pub fn advertise(&mut self) -> AsyncResult<()> {
+    match self.channel {
+      None => {
+        self.start(AdvertisingChannel::Ch37)?;
+        Err(nb::Error::WouldBlock)
+      },
+      Some(channel) => match self.radio.wait_disabled() {
+        Ok(()) => match channel.next() {
+          Some(next_channel) => {
+            self.start(next_channel)?;
+            Err(nb::Error::WouldBlock)
+          },
+          None => {
+            self.channel = None;
+            Ok(())
+          },
+        },
+        Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+        Err(nb::Error::Other(error)) => {
+          self.channel = None;
+          Err(nb::Error::Other(Error::RadioError(error)))
+        },
+      },
+    }
+  }
+
+  fn start(&mut self, channel: AdvertisingChannel) -> Result<()> {
+    self.radio
+        .set_frequency(channel.frequency())
+        .set_whitening_iv(channel.whitening_iv());
+    match self.radio.enable_tx() {
+      Ok(()) => {
+        self.channel = Some(channel);
+        Ok(())
+      },
+      Err(error) => {
+        self.channel = None;
+        Err(Error::RadioError(error))
+      },
+    }
+  }
+
+  /// Give back the radio, once the advertisement has been completely sent
+  pub fn free(self) -> Radio<'a, LFOSC, LFSTAT, R> {
+    self.radio
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ad_structures() {
+    let mut data = AdvertisingData::default();
+    data.add_flags(FLAG_LE_GENERAL_DISCOVERABLE | FLAG_BR_EDR_NOT_SUPPORTED).unwrap()
+        .add_complete_local_name("MDP").unwrap()
+        .add_manufacturer_specific_data(0x0059, &[0xaa, 0xbb]).unwrap();
+    assert_eq!(data.as_bytes(), &[
+      0x02, 0x01, 0x06,
+      0x04, 0x09, b'M', b'D', b'P',
+      0x05, 0xff, 0x59, 0x00, 0xaa, 0xbb,
+    ]);
+
+    data.clear();
+    assert_eq!(data.as_bytes(), &[]);
+  }
+
+  #[test]
+  fn data_up_to_the_limit() {
+    let mut data = AdvertisingData::default();
+    // 2 bytes of length and type, and 29 bytes of value
+    data.add(AD_TYPE_MANUFACTURER_SPECIFIC_DATA, &[0x11; 29]).unwrap();
+    assert_eq!(data.as_bytes().len(), MAX_ADVERTISING_DATA_LENGTH);
+    assert_eq!(&data.as_bytes()[..2], &[30, AD_TYPE_MANUFACTURER_SPECIFIC_DATA]);
+  }
+
+  #[test]
+  fn data_too_long() {
+    let mut data = AdvertisingData::default();
+    assert_eq!(data.add(AD_TYPE_COMPLETE_LOCAL_NAME, &[b'x'; 30]).err(), Some(Error::DataTooLong));
+    assert_eq!(data.as_bytes(), &[]);
+
+    // A structure that does not fit leaves the previous ones untouched
+    data.add_flags(FLAG_LE_GENERAL_DISCOVERABLE).unwrap();
+    assert_eq!(data.add_manufacturer_specific_data(0x0059, &[0; 26]).err(), Some(Error::DataTooLong));
+    assert_eq!(data.as_bytes(), &[0x02, 0x01, 0x02]);
+  }
+
+  #[test]
+  fn advertising_channels() {
+    assert_eq!(AdvertisingChannel::Ch37.frequency().mhz(), 2402);
+    assert_eq!(AdvertisingChannel::Ch38.frequency().mhz(), 2426);
+    assert_eq!(AdvertisingChannel::Ch39.frequency().mhz(), 2480);
+    assert_eq!(AdvertisingChannel::Ch37.whitening_iv(), 37);
+    assert_eq!(AdvertisingChannel::Ch38.whitening_iv(), 38);
+    assert_eq!(AdvertisingChannel::Ch39.whitening_iv(), 39);
+  }
+}
@@ -0,0 +1,48 @@
+/*!
+
+Snapshot of the radio configuration
+
+The configuration registers are saved as they are, so restoring a snapshot leaves the radio
+exactly as it was, including any setting without a typed setter. This allows switching the radio
+between different protocols at runtime, i.e. from ESB to BLE advertising and back.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14 Registers
+
+*/
+
+use crate::hal::target::radio::RegisterBlock;
+
+macro_rules! radio_config {
+  ( $( $(#[$attr:meta])* $register:ident ),* ) => {
+    /// Values of the configuration registers, see `Radio::get_config` and `Radio::set_config`
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct RadioConfig {
+      $( $(#[$attr])* $register: u32, )*
+    }
+
+    impl RadioConfig {
+      pub(crate) fn read(radio: &RegisterBlock) -> Self {
+        RadioConfig {
+          $( $(#[$attr])* $register: radio.$register.read().bits(), )*
+        }
+      }
+
+      // Only some of the registers have an unsafe `bits` writer
+      #[allow(unused_unsafe)]
+      pub(crate) fn write(&self, radio: &RegisterBlock) {
+        $(
+          $(#[$attr])*
+          radio.$register.write(|w| unsafe { w.bits(self.$register) });
+        )*
+      }
+    }
+  };
+}
+
+radio_config!(
+  shorts, frequency, txpower, mode, pcnf0, pcnf1, base0, base1, prefix0, prefix1,
+  txaddress, rxaddresses, crccnf, crcpoly, crcinit, tifs, datawhiteiv, modecnf0,
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))] sfd,
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))] edcnt,
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))] ccactrl
+);
@@ -0,0 +1,84 @@
+/*!
+
+Buffers for the packets transferred by EasyDMA
+
+EasyDMA can only access the Data RAM. A buffer anywhere else, i.e. a constant in flash, would be
+silently ignored by the radio, so a `DmaBuffer` can only be created from a buffer in RAM.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf):
+- 6.1.4 EasyDMA
+- 4.2.3 Memory map
+
+*/
+
+use core::ops::{Deref, DerefMut};
+
+use crate::radio::{Error, Result};
+
+/// Start of the Data RAM
+pub const RAM_START: usize = 0x2000_0000;
+
+/// End of the Data RAM (64 KB)
+#[cfg(feature = "nrf52832")]
+pub const RAM_END: usize = 0x2001_0000;
+
+/// End of the Data RAM (128 KB)
+#[cfg(feature = "nrf52833")]
+pub const RAM_END: usize = 0x2002_0000;
+
+/// End of the Data RAM (256 KB)
+#[cfg(feature = "nrf52840")]
+pub const RAM_END: usize = 0x2004_0000;
+
+/// Buffer that EasyDMA can access
+pub struct DmaBuffer<'a> {
+  buffer: &'a mut [u8],
+}
+
+impl<'a> DmaBuffer<'a> {
+  /// Fails with `BufferNotInRam` when the buffer is not completely in the Data RAM
+  pub fn new(buffer: &'a mut [u8]) -> Result<Self> {
+    let start = buffer.as_ptr() as usize;
+    let end = start + buffer.len();
+    if start >= RAM_START && end <= RAM_END {
+      Ok(DmaBuffer { buffer })
+    }
+    else {
+      Err(Error::BufferNotInRam)
+    }
+  }
+
+  /// Create the buffer without checking where it is.
+  ///
+  /// # Safety
+  ///
+  /// The buffer must be in the Data RAM when it is used with the RADIO peripheral.
+  /// It is meant for emulated registers running on the host.
+  pub unsafe fn new_unchecked(buffer: &'a mut [u8]) -> Self {
+    DmaBuffer { buffer }
+  }
+
+  /// Address for the PACKETPTR register
+  pub(crate) fn address(&self) -> u32 {
+    self.buffer.as_ptr() as u32
+  }
+
+  /// Give back the buffer
+  pub fn free(self) -> &'a mut [u8] {
+    self.buffer
+  }
+}
+
+impl<'a> Deref for DmaBuffer<'a> {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    self.buffer
+  }
+}
+
+impl<'a> DerefMut for DmaBuffer<'a> {
+  fn deref_mut(&mut self) -> &mut [u8] {
+    self.buffer
+  }
+}
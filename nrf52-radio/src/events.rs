@@ -0,0 +1,30 @@
+
+bitflags! {
+    /// Radio events, with the same bit positions as in the INTENSET and INTENCLR registers
+    ///
+    /// See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14.4 INTENSET
+    pub struct Events: u32 {
+        const READY = 1 << 0;
+        const ADDRESS = 1 << 1;
+        const PAYLOAD = 1 << 2;
+        const END = 1 << 3;
+        const DISABLED = 1 << 4;
+        const DEVMATCH = 1 << 5;
+        const DEVMISS = 1 << 6;
+        const RSSIEND = 1 << 7;
+        const BCMATCH = 1 << 10;
+        const CRCOK = 1 << 12;
+        const CRCERROR = 1 << 13;
+        const FRAMESTART = 1 << 14;
+        const EDEND = 1 << 15;
+        const EDSTOPPED = 1 << 16;
+        const CCAIDLE = 1 << 17;
+        const CCABUSY = 1 << 18;
+        const CCASTOPPED = 1 << 19;
+        const RATEBOOST = 1 << 20;
+        const TXREADY = 1 << 21;
+        const RXREADY = 1 << 22;
+        const MHRMATCH = 1 << 23;
+        const PHYEND = 1 << 27;
+    }
+}
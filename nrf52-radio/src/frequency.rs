@@ -1,17 +1,143 @@
-/// 6.20.14.10 FREQUENCY
-pub enum Frequency {
-  Default2400MHz(u8),
-  Low2360MHz(u8),
+/*!
+
+Radio channel frequency
+
+The radio covers from 2360 MHz to 2500 MHz in steps of 1 MHz, using two maps of 101 channels:
+the default one from 2400 MHz and the low one from 2360 MHz.
+
+Besides the channels of each map, a frequency can be built from the absolute MHz,
+from the RF_CH register of the nRF24L01+, or from a BLE channel index.
+It can only be built through these constructors, so the channel is always within its map.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14.10 FREQUENCY
+
+*/
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+/// Highest channel of a map
+pub const MAX_CHANNEL: u8 = 100;
+
+const DEFAULT_BASE_MHZ: u16 = 2400;
+const LOW_BASE_MHZ: u16 = 2360;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The channel is above `MAX_CHANNEL`
+  InvalidChannel,
+
+  /// The frequency is out of the range of the radio
+  InvalidMhz,
+
+  /// The nRF24L01+ RF_CH is above what the radio covers
+  InvalidNrf24Channel,
+
+  /// The BLE channel index is above 39
+  InvalidBleChannel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Map {
+  /// Channels from 2400 MHz
+  Default2400MHz,
+  /// Channels from 2360 MHz
+  Low2360MHz,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frequency {
+  map: Map,
+  channel: u8,
 }
 
 impl Frequency {
-  pub fn from_2400mhz_channel(channel: u8) -> Self {
-    assert!(channel <= 100);
-    Frequency::Default2400MHz(channel)
+  pub fn from_channel(map: Map, channel: u8) -> Result<Self> {
+    if channel <= MAX_CHANNEL {
+      Ok(Frequency { map, channel })
+    }
+    else {
+      Err(Error::InvalidChannel)
+    }
+  }
+
+  /// For the channels that are known to be within the default map
+  pub(crate) const fn from_valid_2400mhz_channel(channel: u8) -> Self {
+    Frequency { map: Map::Default2400MHz, channel }
+  }
+
+  pub fn from_2400mhz_channel(channel: u8) -> Result<Self> {
+    Self::from_channel(Map::Default2400MHz, channel)
+  }
+
+  pub fn from_2360mhz_channel(channel: u8) -> Result<Self> {
+    Self::from_channel(Map::Low2360MHz, channel)
+  }
+
+  /// From 2360 MHz to 2500 MHz, using the default map from 2400 MHz
+  pub fn from_mhz(mhz: u16) -> Result<Self> {
+    if (DEFAULT_BASE_MHZ ..= DEFAULT_BASE_MHZ + u16::from(MAX_CHANNEL)).contains(&mhz) {
+      Self::from_2400mhz_channel((mhz - DEFAULT_BASE_MHZ) as u8)
+    }
+    else if (LOW_BASE_MHZ .. DEFAULT_BASE_MHZ).contains(&mhz) {
+      Self::from_2360mhz_channel((mhz - LOW_BASE_MHZ) as u8)
+    }
+    else {
+      Err(Error::InvalidMhz)
+    }
+  }
+
+  /// Channel as set in the RF_CH register of the nRF24L01+, at 2400 + RF_CH MHz.
+  /// It goes up to 125, but the radio only covers up to 100.
+  pub fn from_nrf24_channel(rf_ch: u8) -> Result<Self> {
+    Self::from_2400mhz_channel(rf_ch).map_err(|_| Error::InvalidNrf24Channel)
+  }
+
+  /// BLE channel index, from 0 to 36 for data channels, and 37 to 39 for advertising channels.
+  /// See Bluetooth Core Specification, Vol 6, Part B, 1.4.1
+  pub fn from_ble_channel(index: u8) -> Result<Self> {
+    let mhz = match index {
+      0 ..= 10 => 2404 + 2 * u16::from(index),
+      11 ..= 36 => 2428 + 2 * u16::from(index - 11),
+      37 => 2402,
+      38 => 2426,
+      39 => 2480,
+      _ => return Err(Error::InvalidBleChannel),
+    };
+    Self::from_mhz(mhz)
+  }
+
+  pub fn map(&self) -> Map {
+    self.map
+  }
+
+  /// Channel in its map, up to `MAX_CHANNEL`
+  pub fn channel(&self) -> u8 {
+    self.channel
+  }
+
+  /// Absolute frequency in MHz
+  pub fn mhz(&self) -> u16 {
+    let base = match self.map {
+      Map::Default2400MHz => DEFAULT_BASE_MHZ,
+      Map::Low2360MHz => LOW_BASE_MHZ,
+    };
+    base + u16::from(self.channel)
+  }
+
+  /// nRF24L01+ RF_CH for the same frequency, None below 2400 MHz
+  pub fn nrf24_channel(&self) -> Option<u8> {
+    self.mhz().checked_sub(DEFAULT_BASE_MHZ).map(|channel| channel as u8)
   }
 
-  pub fn from_2360mhz_channel(channel: u8) -> Self {
-    assert!(channel <= 100);
-    Frequency::Low2360MHz(channel)
+  /// BLE channel index for the same frequency, None when it is not a BLE channel
+  pub fn ble_channel(&self) -> Option<u8> {
+    match self.mhz() {
+      2402 => Some(37),
+      2426 => Some(38),
+      2480 => Some(39),
+      mhz @ 2404 ..= 2424 if mhz % 2 == 0 => Some(((mhz - 2404) / 2) as u8),
+      mhz @ 2428 ..= 2478 if mhz % 2 == 0 => Some(((mhz - 2428) / 2) as u8 + 11),
+      _ => None,
+    }
   }
 }
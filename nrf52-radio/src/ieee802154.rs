@@ -0,0 +1,119 @@
+/*!
+
+IEEE 802.15.4 support: framing, clear channel assessment and energy detection
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.12 IEEE 802.15.4 operation
+
+*/
+
+use crate::values_as_enum;
+use crate::frequency::{Frequency, Result as FrequencyResult, Error as FrequencyError};
+use crate::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+
+/// Start of frame delimiter defined by the standard
+pub const DEFAULT_SFD: u8 = 0xa7;
+
+/// Maximum PSDU length, including the CRC (FCS)
+pub const MAX_PSDU_LENGTH: u8 = 127;
+
+/// ITU-T CRC polynomial x^16 + x^12 + x^5 + 1
+pub const CRC_POLYNOMIAL: u32 = 0x11021;
+
+const ED_RSSISCALE: i16 = 4;
+const ED_RSSIOFFS: i16 = -92;
+
+values_as_enum!(
+  CcaMode, "Clear channel assessment mode",
+  (0, EdMode, "Energy above threshold"),
+  (1, CarrierMode, "Carrier seen"),
+  (2, CarrierAndEdMode, "Energy above threshold and carrier seen"),
+  (3, CarrierOrEdMode, "Energy above threshold or carrier seen"),
+  (4, EdModeTest1, "Energy above threshold test mode that will abort when first ED measurement over threshold is seen")
+);
+
+/// Configuration for the CCACTRL register
+///
+/// [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14.49 CCACTRL
+#[derive(Debug, Clone, Copy)]
+pub struct CcaConfig {
+  /// CCA mode of operation
+  pub mode: CcaMode,
+
+  /// CCA energy busy threshold, used in all the modes except `CarrierMode`
+  pub ed_threshold: u8,
+
+  /// CCA correlator busy threshold, only relevant to the carrier modes
+  pub corr_threshold: u8,
+
+  /// Limit for occurrences above `corr_threshold`, when not equal to zero the correlator
+  /// based signal detect is enabled
+  pub corr_count: u8,
+}
+
+impl Default for CcaConfig {
+  fn default() -> Self {
+    CcaConfig {
+      mode: CcaMode::EdMode,
+      ed_threshold: 0x2d,
+      corr_threshold: 0x2d,
+      corr_count: 0x02,
+    }
+  }
+}
+
+impl CcaConfig {
+  pub fn with_mode(self, mode: CcaMode) -> Self {
+    Self { mode, .. self }
+  }
+
+  pub fn with_ed_threshold(self, ed_threshold: u8) -> Self {
+    Self { ed_threshold, .. self }
+  }
+
+  pub fn with_corr_threshold(self, corr_threshold: u8) -> Self {
+    Self { corr_threshold, .. self }
+  }
+
+  pub fn with_corr_count(self, corr_count: u8) -> Self {
+    Self { corr_count, .. self }
+  }
+
+  /// Value for the CCACTRL register
+  pub fn value(&self) -> u32 {
+    self.mode.value() |
+        u32::from(self.ed_threshold) << 8 |
+        u32::from(self.corr_threshold) << 16 |
+        u32::from(self.corr_count) << 24
+  }
+}
+
+/// Packet configuration for IEEE 802.15.4 frames: an 8 bits PHR with the length
+/// of the PSDU, which includes the CRC
+pub fn packet_config() -> PacketConfig {
+  PacketConfig::default()
+      .with_length_bits(8)
+      .with_s0_byte_included(false)
+      .with_s1_len(S1Length::Of0Bits)
+      .with_s1_include_in_ram(S1IncludeInRam::Automatic)
+      .with_preamble_len(PreambleLength::Of32Bits)
+      .with_crc_included_in_length(true)
+      .with_max_bytes(MAX_PSDU_LENGTH)
+      .with_static_bytes(0)
+      .with_endianess(Endianess::LittleEndian)
+      .with_whitening_enabled(false)
+}
+
+/// Frequency for an IEEE 802.15.4 channel between 11 and 26
+pub fn channel_frequency(channel: u8) -> FrequencyResult<Frequency> {
+  if (11 ..= 26).contains(&channel) {
+    Frequency::from_2400mhz_channel(5 + 5 * (channel - 11))
+  }
+  else {
+    Err(FrequencyError::InvalidChannel)
+  }
+}
+
+/// Convert an energy detection sample (EDSAMPLE) into dBm
+pub fn ed_to_dbm(sample: u8) -> i16 {
+  ED_RSSIOFFS + ED_RSSISCALE * i16::from(sample)
+}
@@ -3,7 +3,22 @@
 #[macro_use]
 extern crate bitflags;
 
-use nrf52840_hal as hal;
+#[cfg(not(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840")))]
+compile_error!("select the chip with one of the features: nrf52832, nrf52833 or nrf52840");
+
+#[cfg(any(
+  all(feature = "nrf52832", feature = "nrf52833"),
+  all(feature = "nrf52832", feature = "nrf52840"),
+  all(feature = "nrf52833", feature = "nrf52840"),
+))]
+compile_error!("only one of the features nrf52832, nrf52833 or nrf52840 can be selected");
+
+#[cfg(feature = "nrf52832")]
+pub use nrf52832_hal as hal;
+#[cfg(feature = "nrf52833")]
+pub use nrf52833_hal as hal;
+#[cfg(feature = "nrf52840")]
+pub use nrf52840_hal as hal;
 
 pub use radio::{AsyncResult, Error, Radio, RadioExt, Result};
 
@@ -16,6 +31,17 @@ pub mod frequency;
 pub mod logical_address;
 pub mod rx_addresses;
 pub mod shortcuts;
+pub mod events;
 pub mod states;
 pub mod radio;
+pub mod typestate;
+pub mod ble;
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub mod ieee802154;
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub mod long_range;
+pub mod config;
+pub mod timestamps;
+pub mod dma_buffer;
+pub mod traits;
 
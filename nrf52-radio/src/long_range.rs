@@ -0,0 +1,120 @@
+/*!
+
+BLE Long Range (Coded PHY)
+
+Packets are sent with a long range preamble, followed by the access address, a code indicator (CI),
+a TERM1 field, the FEC coded PDU and CRC, and a TERM2 field. The code indicator tells the receiver
+whether the rest of the packet uses S=8 (125 kbit/s) or S=2 (500 kbit/s) coding, so a receiver
+in any of the long range modes gets both.
+
+Use the PHYEND event rather than END to know when a packet has been completely sent or received.
+`LongRange` does it for a whole transfer, from the DISABLED state back to it.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.15 BLE Long Range
+
+*/
+
+use crate::values_as_enum;
+use crate::radio::{Radio, Result, AsyncResult};
+use crate::mode::Mode;
+use crate::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+use crate::shortcuts::Shortcuts;
+use crate::events::Events;
+use crate::dma_buffer::DmaBuffer;
+
+/// Length of the code indicator in bits
+pub const CI_LENGTH: u8 = 2;
+
+/// Length of the TERM1 field in bits
+pub const TERM_LENGTH: u8 = 3;
+
+values_as_enum!(
+  CodingIndicator, "Coding of a Long Range packet, as given by its code indicator",
+  (0, S8, "Coding with S=8, 125 kbit/s"),
+  (1, S2, "Coding with S=2, 500 kbit/s")
+);
+
+/// Packet configuration for BLE Long Range, with up to `max_bytes` of payload
+pub fn packet_config(max_bytes: u8) -> PacketConfig {
+  PacketConfig::default()
+      .with_length_bits(8)
+      .with_s0_byte_included(true)
+      .with_s1_len(S1Length::Of0Bits)
+      .with_s1_include_in_ram(S1IncludeInRam::Automatic)
+      .with_ci_len(CI_LENGTH)
+      .with_preamble_len(PreambleLength::ForLongRange)
+      .with_crc_included_in_length(false)
+      .with_term_len(TERM_LENGTH)
+      .with_max_bytes(max_bytes)
+      .with_static_bytes(0)
+      .with_endianess(Endianess::LittleEndian)
+      .with_whitening_enabled(true)
+}
+
+/// Sends and receives single Long Range packets.
+/// The radio starts on READY and disables itself on PHYEND, so a transfer only finishes
+/// once the TERM2 field has been sent or received, and not on END.
+pub struct LongRange<'a, LFOSC, LFSTAT> {
+  radio: Radio<'a, LFOSC, LFSTAT>,
+  phy_end: bool,
+}
+
+impl<'a, LFOSC, LFSTAT> LongRange<'a, LFOSC, LFSTAT> {
+  /// The radio needs to be disabled, and `buffer` needs to hold the header and `max_bytes` of payload.
+  /// The frequency is left to the radio.
+  pub fn new(mut radio: Radio<'a, LFOSC, LFSTAT>, mode: Mode, max_bytes: u8, access_address: u32, crc_init: u32,
+             buffer: DmaBuffer<'a>) -> Result<Self> {
+    radio
+        .set_ble_long_range(mode, max_bytes, crc_init)?
+        .set_ble_access_address(access_address)
+        .set_shortcuts(Shortcuts::READY_START | Shortcuts::PHYEND_DISABLE);
+    radio.swap_buffer(&mut Some(buffer))?;
+    Ok(LongRange {
+      radio,
+      phy_end: false,
+    })
+  }
+
+  /// Send the packet in the buffer, it needs to be polled with `wait`
+  pub fn start_tx(&mut self) -> Result<()> {
+    self.prepare();
+    self.radio.enable_tx()
+  }
+
+  /// Listen for a packet, it needs to be polled with `wait`
+  pub fn start_rx(&mut self) -> Result<()> {
+    self.prepare();
+    self.radio.enable_rx()
+  }
+
+  /// Poll until PHYEND, once the packet has been completely sent or received, and the radio is disabled.
+  /// A received packet is in the buffer, see `Radio::is_crc_ok` and `Radio::get_received_coding`.
+  pub fn wait(&mut self) -> AsyncResult<()> {
+    if !self.phy_end {
+      self.radio.wait_phy_end_or_disable()?;
+      self.phy_end = true;
+    }
+    self.radio.wait_disabled()?;
+    self.phy_end = false;
+    Ok(())
+  }
+
+  /// Buffer to write the packet to send, with the header first
+  pub fn buffer_mut(&mut self) -> &mut [u8] {
+    self.radio.get_buffer_mut()
+  }
+
+  pub fn radio(&self) -> &Radio<'a, LFOSC, LFSTAT> {
+    &self.radio
+  }
+
+  /// Give back the radio, once the transfer has finished
+  pub fn free(self) -> Radio<'a, LFOSC, LFSTAT> {
+    self.radio
+  }
+
+  fn prepare(&mut self) {
+    self.radio.clear_events(Events::PHYEND);
+    self.phy_end = false;
+  }
+}
@@ -4,15 +4,21 @@ Data rate and modulation
 
 See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14.12 MODE
 
+The long range and IEEE 802.15.4 modes are only available on the nRF52833 and nRF52840.
+
 */
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mode {
   Nrf1Mbit,            // 1 Mbit/s Nordic proprietary radio mode
   Nrf2Mbit,            // 2 Mbit/s Nordic proprietary radio mode
   Ble1Mbit,            // 1 Mbit/s BLE
   Ble2Mbit,            // 2 Mbit/s BLE
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
   BleLongRange125Kbit, // Long range 125 kbit/s TX, 125 kbit/s and 500 kbit/s RX
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
   BleLongRange500Kbit, // Long range 500 kbit/s TX, 125 kbit/s and 500 kbit/s RX
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
   Ieee802154At250Kbit, // IEEE 802.15.4-2006 250 kbit/s
 }
 
@@ -23,9 +29,28 @@ impl Mode {
       Mode::Nrf2Mbit => 1,
       Mode::Ble1Mbit => 3,
       Mode::Ble2Mbit => 4,
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
       Mode::BleLongRange125Kbit => 5,
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
       Mode::BleLongRange500Kbit => 6,
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
       Mode::Ieee802154At250Kbit => 15,
     }
   }
+
+  pub fn from(value: u32) -> Option<Self> {
+    match value {
+      0 => Some(Mode::Nrf1Mbit),
+      1 => Some(Mode::Nrf2Mbit),
+      3 => Some(Mode::Ble1Mbit),
+      4 => Some(Mode::Ble2Mbit),
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+      5 => Some(Mode::BleLongRange125Kbit),
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+      6 => Some(Mode::BleLongRange500Kbit),
+      #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+      15 => Some(Mode::Ieee802154At250Kbit),
+      _ => None,
+    }
+  }
 }
@@ -6,6 +6,7 @@ use crate::values_as_enum;
 /// - 6.20.14.13 PCNF0
 /// - 6.20.14.14 PCNF1
 ///
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PacketConfig {
 
   /// Length on air of LENGTH field in number of bits.
@@ -20,15 +21,17 @@ pub struct PacketConfig {
   /// Include or exclude S1 field in RAM.
   pub s1_include_in_ram: Option<S1IncludeInRam>,
 
-  // TODO CILEN Length of code indicator - long range
+  /// Length of code indicator in bits - long range, only on the nRF52833 and nRF52840
+  pub ci_len: Option<u8>,
 
-  /// Length of preamble on air.
+  /// Length of preamble on air, only 8 or 16 bits on the nRF52832.
   pub preamble_len: Option<PreambleLength>,
 
-  /// Indicates if LENGTH field contains CRC or not.
+  /// Indicates if LENGTH field contains CRC or not, only on the nRF52833 and nRF52840.
   pub crc_included_in_length: Option<bool>,
 
-  // TODO TERMLEN Length of TERM field in Long Range operation
+  /// Length of TERM field in bits - long range, only on the nRF52833 and nRF52840
+  pub term_len: Option<u8>,
 
   /// Maximum length of packet payload in bytes. Allowed values between 0 and 255.
   /// If the packet payload is larger than `max_len`,
@@ -55,8 +58,10 @@ impl Default for PacketConfig {
       s0_byte_included: None,
       s1_len: None,
       s1_include_in_ram: None,
+      ci_len: None,
       preamble_len: None,
       crc_included_in_length: None,
+      term_len: None,
       max_bytes: None,
       static_bytes: None,
       endianess: None,
@@ -82,6 +87,10 @@ impl PacketConfig {
     Self { s1_include_in_ram: Some(include), .. self }
   }
 
+  pub fn with_ci_len(self, bits: u8) -> Self {
+    Self { ci_len: Some(bits), .. self }
+  }
+
   pub fn with_preamble_len(self, bits: PreambleLength) -> Self {
     Self { preamble_len: Some(bits), .. self }
   }
@@ -90,6 +99,10 @@ impl PacketConfig {
     Self { crc_included_in_length: Some(included), .. self }
   }
 
+  pub fn with_term_len(self, bits: u8) -> Self {
+    Self { term_len: Some(bits), .. self }
+  }
+
   pub fn with_max_bytes(self, bytes: u8) -> Self {
     Self { max_bytes: Some(bytes), .. self }
   }
@@ -6,22 +6,60 @@
 ///!
 
 use core::convert::TryFrom;
+use core::marker::PhantomData;
+use core::ops::Deref;
 use core::sync::atomic::{compiler_fence, Ordering};
-//use core::ops::Deref;
 //use cortex_m_semihosting::{dbg, hprintln, heprintln};
 
-use crate::hal::target::RADIO;
+use crate::hal::target::{RADIO, radio::RegisterBlock};
 use crate::hal::clocks::ExternalOscillator;
 use crate::tx_power::TxPower;
 use crate::mode::Mode;
-use crate::packet_config::{S1IncludeInRam, Endianess, PacketConfig};
-use crate::frequency::Frequency;
+use crate::packet_config::{S1Length, S1IncludeInRam, PreambleLength, Endianess, PacketConfig};
+use crate::frequency::{self, Frequency, Map};
 use crate::base_address::BaseAddresses;
 use crate::logical_address::LogicalAddress;
+use crate::rx_addresses::RxAddresses;
 use crate::states::State;
 use crate::shortcuts::Shortcuts;
-use nrf52840_hal::Clocks;
-
+use crate::events::Events;
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+use crate::ieee802154::{self, CcaConfig};
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+use crate::long_range::{self, CodingIndicator};
+use crate::config::RadioConfig;
+use crate::dma_buffer::DmaBuffer;
+use crate::hal::Clocks;
+
+
+/// Evaluates the body for every event with its EVENTS_* register
+macro_rules! for_each_event {
+  ( $radio:expr, |$event:ident, $register:ident| $body:expr ) => {
+    for_each_event!($radio, |$event, $register| $body,
+      (READY, events_ready), (ADDRESS, events_address), (PAYLOAD, events_payload),
+      (END, events_end), (DISABLED, events_disabled), (DEVMATCH, events_devmatch),
+      (DEVMISS, events_devmiss), (RSSIEND, events_rssiend), (BCMATCH, events_bcmatch),
+      (CRCOK, events_crcok), (CRCERROR, events_crcerror)
+    );
+    // Only the nRF52833 and nRF52840 have the events for IEEE 802.15.4 and BLE Long Range
+    #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+    for_each_event!($radio, |$event, $register| $body,
+      (FRAMESTART, events_framestart), (EDEND, events_edend), (EDSTOPPED, events_edstopped),
+      (CCAIDLE, events_ccaidle), (CCABUSY, events_ccabusy), (CCASTOPPED, events_ccastopped),
+      (RATEBOOST, events_rateboost), (TXREADY, events_txready), (RXREADY, events_rxready),
+      (MHRMATCH, events_mhrmatch), (PHYEND, events_phyend)
+    );
+  };
+  ( $radio:expr, |$event:ident, $register:ident| $body:expr, $( ($name:ident, $field:ident) ),* ) => {
+    $(
+      {
+        let $event = Events::$name;
+        let $register = &$radio.$field;
+        $body;
+      }
+    )*
+  };
+}
 
 macro_rules! map_or {
   ( $option:expr, $default:expr, |$value:ident| $transform:expr ) => {
@@ -39,6 +77,18 @@ pub type AsyncResult<T> = nb::Result<T, Error>;
 pub enum Error {
   BufferNotDefined,
   WrongState,
+
+  /// EasyDMA can only access buffers in the Data RAM
+  BufferNotInRam,
+
+  /// The buffer can not hold the header and `max_bytes` of payload
+  BufferTooSmall,
+
+  /// The mode does not apply to the configuration
+  WrongMode,
+
+  /// The chip does not support the TX power level
+  UnsupportedTxPower,
 }
 
 pub trait RadioExt {
@@ -56,32 +106,38 @@ impl RadioExt for RADIO {
   }
 }
 
-//struct RadioX<T> {
-//  regs: T
-//}
-//
-//impl<T> RadioX<T> where T: Deref<Target=crate::hal::target::radio::RegisterBlock> {
-//  pub fn foo(&self) {
-//    self.regs.power.write(|w| w.power().enabled());
-//  }
-//}
-
 pub struct Packet;
 
-pub struct Radio<'a, LFOSC, LFSTAT> {
-  _clocks: &'a Clocks<ExternalOscillator, LFOSC, LFSTAT>,
-  pub radio: RADIO,
-  buffer: Option<&'a mut [u8]>,
+/// The registers can be those of the RADIO peripheral, or any other register block,
+/// such as the one from an emulator when running on the host.
+pub struct Radio<'a, LFOSC, LFSTAT, R = RADIO> {
+  _clocks: PhantomData<&'a Clocks<ExternalOscillator, LFOSC, LFSTAT>>,
+  pub radio: R,
+  buffer: Option<DmaBuffer<'a>>,
 }
 
-impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
+impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
 
-  pub fn new(radio: RADIO,
-             clocks: &'a Clocks<ExternalOscillator, LFOSC, LFSTAT>) -> Self {
+  pub fn new(radio: R,
+             _clocks: &'a Clocks<ExternalOscillator, LFOSC, LFSTAT>) -> Self {
 
     Radio {
       radio,
-      _clocks: clocks,
+      _clocks: PhantomData,
+      buffer: None,
+    }
+  }
+
+  /// Create the radio without proof that the external oscillator is running.
+  ///
+  /// # Safety
+  ///
+  /// The radio does not work without the external oscillator, so it must be running
+  /// when the registers belong to the RADIO peripheral.
+  pub unsafe fn new_unchecked(radio: R) -> Self {
+    Radio {
+      radio,
+      _clocks: PhantomData,
       buffer: None,
     }
   }
@@ -95,16 +151,21 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
-  pub fn enable_interrupts(&self, bits: u32) -> &Self {
-    self.radio.intenset.write(|w| unsafe { w.bits(bits) });
+  pub fn enable_interrupts(&self, events: Events) -> &Self {
+    self.radio.intenset.write(|w| unsafe { w.bits(events.bits()) });
     self
   }
 
-  pub fn disable_interrupts(&self, bits: u32) -> &Self {
-    self.radio.intenclr.write(|w| unsafe { w.bits(bits) });
+  pub fn disable_interrupts(&self, events: Events) -> &Self {
+    self.radio.intenclr.write(|w| unsafe { w.bits(events.bits()) });
     self
   }
 
+  /// Events that will trigger the RADIO interrupt
+  pub fn get_enabled_interrupts(&self) -> Events {
+    Events::from_bits_truncate(self.radio.intenset.read().bits())
+  }
+
   pub fn disable_all_interrupts(&self) -> &Self {
     self.radio.intenclr.write(|w| unsafe { w.bits(0xffffffff) });
     self
@@ -120,9 +181,17 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
-  pub fn set_tx_power(&self, tx_power: TxPower) -> &Self {
+  /// A level that the chip does not support is refused, see `TxPower::to_supported`
+  pub fn set_tx_power(&self, tx_power: TxPower) -> Result<&Self> {
+    if !tx_power.is_supported() {
+      return Err(Error::UnsupportedTxPower);
+    }
     self.radio.txpower.write(|w| unsafe { w.bits(tx_power.value()) });
-    self
+    Ok(self)
+  }
+
+  pub fn get_tx_power(&self) -> TxPower {
+    TxPower::from(self.radio.txpower.read().bits())
   }
 
   pub fn set_mode(&self, mode: Mode) -> &Self {
@@ -130,6 +199,27 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
+  /// None when the MODE register holds a reserved value
+  pub fn get_mode(&self) -> Option<Mode> {
+    Mode::from(self.radio.mode.read().bits())
+  }
+
+  /// Snapshot of all the configuration registers
+  pub fn get_config(&self) -> RadioConfig {
+    RadioConfig::read(&self.radio)
+  }
+
+  /// Restore a configuration from `get_config`, the radio needs to be disabled
+  pub fn set_config(&self, config: &RadioConfig) -> Result<()> {
+    match self.get_state() {
+      State::Disabled => {
+        config.write(&self.radio);
+        Ok(())
+      },
+      _ => Err(Error::WrongState)
+    }
+  }
+
   pub fn set_packet_config(&self, pcfn: PacketConfig) -> &Self {
     self.radio.pcnf0.modify(|_, w| unsafe {
       Some(w)
@@ -137,8 +227,22 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
         .map(|w| map_or!(&pcfn.s0_byte_included, w, |included| w.s0len().bit(*included)))
         .map(|w| map_or!(&pcfn.s1_len, w, |len| w.s1len().bits(u8::try_from(len.value()).unwrap())))
         .map(|w| map_or!(&pcfn.s1_include_in_ram, w, |included| w.s1incl().bit(*included == S1IncludeInRam::Always)))
+        .unwrap()
+    });
+    // The nRF52832 PLEN is a single bit, for 8 or 16 bits, and there is no CRCINC
+    #[cfg(feature = "nrf52832")]
+    self.radio.pcnf0.modify(|_, w| {
+      Some(w)
+        .map(|w| map_or!(&pcfn.preamble_len, w, |len| w.plen().bit(*len == PreambleLength::Of16Bits)))
+        .unwrap()
+    });
+    #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+    self.radio.pcnf0.modify(|_, w| unsafe {
+      Some(w)
         .map(|w| map_or!(&pcfn.preamble_len, w, |len| w.plen().bits(u8::try_from(len.value()).unwrap())))
         .map(|w| map_or!(&pcfn.crc_included_in_length, w, |included| w.crcinc().bit(*included)))
+        .map(|w| map_or!(&pcfn.ci_len, w, |bits| w.cilen().bits(*bits)))
+        .map(|w| map_or!(&pcfn.term_len, w, |bits| w.termlen().bits(*bits)))
         .unwrap()
     });
     self.radio.pcnf1.modify(|_, w| unsafe {
@@ -152,6 +256,32 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
+  /// Packet configuration with all the fields read from PCNF0 and PCNF1
+  pub fn get_packet_config(&self) -> PacketConfig {
+    let pcnf0 = self.radio.pcnf0.read();
+    let pcnf1 = self.radio.pcnf1.read();
+    let s1_include_in_ram = if pcnf0.s1incl().bit() { S1IncludeInRam::Always } else { S1IncludeInRam::Automatic };
+    let endianess = if pcnf1.endian().bit() { Endianess::BigEndian } else { Endianess::LittleEndian };
+    let pcfn = PacketConfig::default()
+        .with_length_bits(pcnf0.lflen().bits())
+        .with_s0_byte_included(pcnf0.s0len().bit())
+        .with_s1_len(S1Length::from(u32::from(pcnf0.s1len().bits())).unwrap())
+        .with_s1_include_in_ram(s1_include_in_ram)
+        .with_max_bytes(pcnf1.maxlen().bits())
+        .with_static_bytes(pcnf1.statlen().bits())
+        .with_endianess(endianess)
+        .with_whitening_enabled(pcnf1.whiteen().bit());
+    #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+    return pcfn
+        .with_preamble_len(PreambleLength::from(u32::from(pcnf0.plen().bits())).unwrap())
+        .with_crc_included_in_length(pcnf0.crcinc().bit())
+        .with_ci_len(pcnf0.cilen().bits())
+        .with_term_len(pcnf0.termlen().bits());
+    #[cfg(feature = "nrf52832")]
+    return pcfn
+        .with_preamble_len(PreambleLength::from(u32::from(pcnf0.plen().bit())).unwrap());
+  }
+
   pub fn set_crc_disabled(&self) -> &Self {
     self.radio.crccnf.modify(|_, w| w.len().disabled());
     self
@@ -188,13 +318,36 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
-  pub fn set_crc_ieee802154(self) -> Self {
-    self.radio.crccnf.modify(|_, w| w.skipaddr().include());
+  /// CRC used by BLE: 24 bits, address not included, polynomial x^24 + x^10 + x^9 + x^6 + x^4 + x^3 + x + 1.
+  /// The initial value is 0x555555 for advertising channels, or the one given on connection.
+  pub fn set_crc_ble(&self, initial: u32) -> &Self {
+    self.set_crc_24bits(initial, 0x00065b);
+    self.set_crc_skip_address();
+    self
+  }
+
+  /// Data whitening initial value, for BLE it is the channel index.
+  /// 6.20.14.30 DATAWHITEIV
+  pub fn set_whitening_iv(&self, iv: u8) -> &Self {
+    // Bit 6 is hard-wired to 1
+    self.radio.datawhiteiv.write(|w| unsafe { w.datawhiteiv().bits(iv | 0x40) });
+    self
+  }
+
+  /// BLE access address on logical address 0, both for tx and rx.
+  /// It is sent least significant bit first, so unlike `set_base_addresses` the bits are not reversed.
+  pub fn set_ble_access_address(&self, access_address: u32) -> &Self {
+    self.radio.pcnf1.modify(|_, w| unsafe { w.balen().bits(3) });
+    self.radio.base0.write(|w| unsafe { w.bits(access_address << 8) });
+    self.radio.prefix0.modify(|_, w| unsafe { w.ap0().bits((access_address >> 24) as u8) });
+    self.radio.txaddress.write(|w| unsafe { w.bits(0) });
+    self.radio.rxaddresses.write(|w| w.addr0().enabled());
     self
   }
 
   pub fn set_base_addresses(&self, addr: BaseAddresses) -> &Self {
     let (length, base0, base1) = match addr {
+      BaseAddresses::OneByte(addr0, addr1) => (1, u32::from(addr0), u32::from(addr1)),
       BaseAddresses::TwoBytes(addr0, addr1) => (2, u32::from(addr0), u32::from(addr1)),
       BaseAddresses::ThreeBytes(addr0, addr1) => (3, addr0 & 0xffffff, addr1 & 0xffffff),
       BaseAddresses::FourBytes(addr0, addr1) => (4, addr0, addr1),
@@ -205,6 +358,19 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
+  /// None when the base address length in PCNF1 is not between 1 and 4 bytes
+  pub fn get_base_addresses(&self) -> Option<BaseAddresses> {
+    let base0 = self.radio.base0.read().bits().reverse_bits();
+    let base1 = self.radio.base1.read().bits().reverse_bits();
+    match self.radio.pcnf1.read().balen().bits() {
+      1 => Some(BaseAddresses::OneByte(base0 as u8, base1 as u8)),
+      2 => Some(BaseAddresses::TwoBytes(base0 as u16, base1 as u16)),
+      3 => Some(BaseAddresses::ThreeBytes(base0 & 0xffffff, base1 & 0xffffff)),
+      4 => Some(BaseAddresses::FourBytes(base0, base1)),
+      _ => None,
+    }
+  }
+
   pub fn set_prefixes(&self, prefixes: [u8; 8]) -> &Self {
     let prefix0 = u32::from(prefixes[0]) << 24 |
                         u32::from(prefixes[1]) << 16 |
@@ -219,32 +385,54 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self
   }
 
+  pub fn get_prefixes(&self) -> [u8; 8] {
+    let prefix0 = self.radio.prefix0.read().bits().reverse_bits().to_be_bytes();
+    let prefix1 = self.radio.prefix1.read().bits().reverse_bits().to_be_bytes();
+    [prefix0[0], prefix0[1], prefix0[2], prefix0[3],
+     prefix1[0], prefix1[1], prefix1[2], prefix1[3]]
+  }
+
   /// Radio channel frequency
   /// 6.20.14.10 FREQUENCY
   pub fn set_frequency(&self, freq: Frequency) -> &Self {
     self.radio.frequency.write(|w| unsafe {
-      let (channel, w) = match freq {
-        Frequency::Default2400MHz(channel) => (channel, w.map().default()),
-        Frequency::Low2360MHz(channel) => (channel, w.map().low())
+      let w = match freq.map() {
+        Map::Default2400MHz => w.map().default(),
+        Map::Low2360MHz => w.map().low(),
       };
-      assert!(channel <= 100);
-      w.frequency().bits(channel)
+      w.frequency().bits(freq.channel())
     });
     self
   }
 
+  /// Fails when the FREQUENCY register was written with a channel out of its map
+  pub fn get_frequency(&self) -> frequency::Result<Frequency> {
+    let frequency = self.radio.frequency.read();
+    let map = if frequency.map().is_low() { Map::Low2360MHz } else { Map::Default2400MHz };
+    Frequency::from_channel(map, frequency.frequency().bits())
+  }
+
   pub fn set_tx_address(&self, address: LogicalAddress) -> &Self {
     self.radio.txaddress.write(|w| unsafe { w.bits(address.value())});
     self
   }
 
+  pub fn get_tx_address(&self) -> LogicalAddress {
+    LogicalAddress::from(u32::from(self.radio.txaddress.read().txaddress().bits())).unwrap()
+  }
+
   /// 6.20.14.20 RXADDRESSES: Receive address select
-  // TODO use a library for bit fields
-  pub fn set_rx_addresses(&self, mask: u8) -> &Self {
-    self.radio.rxaddresses.write(|w| unsafe { w.bits(mask.into()) });
+  pub fn set_rx_addresses(&self, addresses: RxAddresses) -> &Self {
+    self.radio.rxaddresses.write(|w| unsafe { w.bits(addresses.bits()) });
     self
   }
 
+  /// Logical addresses enabled for reception
+  /// 6.20.14.20 RXADDRESSES
+  pub fn get_rx_addresses(&self) -> RxAddresses {
+    RxAddresses::from_bits_truncate(self.radio.rxaddresses.read().bits())
+  }
+
   /// Logical address of which previous packet was received
   /// 6.20.14.5 RXMATCH
   pub fn get_received_address(&self) -> LogicalAddress {
@@ -261,6 +449,37 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     State::from_value(self.radio.state.read().state().bits())
   }
 
+  /// Snapshot of all the events that are currently set
+  pub fn pending_events(&self) -> Events {
+    let mut events = Events::empty();
+    for_each_event!(self.radio, |event, register| {
+      if register.read().bits() != 0 {
+        events.insert(event);
+      }
+    });
+    events
+  }
+
+  pub fn clear_events(&self, events: Events) -> &Self {
+    for_each_event!(self.radio, |event, register| {
+      if events.contains(event) {
+        register.reset();
+      }
+    });
+    self
+  }
+
+  /// To be called from the RADIO interrupt handler.
+  /// Clears and returns the pending events that have the interrupt enabled.
+  pub fn handle_interrupt(&self) -> Events {
+    let events = self.pending_events() & self.get_enabled_interrupts();
+    self.clear_events(events);
+    // Read the events back, so the writes that clear them reach the peripheral before leaving the handler,
+    // otherwise the interrupt could be triggered again. A compiler fence would only order the accesses.
+    self.pending_events();
+    events
+  }
+
   pub fn is_ready(&self) -> bool {
     self.radio.events_ready.read().events_ready().bit_is_set()
   }
@@ -286,9 +505,43 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     self.radio.events_disabled.read().events_disabled().bit_is_set()
   }
 
+  /// Start sampling the received signal strength, the radio needs to be in RX
+  pub fn start_rssi(&self) -> Result<()> {
+    match self.get_state() {
+      State::Rx => {
+        self.radio.events_rssiend.reset();
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        compiler_fence(Ordering::Release);
+
+        self.radio.tasks_rssistart.write(|w| w.tasks_rssistart().set_bit());
+        Ok(())
+      },
+      _ => Err(Error::WrongState)
+    }
+  }
+
+  /// Received signal strength in -dBm, once the sample is ready
+  /// 6.20.14.22 RSSISAMPLE
+  pub fn wait_rssi(&self) -> AsyncResult<u8> {
+    if self.radio.events_rssiend.read().events_rssiend().bit_is_set() {
+      self.radio.events_rssiend.reset();
+      Ok(self.radio.rssisample.read().rssisample().bits())
+    }
+    else {
+      Err(nb::Error::WouldBlock)
+    }
+  }
+
+  /// Last received signal strength sample in -dBm, i.e. for the last packet
+  /// when using the `ADDRESS_RSSISTART` shortcut
+  pub fn get_rssi_sample(&self) -> u8 {
+    self.radio.rssisample.read().rssisample().bits()
+  }
+
   /// 6.20.14.9 PACKETPTR
-  fn set_packet_ptr(&self, buffer: &[u8]) {
-    let ptr = buffer.as_ptr() as u32;
+  fn set_packet_ptr(&self, buffer: &DmaBuffer) {
+    let ptr = buffer.address();
     self.radio.packetptr.write(|w| unsafe { w.bits(ptr) });
   }
 
@@ -298,37 +551,64 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
 
   pub fn get_buffer(&self) -> &[u8] {
     match self.buffer.as_ref() {
-      Some(buffer) => *buffer,
+      Some(buffer) => buffer,
       None => &[],
     }
   }
 
   pub fn get_buffer_mut(&mut self) -> &mut [u8] {
     match self.buffer.as_mut() {
-      Some(buffer) => *buffer,
+      Some(buffer) => buffer,
       None => &mut [],
     }
   }
 
-  // TODO should we use a critical section ?
-  pub fn swap_buffer(&mut self, new_buffer: Option<&'a mut [u8]>) -> Option<&'a mut [u8]> {
-    match new_buffer.as_ref() {
-      Some(buffer) =>
-        self.set_packet_ptr(*buffer),
+  /// Bytes that EasyDMA can transfer with the current packet configuration:
+  /// S0, LENGTH and S1 as stored in RAM, followed by up to `max_bytes` of payload.
+  pub fn required_buffer_length(&self) -> usize {
+    let pcnf0 = self.radio.pcnf0.read();
+    let s0_length = if pcnf0.s0len().bit() { 1 } else { 0 };
+    let length_length = (usize::from(pcnf0.lflen().bits()) + 7) / 8;
+    let s1_length = (usize::from(pcnf0.s1len().bits()) + 7) / 8;
+    let s1_length = if pcnf0.s1incl().bit() { s1_length.max(1) } else { s1_length };
+    let max_bytes = usize::from(self.radio.pcnf1.read().maxlen().bits());
+    s0_length + length_length + s1_length + max_bytes
+  }
+
+  /// Give `buffer` to the radio, and get back in it the one the radio had.
+  /// The buffers can not be swapped in the middle of a transfer, that is, in the RX or TX states,
+  /// nor while ramping up or idle when a shortcut may start a transfer at any time,
+  /// and the new buffer needs to hold `required_buffer_length` bytes.
+  /// Nothing changes when it fails.
+  pub fn swap_buffer(&mut self, buffer: &mut Option<DmaBuffer<'a>>) -> Result<()> {
+    let starting_shortcuts = Shortcuts::READY_START | Shortcuts::END_START | Shortcuts::TXREADY_START
+      | Shortcuts::RXREADY_START | Shortcuts::PHYEND_START;
+    match self.get_state() {
+      State::Rx | State::Tx => return Err(Error::WrongState),
+      State::RxRumpUp | State::RxIdle | State::TxRumpUp | State::TxIdle
+        if self.get_shortcuts().intersects(starting_shortcuts) => return Err(Error::WrongState),
+      _ => (),
+    }
+    match buffer.as_ref() {
+      Some(new_buffer) if new_buffer.len() < self.required_buffer_length() =>
+        return Err(Error::BufferTooSmall),
+      Some(new_buffer) =>
+        self.set_packet_ptr(new_buffer),
       None =>
-        // TODO this should only be allowed if the radio is disabled
-        // TODO decide whether to point to an undefined place or to leave the previous buffer
         self.reset_packet_ptr(),
     }
-    let prev_buffer = self.buffer.take();
-    self.buffer = new_buffer;
-    prev_buffer
+
+    // "Preceding reads and writes cannot be moved past subsequent writes."
+    compiler_fence(Ordering::Release);
+
+    core::mem::swap(&mut self.buffer, buffer);
+    Ok(())
   }
 
   pub fn enable_rx(&mut self) -> Result<()> {
     match (self.buffer.as_ref(), self.get_state()) {
       (Some(buffer), State::Disabled) => {
-        self.set_packet_ptr(*buffer);
+        self.set_packet_ptr(buffer);
 
         self.radio.events_ready.reset();
         self.radio.events_disabled.reset();
@@ -351,7 +631,7 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
   pub fn enable_tx(&mut self) -> Result<()> {
     match (self.buffer.as_ref(), self.get_state()) {
       (Some(buffer), State::Disabled) => {
-        self.set_packet_ptr(*buffer);
+        self.set_packet_ptr(buffer);
 
         self.radio.events_ready.reset();
         self.radio.events_disabled.reset();
@@ -383,8 +663,9 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
 
   pub fn start(&self) -> Result<()> {
     match (self.buffer.as_ref(), self.get_state()) {
+      (Some(buffer), _) if buffer.len() < self.required_buffer_length() => Err(Error::BufferTooSmall),
       (Some(buffer), State::RxIdle) | (Some(buffer), State::TxIdle) => {
-        self.set_packet_ptr(*buffer);
+        self.set_packet_ptr(buffer);
 
         self.radio.events_end.reset();
         self.radio.events_address.reset();
@@ -445,9 +726,159 @@ impl<'a, LFOSC, LFSTAT> Radio<'a, LFOSC, LFSTAT> {
     }
   }
 
-  pub fn free(self) -> RADIO {
+  pub fn free(self) -> R {
     // TODO disable, or fail if not disabled
     // TODO reset PACKETPTR ?
     self.radio
   }
 }
+
+/// IEEE 802.15.4 and BLE Long Range, only available on the nRF52833 and nRF52840
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  /// CRC used by IEEE 802.15.4: 16 bits ITU-T with initial value 0, the SFD and PHR are not included
+  pub fn set_crc_ieee802154(&self) -> &Self {
+    self.radio.crccnf.modify(|_, w| w.len().two().skipaddr().ieee802154());
+    self.radio.crcinit.write(|w| unsafe { w.bits(0) });
+    self.radio.crcpoly.write(|w| unsafe { w.bits(ieee802154::CRC_POLYNOMIAL) });
+    self
+  }
+
+  /// Mode, packet configuration and CRC for BLE Long Range (Coded PHY).
+  /// The access address is set with `set_ble_access_address`.
+  /// The mode needs to be one of the Long Range ones.
+  pub fn set_ble_long_range(&self, mode: Mode, max_bytes: u8, crc_init: u32) -> Result<&Self> {
+    match mode {
+      Mode::BleLongRange125Kbit | Mode::BleLongRange500Kbit => Ok(
+        self.set_mode(mode)
+            .set_packet_config(long_range::packet_config(max_bytes))
+            .set_crc_ble(crc_init)
+      ),
+      _ => Err(Error::WrongMode),
+    }
+  }
+
+  /// IEEE 802.15.4 start of frame delimiter
+  /// 6.20.14.48 SFD
+  pub fn set_sfd(&self, sfd: u8) -> &Self {
+    self.radio.sfd.write(|w| unsafe { w.sfd().bits(sfd) });
+    self
+  }
+
+  /// Mode, packet configuration, CRC and SFD for IEEE 802.15.4 frames
+  pub fn set_ieee802154(&self) -> &Self {
+    self.set_mode(Mode::Ieee802154At250Kbit)
+        .set_packet_config(ieee802154::packet_config())
+        .set_crc_ieee802154()
+        .set_sfd(ieee802154::DEFAULT_SFD)
+  }
+
+  /// Clear channel assessment configuration
+  /// 6.20.14.49 CCACTRL
+  pub fn set_cca(&self, config: CcaConfig) -> &Self {
+    self.radio.ccactrl.write(|w| unsafe { w.bits(config.value()) });
+    self
+  }
+
+  /// Number of iterations for the energy detection, each one takes 128 us
+  /// 6.20.14.51 EDCNT
+  pub fn set_ed_count(&self, count: u32) -> &Self {
+    self.radio.edcnt.write(|w| unsafe { w.edcnt().bits(count) });
+    self
+  }
+
+  /// In Long Range the packet ends after the TERM2 field, which comes after END
+  pub fn is_phy_end_or_disable(&self) -> bool {
+    self.radio.events_phyend.read().events_phyend().bit_is_set() ||
+        self.radio.events_disabled.read().events_disabled().bit_is_set()
+  }
+
+  /// Coding of the last received Long Range packet
+  /// 6.20.14.7 PDUSTAT
+  pub fn get_received_coding(&self) -> Option<CodingIndicator> {
+    CodingIndicator::from(u32::from(self.radio.pdustat.read().cistat().bits()))
+  }
+
+  /// Start a clear channel assessment, the radio needs to be in RXIDLE
+  pub fn start_cca(&self) -> Result<()> {
+    match self.get_state() {
+      State::RxIdle => {
+        self.radio.events_ccaidle.reset();
+        self.radio.events_ccabusy.reset();
+        self.radio.events_ccastopped.reset();
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        compiler_fence(Ordering::Release);
+
+        self.radio.tasks_ccastart.write(|w| w.tasks_ccastart().set_bit());
+        Ok(())
+      },
+      _ => Err(Error::WrongState)
+    }
+  }
+
+  /// Whether the channel is idle, once the clear channel assessment finished
+  pub fn wait_cca(&self) -> AsyncResult<bool> {
+    if self.radio.events_ccaidle.read().bits() != 0 {
+      self.radio.events_ccaidle.reset();
+      Ok(true)
+    }
+    else if self.radio.events_ccabusy.read().bits() != 0 {
+      self.radio.events_ccabusy.reset();
+      Ok(false)
+    }
+    else {
+      Err(nb::Error::WouldBlock)
+    }
+  }
+
+  pub fn stop_cca(&self) {
+    self.radio.tasks_ccastop.write(|w| w.tasks_ccastop().set_bit());
+  }
+
+  /// Start the energy detection, the radio needs to be in RXIDLE
+  pub fn start_energy_detection(&self) -> Result<()> {
+    match self.get_state() {
+      State::RxIdle => {
+        self.radio.events_edend.reset();
+        self.radio.events_edstopped.reset();
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        compiler_fence(Ordering::Release);
+
+        self.radio.tasks_edstart.write(|w| w.tasks_edstart().set_bit());
+        Ok(())
+      },
+      _ => Err(Error::WrongState)
+    }
+  }
+
+  /// Maximum energy level measured (EDSAMPLE), once the energy detection finished.
+  /// See `ieee802154::ed_to_dbm` to convert it into dBm.
+  pub fn wait_energy_detection(&self) -> AsyncResult<u8> {
+    if self.radio.events_edend.read().bits() != 0 {
+      self.radio.events_edend.reset();
+      Ok(self.radio.edsample.read().edlvl().bits())
+    }
+    else {
+      Err(nb::Error::WouldBlock)
+    }
+  }
+
+  pub fn stop_energy_detection(&self) {
+    self.radio.tasks_edstop.write(|w| w.tasks_edstop().set_bit());
+  }
+
+  pub fn wait_phy_end_or_disable(&self) -> AsyncResult<()> {
+    if self.is_phy_end_or_disable() {
+      self.radio.events_phyend.reset();
+      self.radio.events_end.reset();
+      self.radio.events_address.reset();
+      self.radio.events_payload.reset();
+      Ok(())
+    }
+    else {
+      Err(nb::Error::WouldBlock)
+    }
+  }
+}
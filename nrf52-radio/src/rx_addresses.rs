@@ -1,13 +1,56 @@
-/// 6.20.14.20 RXADDRESSES
-
-pub const RX_ADDRESS_ALL: u8 = 0xff;
-pub const RX_ADDRESS_NONE: u8 = 0x00;
-
-pub const RX_ADDRESS_1: u8 = 0x01;
-pub const RX_ADDRESS_2: u8 = 0x02;
-pub const RX_ADDRESS_3: u8 = 0x04;
-pub const RX_ADDRESS_4: u8 = 0x08;
-pub const RX_ADDRESS_5: u8 = 0x10;
-pub const RX_ADDRESS_6: u8 = 0x20;
-pub const RX_ADDRESS_7: u8 = 0x40;
-pub const RX_ADDRESS_8: u8 = 0x80;
+use crate::logical_address::LogicalAddress;
+
+bitflags! {
+    /// 6.20.14.20 RXADDRESSES: Receive address select
+    ///
+    /// Bit N enables reception on logical address N.
+    pub struct RxAddresses: u32 {
+        const ADDR0 = 1 << 0;
+        const ADDR1 = 1 << 1;
+        const ADDR2 = 1 << 2;
+        const ADDR3 = 1 << 3;
+        const ADDR4 = 1 << 4;
+        const ADDR5 = 1 << 5;
+        const ADDR6 = 1 << 6;
+        const ADDR7 = 1 << 7;
+    }
+}
+
+impl RxAddresses {
+  /// Set with only the given logical address enabled
+  pub fn from_address(address: LogicalAddress) -> Self {
+    RxAddresses::from_bits_truncate(1 << address.value())
+  }
+
+  /// Set with all the given logical addresses enabled
+  pub fn from_addresses(addresses: &[LogicalAddress]) -> Self {
+    addresses.iter()
+        .fold(RxAddresses::empty(), |set, address| set | RxAddresses::from_address(*address))
+  }
+
+  pub fn contains_address(&self, address: LogicalAddress) -> bool {
+    self.contains(RxAddresses::from_address(address))
+  }
+
+  pub fn insert_address(&mut self, address: LogicalAddress) {
+    self.insert(RxAddresses::from_address(address))
+  }
+
+  pub fn remove_address(&mut self, address: LogicalAddress) {
+    self.remove(RxAddresses::from_address(address))
+  }
+
+  /// Enabled logical addresses in increasing order
+  pub fn addresses(&self) -> impl Iterator<Item=LogicalAddress> {
+    let set = *self;
+    (0..8u32)
+        .filter_map(LogicalAddress::from)
+        .filter(move |address| set.contains_address(*address))
+  }
+}
+
+impl From<LogicalAddress> for RxAddresses {
+  fn from(address: LogicalAddress) -> Self {
+    RxAddresses::from_address(address)
+  }
+}
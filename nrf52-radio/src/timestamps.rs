@@ -0,0 +1,125 @@
+/*!
+
+Packet timestamps captured by hardware
+
+A TIMER counts microseconds, and the ADDRESS and END events from the radio capture its counter
+through PPI, so the timestamps do not depend on when the software polls the radio.
+
+```text
+RADIO EVENTS_ADDRESS --PPI--> TIMER TASKS_CAPTURE[0]
+RADIO EVENTS_END     --PPI--> TIMER TASKS_CAPTURE[1]
+```
+
+The counter is 32 bits long, so it wraps around every 71 minutes.
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf):
+- 6.16 PPI — Programmable peripheral interconnect
+- 6.30 TIMER — Timer/counter
+
+*/
+
+use core::ops::Deref;
+
+use crate::hal::target::{PPI, radio, timer0};
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+/// Number of programmable PPI channels
+pub const PPI_CHANNELS: u8 = 20;
+
+const ADDRESS_CC: usize = 0;
+const END_CC: usize = 1;
+const NOW_CC: usize = 2;
+
+/// 16 MHz / 2^4 = 1 MHz
+const PRESCALER_1MHZ: u8 = 4;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub enum Error {
+  /// The PPI channel is not one of the programmable ones
+  InvalidPpiChannel,
+
+  /// The same PPI channel can not be used for both events
+  SamePpiChannel,
+}
+
+/// Timer counter in microseconds when the events happened
+#[derive(Debug, Clone, PartialEq, Copy)]
+pub struct Timestamp {
+  /// The address was sent or received
+  pub address: u32,
+
+  /// The packet was completely sent or received
+  pub end: u32,
+}
+
+impl Timestamp {
+  /// Microseconds from the address to the end of the packet
+  pub fn duration(&self) -> u32 {
+    self.end.wrapping_sub(self.address)
+  }
+}
+
+pub struct Timestamps<'a> {
+  timer: &'a timer0::RegisterBlock,
+  address_channel: u8,
+  end_channel: u8,
+}
+
+impl<'a> Timestamps<'a> {
+  /// Start the timer at 1 MHz, and connect the radio events to the captures through
+  /// the programmable PPI channels `address_channel` and `end_channel`.
+  pub fn new<T, R>(timer: &'a T, radio: &R, ppi: &PPI, address_channel: u8, end_channel: u8) -> Result<Self>
+    where T: Deref<Target=timer0::RegisterBlock>,
+          R: Deref<Target=radio::RegisterBlock> {
+
+    if address_channel >= PPI_CHANNELS || end_channel >= PPI_CHANNELS {
+      return Err(Error::InvalidPpiChannel);
+    }
+    if address_channel == end_channel {
+      return Err(Error::SamePpiChannel);
+    }
+
+    let timer: &'a timer0::RegisterBlock = timer;
+    timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+    timer.mode.write(|w| w.mode().timer());
+    timer.bitmode.write(|w| w.bitmode()._32bit());
+    timer.prescaler.write(|w| unsafe { w.prescaler().bits(PRESCALER_1MHZ) });
+    timer.tasks_clear.write(|w| w.tasks_clear().set_bit());
+
+    let timestamps = Timestamps { timer, address_channel, end_channel };
+    timestamps.connect(address_channel, &radio.events_address as *const _ as u32, ADDRESS_CC, ppi);
+    timestamps.connect(end_channel, &radio.events_end as *const _ as u32, END_CC, ppi);
+    ppi.chenset.write(|w| unsafe { w.bits(1 << address_channel | 1 << end_channel) });
+
+    timer.tasks_start.write(|w| w.tasks_start().set_bit());
+    Ok(timestamps)
+  }
+
+  fn connect(&self, channel: u8, event: u32, cc: usize, ppi: &PPI) {
+    let task = &self.timer.tasks_capture[cc] as *const _ as u32;
+    let channel = &ppi.ch[usize::from(channel)];
+    channel.eep.write(|w| unsafe { w.bits(event) });
+    channel.tep.write(|w| unsafe { w.bits(task) });
+  }
+
+  /// Timestamps of the last packet sent or received
+  pub fn get_last(&self) -> Timestamp {
+    Timestamp {
+      address: self.timer.cc[ADDRESS_CC].read().bits(),
+      end: self.timer.cc[END_CC].read().bits(),
+    }
+  }
+
+  /// Current value of the timer, in the same time base as the timestamps
+  pub fn now(&self) -> u32 {
+    self.timer.tasks_capture[NOW_CC].write(|w| w.tasks_capture().set_bit());
+    self.timer.cc[NOW_CC].read().bits()
+  }
+
+  /// Disconnect the PPI channels and stop the timer
+  pub fn stop(self, ppi: &PPI) {
+    ppi.chenclr.write(|w| unsafe { w.bits(1 << self.address_channel | 1 << self.end_channel) });
+    self.timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+  }
+}
@@ -0,0 +1,49 @@
+/*!
+
+Generic radio traits
+
+The radio alone does not know about the packet format, so it only implements the channel,
+the output power and the received signal strength. See `nrf52-esb` for sending and receiving packets.
+
+*/
+
+use core::ops::Deref;
+
+use nb::block;
+
+use radio_traits::{Channel, Power, Rssi};
+
+use crate::hal::target::radio::RegisterBlock;
+use crate::frequency::Frequency;
+use crate::radio::{Error, Radio, Result};
+use crate::tx_power::TxPower;
+
+impl<'a, LFOSC, LFSTAT, R> Channel for Radio<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Channel = Frequency;
+  type Error = Error;
+
+  fn set_channel(&mut self, channel: &Frequency) -> Result<()> {
+    self.set_frequency(*channel);
+    Ok(())
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Power for Radio<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+
+  fn set_power(&mut self, power: i8) -> Result<()> {
+    self.set_tx_power(TxPower::from_dbm(power))?;
+    Ok(())
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Rssi for Radio<'a, LFOSC, LFSTAT, R> where R: Deref<Target=RegisterBlock> {
+  type Error = Error;
+
+  /// The radio needs to be in RX, the sample takes 0.25 us
+  fn poll_rssi(&mut self) -> Result<i16> {
+    self.start_rssi()?;
+    let sample = block!(self.wait_rssi())?;
+    Ok(-i16::from(sample))
+  }
+}
@@ -1,9 +1,13 @@
 ///!
 ///! Output power
 ///!
+///! The TXPOWER register holds the power in dBm as a signed byte, but only some levels are
+///! supported, and they depend on the chip. `TxPower::from_dbm` picks the nearest supported one.
+///!
 ///! See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.14.11 TXPOWER
 ///!
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TxPower {
   /// +8 dBm
   Pos8dBm,
@@ -33,10 +37,35 @@ pub enum TxPower {
   Neg20dBm,
   /// -40 dBm
   Neg40dBm,
-  /// Custom value
-  Custom(u8)
+  /// Value without a variant, only read back from the TXPOWER register
+  Custom(CustomTxPower)
 }
 
+/// Raw TXPOWER value, built by `TxPower::from` so an arbitrary byte can not be written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CustomTxPower(u8);
+
+impl CustomTxPower {
+  pub fn value(&self) -> u8 {
+    self.0
+  }
+}
+
+/// Levels supported by the chip, from the highest to the lowest
+#[cfg(feature = "nrf52832")]
+pub const LEVELS: [TxPower; 9] = [
+  TxPower::Pos4dBm, TxPower::Pos3dBm, TxPower::ZerodBm, TxPower::Neg4dBm, TxPower::Neg8dBm,
+  TxPower::Neg12dBm, TxPower::Neg16dBm, TxPower::Neg20dBm, TxPower::Neg40dBm,
+];
+
+/// Levels supported by the chip, from the highest to the lowest
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub const LEVELS: [TxPower; 14] = [
+  TxPower::Pos8dBm, TxPower::Pos7dBm, TxPower::Pos6dBm, TxPower::Pos5dBm, TxPower::Pos4dBm,
+  TxPower::Pos3dBm, TxPower::Pos2dBm, TxPower::ZerodBm, TxPower::Neg4dBm, TxPower::Neg8dBm,
+  TxPower::Neg12dBm, TxPower::Neg16dBm, TxPower::Neg20dBm, TxPower::Neg40dBm,
+];
+
 impl TxPower {
   pub fn value(&self) -> u32 {
     match self {
@@ -54,7 +83,171 @@ impl TxPower {
       TxPower::Neg16dBm => 0xf0,
       TxPower::Neg20dBm => 0xec,
       TxPower::Neg40dBm => 0xd8,
-      TxPower::Custom(custom) => *custom as u32,
+      TxPower::Custom(custom) => u32::from(custom.value()),
+    }
+  }
+
+  /// Value read from the TXPOWER register, any value without a variant becomes `Custom`
+  pub fn from(value: u32) -> Self {
+    match value & 0xff {
+      0x08 => TxPower::Pos8dBm,
+      0x07 => TxPower::Pos7dBm,
+      0x06 => TxPower::Pos6dBm,
+      0x05 => TxPower::Pos5dBm,
+      0x04 => TxPower::Pos4dBm,
+      0x03 => TxPower::Pos3dBm,
+      0x02 => TxPower::Pos2dBm,
+      0x00 => TxPower::ZerodBm,
+      0xfc => TxPower::Neg4dBm,
+      0xf8 => TxPower::Neg8dBm,
+      0xf4 => TxPower::Neg12dBm,
+      0xf0 => TxPower::Neg16dBm,
+      0xec => TxPower::Neg20dBm,
+      0xd8 => TxPower::Neg40dBm,
+      custom => TxPower::Custom(CustomTxPower(custom as u8)),
+    }
+  }
+
+  /// Supported level nearest to `dbm`, the lower one when in between two levels
+  pub fn from_dbm(dbm: i8) -> Self {
+    // From the lowest, so the first of two levels as near is the lower one
+    *LEVELS.iter().rev()
+        .min_by_key(|level| (i16::from(level.dbm_unchecked()) - i16::from(dbm)).abs())
+        .unwrap()
+  }
+
+  /// Validate a raw TXPOWER value, None when the chip does not support it
+  pub fn custom(value: u8) -> Option<Self> {
+    let tx_power = TxPower::from(u32::from(value));
+    if tx_power.is_supported() {
+      Some(tx_power)
+    }
+    else {
+      None
+    }
+  }
+
+  /// Whether the chip supports the level
+  pub fn is_supported(&self) -> bool {
+    LEVELS.iter().any(|level| level.value() == self.value())
+  }
+
+  /// Actual output power, None when the chip does not support the level
+  pub fn dbm(&self) -> Option<i8> {
+    if self.is_supported() {
+      Some(self.dbm_unchecked())
     }
+    else {
+      None
+    }
+  }
+
+  /// The level itself when the chip supports it, otherwise the nearest supported one
+  pub fn to_supported(&self) -> Self {
+    match self.dbm() {
+      Some(_) => *self,
+      None => TxPower::from_dbm(self.dbm_unchecked()),
+    }
+  }
+
+  /// Next supported level above this one, None at the highest
+  pub fn higher(&self) -> Option<Self> {
+    let dbm = self.dbm_unchecked();
+    LEVELS.iter().rev().find(|level| level.dbm_unchecked() > dbm).copied()
+  }
+
+  /// Next supported level below this one, None at the lowest
+  pub fn lower(&self) -> Option<Self> {
+    let dbm = self.dbm_unchecked();
+    LEVELS.iter().find(|level| level.dbm_unchecked() < dbm).copied()
+  }
+
+  fn dbm_unchecked(&self) -> i8 {
+    self.value() as u8 as i8
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn from_dbm_exact_level() {
+    assert_eq!(TxPower::from_dbm(0), TxPower::ZerodBm);
+    assert_eq!(TxPower::from_dbm(-20), TxPower::Neg20dBm);
+    assert_eq!(TxPower::from_dbm(4), TxPower::Pos4dBm);
+  }
+
+  #[test]
+  fn from_dbm_nearest_level() {
+    assert_eq!(TxPower::from_dbm(-1), TxPower::ZerodBm);
+    assert_eq!(TxPower::from_dbm(-3), TxPower::Neg4dBm);
+    assert_eq!(TxPower::from_dbm(-30), TxPower::Neg40dBm);
+    assert_eq!(TxPower::from_dbm(-29), TxPower::Neg20dBm);
+    assert_eq!(TxPower::from_dbm(i8::MIN), TxPower::Neg40dBm);
+  }
+
+  #[test]
+  fn from_dbm_lower_level_on_tie() {
+    assert_eq!(TxPower::from_dbm(-2), TxPower::Neg4dBm);
+    assert_eq!(TxPower::from_dbm(-6), TxPower::Neg8dBm);
+  }
+
+  #[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+  #[test]
+  fn from_dbm_above_highest_level() {
+    assert_eq!(TxPower::from_dbm(1), TxPower::ZerodBm);
+    assert_eq!(TxPower::from_dbm(20), TxPower::Pos8dBm);
+    assert_eq!(TxPower::from_dbm(i8::MAX), TxPower::Pos8dBm);
+  }
+
+  #[cfg(feature = "nrf52832")]
+  #[test]
+  fn from_dbm_above_highest_level() {
+    assert_eq!(TxPower::from_dbm(1), TxPower::ZerodBm);
+    assert_eq!(TxPower::from_dbm(2), TxPower::Pos3dBm);
+    assert_eq!(TxPower::from_dbm(8), TxPower::Pos4dBm);
+    assert_eq!(TxPower::from_dbm(i8::MAX), TxPower::Pos4dBm);
+  }
+
+  #[test]
+  fn every_level_reports_its_dbm() {
+    for level in LEVELS.iter() {
+      assert_eq!(TxPower::from_dbm(level.dbm().unwrap()), *level);
+      assert_eq!(TxPower::from(level.value()), *level);
+    }
+  }
+
+  #[test]
+  fn to_supported_keeps_supported_level() {
+    for level in LEVELS.iter() {
+      assert_eq!(level.to_supported(), *level);
+    }
+  }
+
+  #[test]
+  fn to_supported_replaces_custom_value() {
+    let custom = TxPower::from(0xff);
+    assert_eq!(custom, TxPower::Custom(CustomTxPower(0xff)));
+    assert!(!custom.is_supported());
+    assert_eq!(custom.dbm(), None);
+    assert_eq!(custom.to_supported(), TxPower::ZerodBm);
+    assert_eq!(TxPower::from(0xe4).to_supported(), TxPower::Neg20dBm);
+  }
+
+  #[cfg(feature = "nrf52832")]
+  #[test]
+  fn to_supported_replaces_missing_level() {
+    assert!(!TxPower::Pos8dBm.is_supported());
+    assert_eq!(TxPower::Pos8dBm.to_supported(), TxPower::Pos4dBm);
+    assert_eq!(TxPower::Pos2dBm.to_supported(), TxPower::Pos3dBm);
+  }
+
+  #[test]
+  fn custom_only_accepts_supported_values() {
+    assert_eq!(TxPower::custom(0xfc), Some(TxPower::Neg4dBm));
+    assert_eq!(TxPower::custom(0xd8), Some(TxPower::Neg40dBm));
+    assert_eq!(TxPower::custom(0xfe), None);
+    assert_eq!(TxPower::custom(0x7f), None);
   }
 }
@@ -0,0 +1,325 @@
+/*!
+
+Typestate API for the Radio
+
+The state of the radio is part of its type, so operations are only available in the states
+where they make sense, and each transition consumes the radio and returns it in the next state:
+
+```text
+Disabled --enable_rx--> RxRampUp --wait_idle--> RxIdle --start--> Rx --wait_end/stop--> RxIdle
+Disabled --enable_tx--> TxRampUp --wait_idle--> TxIdle --start--> Tx --wait_end/stop--> TxIdle
+any enabled state --disable--> Disabling --wait_disabled--> Disabled
+```
+
+Shortcuts would change the state behind the back of the types, so they are cleared when entering
+this API. The dynamic [`Radio`](../radio/struct.Radio.html) is still available through
+`from_dynamic` and `into_dynamic` when more control is needed.
+
+Misordered calls do not compile, i.e. starting a transfer before the radio is idle:
+
+```compile_fail
+use nrf52_radio::typestate::{Radio, Disabled};
+
+fn start_while_disabled<LFOSC, LFSTAT>(radio: Radio<'static, LFOSC, LFSTAT, Disabled>) {
+  drop(radio.start());
+}
+```
+
+while going through the states in order does:
+
+```no_run
+use nrf52_radio::typestate::{Radio, Disabled};
+
+fn start_when_idle<LFOSC, LFSTAT>(radio: Radio<'static, LFOSC, LFSTAT, Disabled>) {
+  if let Ok(ramping_up) = radio.enable_rx() {
+    drop(ramping_up.wait_idle().start());
+  }
+}
+```
+
+See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf): 6.20.5 Radio states
+
+*/
+
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::hal::target::{RADIO, radio::RegisterBlock};
+use crate::radio::{Radio as DynamicRadio, Error};
+use crate::states::State;
+use crate::shortcuts::Shortcuts;
+use crate::tx_power::TxPower;
+use crate::mode::Mode;
+use crate::packet_config::PacketConfig;
+use crate::frequency::Frequency;
+use crate::base_address::BaseAddresses;
+use crate::logical_address::LogicalAddress;
+use crate::rx_addresses::RxAddresses;
+use crate::dma_buffer::DmaBuffer;
+
+/// Radio disabled, it can be configured
+pub struct Disabled;
+/// Ramping up to receive
+pub struct RxRampUp;
+/// Ready to start receiving
+pub struct RxIdle;
+/// Receiving a packet
+pub struct Rx;
+/// Ramping up to transmit
+pub struct TxRampUp;
+/// Ready to start transmitting
+pub struct TxIdle;
+/// Transmitting a packet
+pub struct Tx;
+/// Waiting for the radio to be disabled
+pub struct Disabling;
+
+/// Result of a transition that can fail, giving back the radio in its original state
+pub type Transition<'a, LFOSC, LFSTAT, FROM, TO, R = RADIO> =
+  core::result::Result<Radio<'a, LFOSC, LFSTAT, TO, R>, (Radio<'a, LFOSC, LFSTAT, FROM, R>, Error)>;
+
+/// Generic over the registers as the dynamic radio, so it can also wrap an emulated one
+pub struct Radio<'a, LFOSC, LFSTAT, STATE, R = RADIO> {
+  radio: DynamicRadio<'a, LFOSC, LFSTAT, R>,
+  _state: PhantomData<STATE>,
+}
+
+impl<'a, LFOSC, LFSTAT, STATE, R> Radio<'a, LFOSC, LFSTAT, STATE, R> where R: Deref<Target=RegisterBlock> {
+  fn into_state<NEXT>(self) -> Radio<'a, LFOSC, LFSTAT, NEXT, R> {
+    Radio { radio: self.radio, _state: PhantomData }
+  }
+
+  /// Escape hatch to the dynamic API, the state is checked at runtime from then on
+  pub fn into_dynamic(self) -> DynamicRadio<'a, LFOSC, LFSTAT, R> {
+    self.radio
+  }
+
+  pub fn get_buffer(&self) -> &[u8] {
+    self.radio.get_buffer()
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, Disabled, R> where R: Deref<Target=RegisterBlock> {
+  /// Enter the typestate API, it requires the radio to be disabled
+  pub fn from_dynamic(radio: DynamicRadio<'a, LFOSC, LFSTAT, R>)
+    -> core::result::Result<Self, DynamicRadio<'a, LFOSC, LFSTAT, R>> {
+
+    match radio.get_state() {
+      State::Disabled => {
+        radio.set_shortcuts(Shortcuts::empty());
+        Ok(Radio { radio, _state: PhantomData })
+      },
+      _ => Err(radio),
+    }
+  }
+
+  pub fn set_tx_power(&self, tx_power: TxPower) -> core::result::Result<&Self, Error> {
+    self.radio.set_tx_power(tx_power)?;
+    Ok(self)
+  }
+
+  pub fn set_mode(&self, mode: Mode) -> &Self {
+    self.radio.set_mode(mode);
+    self
+  }
+
+  pub fn set_packet_config(&self, pcfn: PacketConfig) -> &Self {
+    self.radio.set_packet_config(pcfn);
+    self
+  }
+
+  pub fn set_crc_disabled(&self) -> &Self {
+    self.radio.set_crc_disabled();
+    self
+  }
+
+  pub fn set_crc_8bits(&self, initial: u8, polynomial: u32) -> &Self {
+    self.radio.set_crc_8bits(initial, polynomial);
+    self
+  }
+
+  pub fn set_crc_16bits(&self, initial: u16, polynomial: u32) -> &Self {
+    self.radio.set_crc_16bits(initial, polynomial);
+    self
+  }
+
+  pub fn set_crc_24bits(&self, initial: u32, polynomial: u32) -> &Self {
+    self.radio.set_crc_24bits(initial, polynomial);
+    self
+  }
+
+  pub fn set_base_addresses(&self, addr: BaseAddresses) -> &Self {
+    self.radio.set_base_addresses(addr);
+    self
+  }
+
+  pub fn set_prefixes(&self, prefixes: [u8; 8]) -> &Self {
+    self.radio.set_prefixes(prefixes);
+    self
+  }
+
+  pub fn set_frequency(&self, freq: Frequency) -> &Self {
+    self.radio.set_frequency(freq);
+    self
+  }
+
+  pub fn set_tx_address(&self, address: LogicalAddress) -> &Self {
+    self.radio.set_tx_address(address);
+    self
+  }
+
+  pub fn set_rx_addresses(&self, addresses: RxAddresses) -> &Self {
+    self.radio.set_rx_addresses(addresses);
+    self
+  }
+
+  /// Buffers can only be swapped while the radio is disabled
+  pub fn swap_buffer(&mut self, buffer: &mut Option<DmaBuffer<'a>>) -> core::result::Result<(), Error> {
+    self.radio.swap_buffer(buffer)
+  }
+
+  pub fn get_buffer_mut(&mut self) -> &mut [u8] {
+    self.radio.get_buffer_mut()
+  }
+
+  pub fn enable_rx(mut self) -> Transition<'a, LFOSC, LFSTAT, Disabled, RxRampUp, R> {
+    match self.radio.enable_rx() {
+      Ok(()) => Ok(self.into_state()),
+      Err(error) => Err((self, error)),
+    }
+  }
+
+  pub fn enable_tx(mut self) -> Transition<'a, LFOSC, LFSTAT, Disabled, TxRampUp, R> {
+    match self.radio.enable_tx() {
+      Ok(()) => Ok(self.into_state()),
+      Err(error) => Err((self, error)),
+    }
+  }
+
+  pub fn free(self) -> R {
+    self.radio.free()
+  }
+}
+
+macro_rules! ramp_up {
+  ( $ramp_up:ident, $idle:ident ) => {
+    impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, $ramp_up, R> where R: Deref<Target=RegisterBlock> {
+      /// Returns the radio back while it is still ramping up
+      pub fn try_idle(self) -> core::result::Result<Radio<'a, LFOSC, LFSTAT, $idle, R>, Self> {
+        match self.radio.wait_idle() {
+          Ok(()) => Ok(self.into_state()),
+          Err(_) => Err(self),
+        }
+      }
+
+      pub fn wait_idle(self) -> Radio<'a, LFOSC, LFSTAT, $idle, R> {
+        let mut radio = self;
+        loop {
+          match radio.try_idle() {
+            Ok(idle) => return idle,
+            Err(ramping) => radio = ramping,
+          }
+        }
+      }
+
+      pub fn disable(self) -> Radio<'a, LFOSC, LFSTAT, Disabling, R> {
+        self.radio.disable();
+        self.into_state()
+      }
+    }
+  };
+}
+
+macro_rules! idle {
+  ( $idle:ident, $active:ident ) => {
+    impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, $idle, R> where R: Deref<Target=RegisterBlock> {
+      pub fn start(self) -> Transition<'a, LFOSC, LFSTAT, $idle, $active, R> {
+        match self.radio.start() {
+          Ok(()) => Ok(self.into_state()),
+          Err(error) => Err((self, error)),
+        }
+      }
+
+      pub fn disable(self) -> Radio<'a, LFOSC, LFSTAT, Disabling, R> {
+        self.radio.disable();
+        self.into_state()
+      }
+    }
+  };
+}
+
+macro_rules! active {
+  ( $active:ident, $idle:ident ) => {
+    impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, $active, R> where R: Deref<Target=RegisterBlock> {
+      /// Returns the radio back while the packet is still in progress
+      pub fn try_end(self) -> core::result::Result<Radio<'a, LFOSC, LFSTAT, $idle, R>, Self> {
+        match self.radio.wait_end_or_disable() {
+          Ok(()) => Ok(self.into_state()),
+          Err(_) => Err(self),
+        }
+      }
+
+      pub fn wait_end(self) -> Radio<'a, LFOSC, LFSTAT, $idle, R> {
+        let mut radio = self;
+        loop {
+          match radio.try_end() {
+            Ok(idle) => return idle,
+            Err(active) => radio = active,
+          }
+        }
+      }
+
+      pub fn stop(self) -> Radio<'a, LFOSC, LFSTAT, $idle, R> {
+        // The state matches the type, so the radio accepts the stop
+        drop(self.radio.stop());
+        self.into_state()
+      }
+
+      pub fn disable(self) -> Radio<'a, LFOSC, LFSTAT, Disabling, R> {
+        self.radio.disable();
+        self.into_state()
+      }
+    }
+  };
+}
+
+ramp_up!(RxRampUp, RxIdle);
+ramp_up!(TxRampUp, TxIdle);
+idle!(RxIdle, Rx);
+idle!(TxIdle, Tx);
+active!(Rx, RxIdle);
+active!(Tx, TxIdle);
+
+impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, RxIdle, R> where R: Deref<Target=RegisterBlock> {
+  pub fn is_crc_ok(&self) -> bool {
+    self.radio.is_crc_ok()
+  }
+
+  pub fn get_received_address(&self) -> LogicalAddress {
+    self.radio.get_received_address()
+  }
+
+  pub fn get_received_crc(&self) -> u32 {
+    self.radio.get_received_crc()
+  }
+}
+
+impl<'a, LFOSC, LFSTAT, R> Radio<'a, LFOSC, LFSTAT, Disabling, R> where R: Deref<Target=RegisterBlock> {
+  /// Returns the radio back while it is still being disabled
+  pub fn try_disabled(self) -> core::result::Result<Radio<'a, LFOSC, LFSTAT, Disabled, R>, Self> {
+    match self.radio.wait_disabled() {
+      Ok(()) => Ok(self.into_state()),
+      Err(_) => Err(self),
+    }
+  }
+
+  pub fn wait_disabled(self) -> Radio<'a, LFOSC, LFSTAT, Disabled, R> {
+    let mut radio = self;
+    loop {
+      match radio.try_disabled() {
+        Ok(disabled) => return disabled,
+        Err(disabling) => radio = disabling,
+      }
+    }
+  }
+}
@@ -0,0 +1,70 @@
+/*!
+
+Generic packet radio traits
+
+They follow the traits of the community [radio](https://github.com/rust-iot/radio) crate, so drivers
+for different radios, i.e. the nrf52 RADIO or a nRF24L01+ over SPI, can be swapped behind one interface.
+They are a copy rather than the `radio` traits themselves, see the README for the reasons.
+
+The operations are started and then polled until they finish, which maps to the `nb` based
+drivers without blocking.
+
+*/
+
+#![no_std]
+
+/// Send packets
+pub trait Transmit {
+  type Error;
+
+  /// Start sending a packet with `data` as the payload
+  fn start_transmit(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+  /// Whether the transmission finished
+  fn check_transmit(&mut self) -> Result<bool, Self::Error>;
+}
+
+/// Information about a received packet
+pub trait ReceiveInfo {
+  /// Received signal strength in dBm
+  fn rssi(&self) -> i16;
+}
+
+/// Receive packets
+pub trait Receive {
+  type Error;
+  type Info: ReceiveInfo;
+
+  /// Start listening for a packet
+  fn start_receive(&mut self) -> Result<(), Self::Error>;
+
+  /// Whether a packet was received. With `restart` the radio listens again after an invalid packet.
+  fn check_receive(&mut self, restart: bool) -> Result<bool, Self::Error>;
+
+  /// Copy the payload of the received packet into `buffer` and return its length
+  fn get_received(&mut self, info: &mut Self::Info, buffer: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+/// Sample the received signal strength
+pub trait Rssi {
+  type Error;
+
+  /// Current received signal strength in dBm
+  fn poll_rssi(&mut self) -> Result<i16, Self::Error>;
+}
+
+/// Select the channel
+pub trait Channel {
+  type Channel;
+  type Error;
+
+  fn set_channel(&mut self, channel: &Self::Channel) -> Result<(), Self::Error>;
+}
+
+/// Select the output power
+pub trait Power {
+  type Error;
+
+  /// Output power in dBm, the driver picks the nearest level it supports
+  fn set_power(&mut self, power: i8) -> Result<(), Self::Error>;
+}
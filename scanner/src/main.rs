@@ -0,0 +1,118 @@
+#![no_main]
+#![no_std]
+
+use cortex_m_rt::entry;
+
+#[allow(unused_imports)]
+//use panic_halt;
+use panic_semihosting as _;
+
+use core::fmt::Write;
+use nb::block;
+
+use nrf52840_hal as hal;
+use hal::timer::TimerExt;
+use hal::clocks::ClocksExt;
+use hal::{Uarte, target::UARTE0};
+
+use nrf52840_mdk::{leds_welcome, Board};
+
+use nrf52_radio::{Radio, Result as RadioResult};
+use nrf52_radio::mode::Mode;
+use nrf52_radio::frequency::{Frequency, Map};
+use nrf52_radio::packet_config::PacketConfig;
+use nrf52_radio::dma_buffer::DmaBuffer;
+use nrf52_radio::states::State as RadioState;
+
+/// Number of channels in each frequency map
+const NUM_CHANNELS: usize = 101;
+
+/// Number of RSSI samples taken on every channel, the strongest one is reported
+const SAMPLES_PER_CHANNEL: usize = 16;
+
+/// Whether to sweep also the 2360 MHz frequency map
+const SCAN_LOW_MAP: bool = false;
+
+
+#[entry]
+fn main() -> ! {
+    let mut board = Board::take().unwrap();
+    let mut timer = board.TIMER0.constrain();
+
+    drop(board.uart_daplink.write_str("Initialising ...\n"));
+
+    leds_welcome(&mut board.leds, &mut timer);
+
+    let clocks = board.CLOCK.constrain().enable_ext_hfosc();
+
+    // The radio needs a buffer to be enabled, although no packet is expected
+    let mut buffer = [0x00u8; 2];
+
+    let mut radio = Radio::new(board.RADIO, &clocks);
+    radio
+        .set_mode(Mode::Nrf1Mbit)
+        .set_packet_config(PacketConfig::default().with_max_bytes(0))
+        .enable_power();
+    radio.swap_buffer(&mut Some(DmaBuffer::new(&mut buffer).unwrap())).unwrap();
+
+    let mut spectrum = [None; NUM_CHANNELS];
+
+    drop(board.uart_daplink.write_str("Starting ...\n"));
+
+    loop {
+        board.leds.green.on();
+        sweep(&mut radio, Map::Default2400MHz, &mut spectrum);
+        board.leds.green.off();
+        print_spectrum(2400, &spectrum, &mut board.uart_daplink);
+
+        if SCAN_LOW_MAP {
+            board.leds.blue.on();
+            sweep(&mut radio, Map::Low2360MHz, &mut spectrum);
+            board.leds.blue.off();
+            print_spectrum(2360, &spectrum, &mut board.uart_daplink);
+        }
+    }
+}
+
+/// Strongest RSSI in -dBm for every channel of a frequency map, or None if it could not be sampled
+fn sweep<LFOSC, LFSTAT>(radio: &mut Radio<LFOSC, LFSTAT>, map: Map, spectrum: &mut [Option<u8>]) {
+    for (channel, rssi) in spectrum.iter_mut().enumerate() {
+        *rssi = Frequency::from_channel(map, channel as u8).ok()
+            .and_then(|frequency| sample_channel(radio, frequency).ok());
+    }
+}
+
+fn sample_channel<LFOSC, LFSTAT>(radio: &mut Radio<LFOSC, LFSTAT>, frequency: Frequency) -> RadioResult<u8> {
+    match radio.get_state() {
+        RadioState::Disabled => {},
+        _ => {
+            radio.disable();
+            block!(radio.wait_disabled())?;
+        }
+    }
+
+    radio.set_frequency(frequency);
+    radio.enable_rx()?;
+    block!(radio.wait_idle())?;
+    radio.start()?;
+
+    // RSSI is given in -dBm, so the strongest signal is the lowest value
+    let mut strongest = u8::max_value();
+    for _ in 0..SAMPLES_PER_CHANNEL {
+        radio.start_rssi()?;
+        strongest = strongest.min(block!(radio.wait_rssi())?);
+    }
+    Ok(strongest)
+}
+
+/// A channel that could not be sampled is printed as `?`
+fn print_spectrum(map: u16, spectrum: &[Option<u8>], uarte: &mut Uarte<UARTE0>) {
+    drop(uarte.write_fmt(format_args!("{}", map)));
+    for rssi in spectrum.iter() {
+        match rssi {
+            Some(rssi) => drop(uarte.write_fmt(format_args!(" -{}", *rssi))),
+            None => drop(uarte.write_str(" ?")),
+        }
+    }
+    drop(uarte.write_char('\n'));
+}
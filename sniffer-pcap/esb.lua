@@ -0,0 +1,57 @@
+-- Wireshark dissector for the ESB packets captured by the sniffer, with the LINKTYPE_USER0 link type.
+-- Load it with `wireshark -X lua_script:esb.lua` or copy it into the Wireshark plugins folder.
+
+local esb = Proto("esb", "Enhanced ShockBurst")
+
+local flag_names = { [0] = "No", [1] = "Yes" }
+
+local f = esb.fields
+f.version = ProtoField.uint8("esb.version", "Version")
+f.flags = ProtoField.uint8("esb.flags", "Flags", base.HEX)
+f.crc_ok = ProtoField.uint8("esb.crc_ok", "CRC ok", base.DEC, flag_names, 0x01)
+f.no_ack = ProtoField.uint8("esb.no_ack", "No ack", base.DEC, flag_names, 0x02)
+f.has_logical_address = ProtoField.uint8("esb.has_logical_address", "Logical address known", base.DEC, flag_names, 0x04)
+f.frequency = ProtoField.uint16("esb.frequency", "Frequency (MHz)")
+f.rssi = ProtoField.int8("esb.rssi", "RSSI (dBm)")
+f.logical_address = ProtoField.uint8("esb.logical_address", "Logical address")
+f.address = ProtoField.bytes("esb.address", "Address")
+f.length = ProtoField.uint8("esb.length", "Length")
+f.pid = ProtoField.uint8("esb.pid", "PID")
+f.payload = ProtoField.bytes("esb.payload", "Payload")
+
+local PSEUDO_HEADER_LENGTH = 14
+
+function esb.dissector(buffer, pinfo, tree)
+  if buffer:len() < PSEUDO_HEADER_LENGTH then
+    return 0
+  end
+  pinfo.cols.protocol = "ESB"
+
+  local subtree = tree:add(esb, buffer(), "Enhanced ShockBurst")
+  subtree:add(f.version, buffer(0, 1))
+  local flags = subtree:add(f.flags, buffer(1, 1))
+  flags:add(f.crc_ok, buffer(1, 1))
+  flags:add(f.no_ack, buffer(1, 1))
+  flags:add(f.has_logical_address, buffer(1, 1))
+  subtree:add_le(f.frequency, buffer(2, 2))
+  subtree:add(f.rssi, buffer(4, 1))
+  if bit.band(buffer(1, 1):uint(), 0x04) ~= 0 then
+    subtree:add(f.logical_address, buffer(5, 1))
+  end
+  local address_length = buffer(6, 1):uint()
+  if address_length > 0 then
+    subtree:add(f.address, buffer(7, address_length))
+  end
+  subtree:add(f.length, buffer(12, 1))
+  subtree:add(f.pid, buffer(13, 1))
+  if buffer:len() > PSEUDO_HEADER_LENGTH then
+    subtree:add(f.payload, buffer(PSEUDO_HEADER_LENGTH))
+  end
+
+  local address = address_length > 0 and tostring(buffer(7, address_length):bytes()) or "?"
+  local crc = bit.band(buffer(1, 1):uint(), 0x01) ~= 0 and "" or " [CRC error]"
+  pinfo.cols.info = string.format("%d MHz %s PID %d%s", buffer(2, 2):le_uint(), address, buffer(13, 1):uint(), crc)
+  return buffer:len()
+end
+
+DissectorTable.get("wtap_encap"):add(wtap.USER0, esb)
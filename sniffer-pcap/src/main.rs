@@ -0,0 +1,134 @@
+//! Saves the pcap capture that the sniffer sends through the UART into a file
+//!
+//! It skips whatever comes before the pcap global header, and moves the timestamps of the packets,
+//! which count from the start of the sniffer, to the host clock: the first packet gets the time
+//! when the host received it, and the next ones keep their distance to it as measured by the sniffer.
+//! The sniffer sends the header again after the text that echoes the commands, so the capture
+//! continues from there when a record does not make sense.
+//! The output can be `-` to pipe the capture into Wireshark while it is running.
+
+use std::convert::TryInto;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 0xa1b2c3d4 in little endian
+const MAGIC: [u8; 4] = [0xd4, 0xc3, 0xb2, 0xa1];
+const LINKTYPE_USER0: u32 = 147;
+
+const GLOBAL_HEADER_LENGTH: usize = 24;
+const RECORD_HEADER_LENGTH: usize = 16;
+const PSEUDO_HEADER_LENGTH: usize = 14;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <serial device> <capture.pcap | ->", args[0]);
+        process::exit(1);
+    }
+
+    if let Err(error) = capture(&args[1], &args[2]) {
+        eprintln!("\nError: {}", error);
+        process::exit(1);
+    }
+}
+
+fn capture(device: &str, path: &str) -> io::Result<()> {
+    let mut input = BufReader::new(File::open(device)?);
+    let output: Box<dyn Write> = match path {
+        "-" => Box::new(io::stdout()),
+        _ => Box::new(File::create(path)?),
+    };
+    let mut output = BufWriter::new(output);
+
+    eprintln!("Waiting for the sniffer ...");
+    let header = sync(&mut input)?;
+    let snaplen = check_header(&header)?;
+    output.write_all(&header)?;
+    output.flush()?;
+
+    let mut record = [0u8; RECORD_HEADER_LENGTH];
+    let mut data = vec![0u8; snaplen];
+    let mut packets = 0u64;
+    // Host and sniffer times of the first packet
+    let mut first: Option<(u64, u64)> = None;
+    loop {
+        match input.read_exact(&mut record) {
+            Ok(()) => {},
+            Err(ref error) if error.kind() == ErrorKind::UnexpectedEof => break,
+            Err(error) => return Err(error),
+        }
+        let length = read_u32(&record[8..12]) as usize;
+        if length < PSEUDO_HEADER_LENGTH || length > snaplen {
+            // The header may have started within the bytes taken as a record
+            let header = sync(&mut (&record[..]).chain(&mut input))?;
+            if check_header(&header)? != snaplen {
+                return Err(invalid_data("The snapshot length changed".to_string()));
+            }
+            continue;
+        }
+        let data = &mut data[..length];
+        input.read_exact(data)?;
+
+        let device_timestamp = u64::from(read_u32(&record[0..4])) * 1_000_000 + u64::from(read_u32(&record[4..8]));
+        let (host_start, device_start) = *first.get_or_insert_with(|| (now(), device_timestamp));
+        let timestamp = host_start + device_timestamp.saturating_sub(device_start);
+        record[0..4].copy_from_slice(&((timestamp / 1_000_000) as u32).to_le_bytes());
+        record[4..8].copy_from_slice(&((timestamp % 1_000_000) as u32).to_le_bytes());
+        output.write_all(&record)?;
+        output.write_all(data)?;
+        output.flush()?;
+
+        packets += 1;
+        eprint!("\r{} packets", packets);
+    }
+    eprintln!();
+    Ok(())
+}
+
+/// Microseconds since the Unix epoch
+fn now() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    now.as_secs() * 1_000_000 + u64::from(now.subsec_micros())
+}
+
+/// Skip the bytes until the magic number, and read the rest of the global header
+fn sync<R: Read>(input: &mut R) -> io::Result<[u8; GLOBAL_HEADER_LENGTH]> {
+    let mut byte = [0u8];
+    let mut matched = 0;
+    while matched < MAGIC.len() {
+        input.read_exact(&mut byte)?;
+        matched = if byte[0] == MAGIC[matched] {
+            matched + 1
+        }
+        else if byte[0] == MAGIC[0] {
+            1
+        }
+        else {
+            0
+        };
+    }
+    let mut header = [0u8; GLOBAL_HEADER_LENGTH];
+    header[..MAGIC.len()].copy_from_slice(&MAGIC);
+    input.read_exact(&mut header[MAGIC.len()..])?;
+    Ok(header)
+}
+
+/// Snapshot length of a global header from the sniffer
+fn check_header(header: &[u8; GLOBAL_HEADER_LENGTH]) -> io::Result<usize> {
+    let linktype = read_u32(&header[20..24]);
+    if linktype != LINKTYPE_USER0 {
+        return Err(invalid_data(format!("Unexpected link type {}", linktype)));
+    }
+    Ok(read_u32(&header[16..20]) as usize)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
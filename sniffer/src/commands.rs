@@ -0,0 +1,164 @@
+//! Commands received through the UART to change the sniffer settings at runtime
+//!
+//! One command per line, with the same syntax as the configuration that is echoed back:
+//!
+//! ```text
+//! mode <esb | promiscuous | discovery>
+//! channel <mhz>                  2360 to 2500
+//! rate <1m | 2m | ble1m | ble2m>
+//! base <8 hex digits>            base address
+//! prefixes <16 hex digits>       prefixes of the logical addresses 0 to 7
+//! payload fixed <length>         up to 32 bytes
+//! payload dynamic <max length>   up to 252 bytes
+//! crc <0 | 8 | 16>
+//! output <text | pcap>
+//! config                         only echo the configuration
+//! ```
+//!
+//! The promiscuous and the discovery modes listen to any address with their own address setup,
+//! so they refuse `crc 0` and payloads longer than 32 bytes, and the promiscuous mode refuses `base` and `prefixes`.
+
+use core::str::{self, SplitWhitespace};
+
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::mode::Mode;
+
+use nrf52_esb::frame::Crc;
+use nrf52_esb::promiscuous;
+use nrf52_esb::protocol::Protocol;
+
+use crate::config::{Config, Output, SnifferMode, MODES, SNIFFER_MODES};
+
+const MAX_FIXED_PAYLOAD_LENGTH: u8 = 32;
+const MAX_DYNAMIC_PAYLOAD_LENGTH: u8 = 252;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The line is not valid UTF-8
+    InvalidText,
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    TooManyArguments,
+    /// The command does not apply to the current sniffer mode
+    UnsupportedCommand,
+}
+
+pub type Result<A> = core::result::Result<A, Error>;
+
+#[derive(Clone, Copy)]
+pub enum Command {
+    Mode(SnifferMode),
+    Channel(Frequency),
+    Rate(Mode),
+    Base([u8; 4]),
+    Prefixes([u8; 8]),
+    Payload(Protocol),
+    Crc(Crc),
+    Output(Output),
+    Config,
+}
+
+impl Command {
+    pub fn parse(line: &[u8]) -> Result<Command> {
+        let line = str::from_utf8(line).map_err(|_| Error::InvalidText)?;
+        let mut args = line.split_whitespace();
+        let command = match next(&mut args)? {
+            "mode" => {
+                let name = next(&mut args)?;
+                let sniffer_mode = SNIFFER_MODES.iter().find(|(n, _)| *n == name).ok_or(Error::InvalidArgument)?;
+                Command::Mode(sniffer_mode.1)
+            },
+            "channel" => {
+                let mhz = next(&mut args)?.parse().map_err(|_| Error::InvalidArgument)?;
+                Command::Channel(Frequency::from_mhz(mhz).map_err(|_| Error::InvalidArgument)?)
+            },
+            "rate" => {
+                let name = next(&mut args)?;
+                let mode = MODES.iter().find(|(n, _)| *n == name).ok_or(Error::InvalidArgument)?;
+                Command::Rate(mode.1)
+            },
+            "base" => {
+                let mut base = [0u8; 4];
+                parse_hex(next(&mut args)?, &mut base)?;
+                Command::Base(base)
+            },
+            "prefixes" => {
+                let mut prefixes = [0u8; 8];
+                parse_hex(next(&mut args)?, &mut prefixes)?;
+                Command::Prefixes(prefixes)
+            },
+            "payload" => {
+                let kind = next(&mut args)?;
+                let length: u8 = next(&mut args)?.parse().map_err(|_| Error::InvalidArgument)?;
+                match kind {
+                    "fixed" if length <= MAX_FIXED_PAYLOAD_LENGTH =>
+                        Command::Payload(Protocol::fixed_payload_length(length)),
+                    "dynamic" if length <= MAX_DYNAMIC_PAYLOAD_LENGTH =>
+                        Command::Payload(Protocol::dynamic_payload_length(length)),
+                    _ => return Err(Error::InvalidArgument),
+                }
+            },
+            "crc" => match next(&mut args)? {
+                "0" => Command::Crc(Crc::Disabled),
+                "8" => Command::Crc(Crc::OneByte),
+                "16" => Command::Crc(Crc::TwoBytes),
+                _ => return Err(Error::InvalidArgument),
+            },
+            "output" => match next(&mut args)? {
+                "text" => Command::Output(Output::Text),
+                "pcap" => Command::Output(Output::Pcap),
+                _ => return Err(Error::InvalidArgument),
+            },
+            "config" => Command::Config,
+            _ => return Err(Error::UnknownCommand),
+        };
+        match args.next() {
+            Some(_) => Err(Error::TooManyArguments),
+            None => Ok(command),
+        }
+    }
+
+    /// The promiscuous and the discovery modes listen to any address, and need a CRC and short payloads.
+    /// The promiscuous mode has no use for the configured addresses.
+    pub fn check(&self, config: &Config) -> Result<()> {
+        let mut next = *config;
+        self.apply(&mut next);
+        match (*self, next.sniffer_mode) {
+            (_, SnifferMode::Esb) => Ok(()),
+            (Command::Base(_), SnifferMode::Promiscuous) | (Command::Prefixes(_), SnifferMode::Promiscuous) =>
+                Err(Error::UnsupportedCommand),
+            _ => promiscuous::check_protocol(next.protocol, next.crc).map_err(|_| Error::InvalidArgument),
+        }
+    }
+
+    pub fn apply(self, config: &mut Config) {
+        match self {
+            Command::Mode(sniffer_mode) => config.sniffer_mode = sniffer_mode,
+            Command::Channel(frequency) => config.frequency = frequency,
+            Command::Rate(mode) => config.mode = mode,
+            Command::Base(base) => config.base_address = base,
+            Command::Prefixes(prefixes) => config.prefixes = prefixes,
+            Command::Payload(protocol) => config.protocol = protocol,
+            Command::Crc(crc) => config.crc = crc,
+            Command::Output(output) => config.output = output,
+            Command::Config => {},
+        }
+    }
+}
+
+fn next<'a>(args: &mut SplitWhitespace<'a>) -> Result<&'a str> {
+    args.next().ok_or(Error::MissingArgument)
+}
+
+/// Exactly two hex digits for each byte
+fn parse_hex(text: &str, bytes: &mut [u8]) -> Result<()> {
+    if !text.is_ascii() || text.len() != bytes.len() * 2 {
+        return Err(Error::InvalidArgument);
+    }
+    for (index, byte) in bytes.iter_mut().enumerate() {
+        let digits = &text[index * 2..index * 2 + 2];
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| Error::InvalidArgument)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Sniffer settings that can be changed at runtime through the UART commands
+
+use core::fmt::Write;
+
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::mode::Mode;
+
+use nrf52_esb::frame::Crc;
+use nrf52_esb::protocol::Protocol;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnifferMode {
+    /// Print the packets to the configured addresses on a fixed channel
+    Esb,
+    /// Listen to any address, to discover the addresses of unknown units
+    Promiscuous,
+    /// Sweep all the channels, and report which ones carry packets, and to which addresses
+    Discovery,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    /// A line of text for each packet
+    Text,
+    /// A pcap capture, to be saved with the `sniffer-pcap` host tool
+    Pcap,
+}
+
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub sniffer_mode: SnifferMode,
+    pub frequency: Frequency,
+    pub mode: Mode,
+    pub base_address: [u8; 4],
+    pub prefixes: [u8; 8],
+    pub protocol: Protocol,
+    pub crc: Crc,
+    pub output: Output,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sniffer_mode: SnifferMode::Esb,
+            frequency: Frequency::from_2400mhz_channel(78).unwrap(),
+            mode: Mode::Nrf2Mbit,
+            base_address: [0xa0, 0xb1, 0xc2, 0xd3],
+            prefixes: [0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7],
+            protocol: Protocol::fixed_payload_length(32),
+            crc: Crc::TwoBytes,
+            output: Output::Text,
+        }
+    }
+}
+
+impl Config {
+    /// Address in the same order as the promiscuous candidates, the base address followed by the prefix
+    pub fn address(&self, logical_address: usize) -> [u8; 5] {
+        let mut address = [0u8; 5];
+        address[..4].copy_from_slice(&self.base_address);
+        address[4] = self.prefixes[logical_address];
+        address
+    }
+
+    /// A single line with the same syntax as the commands
+    pub fn print<W: Write>(&self, w: &mut W) {
+        drop(w.write_fmt(format_args!("mode {} channel {} rate {} base ",
+                                      sniffer_mode_name(self.sniffer_mode), self.frequency.mhz(), mode_name(self.mode))));
+        print_hex(&self.base_address, w);
+        drop(w.write_str(" prefixes "));
+        print_hex(&self.prefixes, w);
+        let (payload, length) = match self.protocol {
+            Protocol::FixedPayloadLength(length) => ("fixed", length),
+            Protocol::DynamicPayloadLength(max_length) => ("dynamic", max_length),
+        };
+        let crc = match self.crc {
+            Crc::Disabled => 0,
+            Crc::OneByte => 8,
+            Crc::TwoBytes => 16,
+        };
+        let output = match self.output {
+            Output::Text => "text",
+            Output::Pcap => "pcap",
+        };
+        drop(w.write_fmt(format_args!(" payload {} {} crc {} output {}\n", payload, length, crc, output)));
+    }
+}
+
+/// Names of the sniffer modes in the `mode` command
+pub const SNIFFER_MODES: [(&str, SnifferMode); 3] = [
+    ("esb", SnifferMode::Esb),
+    ("promiscuous", SnifferMode::Promiscuous),
+    ("discovery", SnifferMode::Discovery),
+];
+
+/// Names of the data rates in the `rate` command
+pub const MODES: [(&str, Mode); 4] = [
+    ("1m", Mode::Nrf1Mbit),
+    ("2m", Mode::Nrf2Mbit),
+    ("ble1m", Mode::Ble1Mbit),
+    ("ble2m", Mode::Ble2Mbit),
+];
+
+fn mode_name(mode: Mode) -> &'static str {
+    MODES.iter().find(|(_, m)| *m == mode).map_or("?", |(name, _)| *name)
+}
+
+fn sniffer_mode_name(sniffer_mode: SnifferMode) -> &'static str {
+    SNIFFER_MODES.iter().find(|(_, m)| *m == sniffer_mode).map_or("?", |(name, _)| *name)
+}
+
+fn print_hex<W: Write>(bytes: &[u8], w: &mut W) {
+    for b in bytes.iter() {
+        drop(w.write_fmt(format_args!("{:02x}", *b)));
+    }
+}
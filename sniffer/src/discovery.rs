@@ -0,0 +1,140 @@
+//! Channel and address discovery
+//!
+//! The sniffer dwells on every channel from 2360 MHz to 2500 MHz, which covers both frequency maps,
+//! and listens in promiscuous mode, so it finds the units whatever their address. After every sweep
+//! it reports the channels with traffic, with the packet rate of each address, and the logical address
+//! it stands for when it is one of the configured addresses.
+
+use core::fmt::Write;
+
+use nrf52_radio::frequency::Frequency;
+
+use nrf52_esb::promiscuous::MAX_ADDRESS_LENGTH;
+
+use crate::config::Config;
+
+const FIRST_MHZ: u16 = 2360;
+const LAST_MHZ: u16 = 2500;
+
+/// Channels in both frequency maps, 1 MHz apart
+pub const CHANNELS: usize = (LAST_MHZ - FIRST_MHZ + 1) as usize;
+
+/// Addresses counted apart on each channel, the packets to any other address are counted together
+const ADDRESSES_PER_CHANNEL: usize = 4;
+
+const LOGICAL_ADDRESSES: usize = 8;
+
+#[derive(Clone, Copy, Default)]
+struct AddressStats {
+    address: [u8; MAX_ADDRESS_LENGTH],
+    address_length: usize,
+    packets: u32,
+}
+
+impl AddressStats {
+    fn address(&self) -> &[u8] {
+        &self.address[..self.address_length]
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ChannelStats {
+    addresses: [AddressStats; ADDRESSES_PER_CHANNEL],
+    /// Packets to the addresses that did not fit
+    others: u32,
+    dwell_us: u64,
+}
+
+impl ChannelStats {
+    fn total(&self) -> u32 {
+        self.addresses.iter().map(|stats| stats.packets).sum::<u32>() + self.others
+    }
+
+    fn record(&mut self, address: &[u8]) {
+        let found = self.addresses.iter_mut()
+            .find(|stats| stats.packets == 0 || stats.address() == address);
+        match found {
+            Some(stats) => {
+                stats.address[..address.len()].copy_from_slice(address);
+                stats.address_length = address.len();
+                stats.packets += 1;
+            },
+            None => self.others += 1,
+        }
+    }
+
+    /// Packets per second multiplied by 10, to print one decimal
+    fn rate_x10(&self, packets: u32) -> u64 {
+        match self.dwell_us {
+            0 => 0,
+            dwell_us => u64::from(packets) * 10_000_000 / dwell_us,
+        }
+    }
+}
+
+pub struct Discovery {
+    stats: [ChannelStats; CHANNELS],
+    index: usize,
+    sweeps: u32,
+}
+
+impl Discovery {
+    pub fn new() -> Self {
+        Discovery {
+            stats: [ChannelStats::default(); CHANNELS],
+            index: 0,
+            sweeps: 0,
+        }
+    }
+
+    /// Channel to dwell on
+    pub fn frequency(&self) -> Frequency {
+        Frequency::from_mhz(FIRST_MHZ + self.index as u16).unwrap()
+    }
+
+    /// A frame with a valid CRC was received on the current channel, the address in on-air order
+    pub fn record(&mut self, address: &[u8]) {
+        self.stats[self.index].record(address);
+    }
+
+    /// Account the time spent on the current channel and move to the next one.
+    /// Returns true when a sweep is complete.
+    pub fn next(&mut self, dwell_us: u32) -> bool {
+        self.stats[self.index].dwell_us += u64::from(dwell_us);
+        self.index = (self.index + 1) % CHANNELS;
+        if self.index == 0 {
+            self.sweeps += 1;
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    /// Channels with traffic since the start, with the packet rate of each address.
+    /// The configured addresses are followed by their logical address.
+    pub fn report<W: Write>(&self, config: &Config, w: &mut W) {
+        drop(w.write_fmt(format_args!("Sweep {}\n", self.sweeps)));
+        for (index, stats) in self.stats.iter().enumerate().filter(|(_, stats)| stats.total() > 0) {
+            let frequency = Frequency::from_mhz(FIRST_MHZ + index as u16).unwrap();
+            drop(w.write_fmt(format_args!("{} MHz {:?}:", frequency.mhz(), frequency)));
+            for address in stats.addresses.iter().filter(|address| address.packets > 0) {
+                drop(w.write_char(' '));
+                for b in address.address().iter() {
+                    drop(w.write_fmt(format_args!("{:02x}", *b)));
+                }
+                let logical_address = (0..LOGICAL_ADDRESSES).find(|index| config.address(*index)[..] == *address.address());
+                if let Some(logical_address) = logical_address {
+                    drop(w.write_fmt(format_args!(" [{}]", logical_address)));
+                }
+                let rate = stats.rate_x10(address.packets);
+                drop(w.write_fmt(format_args!(" {} packets {}.{} pkt/s", address.packets, rate / 10, rate % 10)));
+            }
+            if stats.others > 0 {
+                let rate = stats.rate_x10(stats.others);
+                drop(w.write_fmt(format_args!(" others {} packets {}.{} pkt/s", stats.others, rate / 10, rate % 10)));
+            }
+            drop(w.write_char('\n'));
+        }
+    }
+}
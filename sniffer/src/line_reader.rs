@@ -0,0 +1,125 @@
+//! Lines received through the UART, without blocking the radio loops
+//!
+//! The UARTE receives continuously through EasyDMA into two buffers, switching from one to the other
+//! with the `ENDRX_STARTRX` shortcut, while the next buffer is set on every `RXSTARTED` event.
+//! EasyDMA stores the bytes as they arrive, and the buffers are cleared before being reused,
+//! so any non-zero byte is new. Once a buffer is complete all its bytes are valid.
+//!
+//! The transmission is left to the HAL `Uarte`, which owns the peripheral, but never touches the reception.
+//!
+//! See [Product Specification](https://infocenter.nordicsemi.com/pdf/nRF52840_PS_v1.0.pdf):
+//! 6.34 UARTE — Universal asynchronous receiver/transmitter with EasyDMA
+
+use core::ptr;
+
+use nrf52840_hal::target::{uarte0, UARTE0};
+
+/// Bytes received into each buffer before switching to the other one.
+/// The reader needs to be polled before a whole buffer is received, 22 ms at 115200 bauds.
+pub const BUFFER_LENGTH: usize = 256;
+
+/// Longest line, the longer ones are dropped
+pub const LINE_LENGTH: usize = 64;
+
+pub struct LineReader<'a> {
+    uarte: &'static uarte0::RegisterBlock,
+    buffers: &'a mut [[u8; BUFFER_LENGTH]; 2],
+    /// Buffer to set when the reception of the other one starts
+    next: usize,
+    /// Buffer being read
+    current: usize,
+    index: usize,
+    /// Buffers completely received and not read yet
+    ended: usize,
+    line: [u8; LINE_LENGTH],
+    line_length: usize,
+    line_complete: bool,
+    overflow: bool,
+}
+
+impl<'a> LineReader<'a> {
+    /// Start receiving with the UARTE0, that needs to be configured already
+    pub fn new(buffers: &'a mut [[u8; BUFFER_LENGTH]; 2]) -> Self {
+        // The HAL `Uarte` owns the peripheral, but it only uses it to transmit
+        let uarte = unsafe { &*UARTE0::ptr() };
+
+        for buffer in buffers.iter_mut() {
+            *buffer = [0; BUFFER_LENGTH];
+        }
+        uarte.events_rxstarted.reset();
+        uarte.events_endrx.reset();
+        uarte.rxd.ptr.write(|w| unsafe { w.ptr().bits(buffers[0].as_ptr() as u32) });
+        uarte.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(BUFFER_LENGTH as _) });
+        uarte.shorts.modify(|_, w| w.endrx_startrx().enabled());
+        uarte.tasks_startrx.write(|w| w.tasks_startrx().set_bit());
+
+        LineReader {
+            uarte,
+            buffers,
+            next: 1,
+            current: 0,
+            index: 0,
+            ended: 0,
+            line: [0; LINE_LENGTH],
+            line_length: 0,
+            line_complete: false,
+            overflow: false,
+        }
+    }
+
+    /// Next complete line, without the line ending
+    pub fn poll(&mut self) -> Option<&[u8]> {
+        if self.line_complete {
+            self.line_length = 0;
+            self.line_complete = false;
+        }
+
+        if self.uarte.events_rxstarted.read().events_rxstarted().bit_is_set() {
+            self.uarte.events_rxstarted.reset();
+            let ptr = self.buffers[self.next].as_ptr() as u32;
+            self.uarte.rxd.ptr.write(|w| unsafe { w.ptr().bits(ptr) });
+            self.next ^= 1;
+        }
+        if self.uarte.events_endrx.read().events_endrx().bit_is_set() {
+            self.uarte.events_endrx.reset();
+            self.ended += 1;
+        }
+
+        loop {
+            if self.index == BUFFER_LENGTH {
+                if self.ended == 0 {
+                    return None;
+                }
+                self.buffers[self.current] = [0; BUFFER_LENGTH];
+                self.ended -= 1;
+                self.current ^= 1;
+                self.index = 0;
+            }
+
+            let byte = unsafe { ptr::read_volatile(&self.buffers[self.current][self.index]) };
+            if byte == 0 && self.ended == 0 {
+                return None;
+            }
+            self.index += 1;
+
+            match byte {
+                b'\r' | b'\n' => {
+                    if self.overflow {
+                        self.overflow = false;
+                        self.line_length = 0;
+                    }
+                    else if self.line_length > 0 {
+                        self.line_complete = true;
+                        return Some(&self.line[..self.line_length]);
+                    }
+                },
+                0 => {},
+                _ if self.line_length < LINE_LENGTH => {
+                    self.line[self.line_length] = byte;
+                    self.line_length += 1;
+                },
+                _ => self.overflow = true,
+            }
+        }
+    }
+}
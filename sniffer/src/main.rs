@@ -6,6 +6,12 @@
 
 use cortex_m_rt::entry;
 
+mod commands;
+mod config;
+mod discovery;
+mod line_reader;
+mod pcap;
+
 #[allow(unused_imports)]
 //use panic_halt;
 use panic_semihosting as _;
@@ -20,7 +26,7 @@ use embedded_hal::timer::CountDown;
 use nrf52840_hal as hal;
 use hal::timer::{TimerExt, Timer};
 use hal::clocks::{ClocksExt, Clocks};
-use hal::{Uarte, target::UARTE0};
+use hal::{Uarte, target::{TIMER0, UARTE0}};
 
 use nrf52840_mdk::Leds;
 
@@ -30,20 +36,46 @@ use nrf52_radio::tx_power::TxPower;
 use nrf52_radio::mode::Mode;
 use nrf52_radio::frequency::Frequency;
 use nrf52_radio::logical_address::LogicalAddress;
-use nrf52_radio::rx_addresses::RX_ADDRESS_ALL;
+use nrf52_radio::rx_addresses::RxAddresses;
 use nrf52_radio::base_address::BaseAddresses;
+use nrf52_radio::dma_buffer::DmaBuffer;
+use nrf52_radio::shortcuts::Shortcuts;
+use nrf52_radio::timestamps::Timestamps;
 
-use nrf52_esb::{Esb, RxConfig, TxConfig, RxPacket};
-use nrf52_esb::protocol::Protocol as EsbProtocol;
+use nrf52_esb::{Esb, Error as EsbError, RxConfig, TxConfig, RxPacket};
+use nrf52_esb::protocol::{Protocol as EsbProtocol, buffer_length, HEADER_LENGTH};
+use nrf52_esb::promiscuous::{Promiscuous, Candidate, RAW_LENGTH};
+use nrf52_esb::frame::Crc;
 use nrf52840_mdk::{leds_welcome, Board};
 
+use crate::commands::Command;
+use crate::config::{Config, Output, SnifferMode};
+use crate::discovery::Discovery;
+use crate::line_reader::{LineReader, BUFFER_LENGTH};
+use crate::pcap::{Clock, Packet};
+
 const LED_INTERVAL: u32 = 1_000_000;
 
+/// The buffers fit any protocol that can be set at runtime
+const MAX_PAYLOAD_LENGTH: u8 = 252;
+
+/// PPI channels that capture the packet timestamps
+const TIMESTAMP_ADDRESS_PPI_CHANNEL: u8 = 0;
+const TIMESTAMP_END_PPI_CHANNEL: u8 = 1;
+
+/// Time spent on each channel when discovering them
+const DWELL_INTERVAL: u32 = 100_000;
+
+/// Buffers handed from one mode to the next
+type Buffers<'a> = (DmaBuffer<'a>, DmaBuffer<'a>);
 
 #[entry]
 fn main() -> ! {
     let mut board = Board::take().unwrap();
 
+    let mut command_buffers = [[0u8; BUFFER_LENGTH]; 2];
+    let commands = LineReader::new(&mut command_buffers);
+
     drop(board.uart_daplink.write_str("Initialising ...\n"));
 
     let mut timer = board.TIMER0.constrain();
@@ -52,60 +84,374 @@ fn main() -> ! {
 
     let clocks = board.CLOCK.constrain().enable_ext_hfosc();
 
-    let radio = Radio::new(board.RADIO, &clocks);
+    let timestamps = Timestamps::new(&board.TIMER1, &board.RADIO, &board.PPI,
+                                     TIMESTAMP_ADDRESS_PPI_CHANNEL, TIMESTAMP_END_PPI_CHANNEL).unwrap();
+    let clock = Clock::new(timestamps.now());
+
+    let mut radio = Radio::new(board.RADIO, &clocks);
     radio
-        .set_tx_power(TxPower::Pos8dBm)
-        .set_mode(Mode::Nrf2Mbit)
-        .set_frequency(Frequency::from_2400mhz_channel(78))
-        .set_base_addresses(BaseAddresses::from_same_four_bytes([0xa0, 0xb1, 0xc2, 0xd3]))
-        .set_prefixes([0xe0, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7])
-        .set_rx_addresses(RX_ADDRESS_ALL)
+        .set_tx_power(TxPower::Pos8dBm).unwrap()
         .enable_power();
 
-    let mut buffer1 = [0x00u8; 34];
-    let mut buffer2 = [0x00u8; 34];
+    let mut buffer1 = [0x00u8; buffer_length(MAX_PAYLOAD_LENGTH)];
+    let mut buffer2 = [0x00u8; buffer_length(MAX_PAYLOAD_LENGTH)];
+    let mut buffers = (DmaBuffer::new(&mut buffer1).unwrap(), DmaBuffer::new(&mut buffer2).unwrap());
 
-    // TODO EsbProtocol and buffers size must match
-    let mut esb = Esb::new(radio, EsbProtocol::fixed_payload_length(32), &mut buffer1, &mut buffer2);
-    esb.set_crc_16bits();
+    let mut sniffer = Sniffer {
+        config: Config::default(),
+        commands,
+        timer,
+        timestamps,
+        clock,
+        leds: &mut board.leds,
+        uarte: &mut board.uart_daplink,
+    };
 
-    let rx_config = RxConfig::default().with_skip_ack(true);
+    // Every mode runs until a command selects another one
+    loop {
+        let (next_radio, next_buffers) = match sniffer.config.sniffer_mode {
+            SnifferMode::Esb => sniffer.run_esb(radio, buffers),
+            SnifferMode::Promiscuous => sniffer.run_promiscuous(radio, buffers),
+            SnifferMode::Discovery => sniffer.run_discovery(radio, buffers),
+        };
+        radio = next_radio;
+        buffers = next_buffers;
+    }
+}
 
-    drop(board.uart_daplink.write_str("Starting ...\n"));
+/// Everything the modes share besides the radio and its buffers
+struct Sniffer<'a> {
+    config: Config,
+    commands: LineReader<'a>,
+    timer: Timer<TIMER0>,
+    timestamps: Timestamps<'a>,
+    clock: Clock,
+    leds: &'a mut Leds,
+    uarte: &'a mut Uarte<UARTE0>,
+}
 
-    board.leds.green.on();
-    board.leds.blue.off();
-    timer.start(LED_INTERVAL);
+impl<'a> Sniffer<'a> {
+    /// Print the packets to the configured addresses
+    fn run_esb<'r, LFOSC, LFSTAT>(&mut self, radio: Radio<'r, LFOSC, LFSTAT>, buffers: Buffers<'r>)
+                                  -> (Radio<'r, LFOSC, LFSTAT>, Buffers<'r>) {
 
-    loop {
-        if let Err(error) = esb.start_rx(rx_config) {
-            board.leds.green.off();
-            board.leds.red.on();
-            drop(board.uart_daplink.write_fmt(format_args!("Error: {:?}\n", error)));
+        let (buffer1, buffer2) = buffers;
+        let mut esb = Esb::new(radio, self.config.protocol, buffer1, buffer2).unwrap();
+        // Sample the RSSI of every packet
+        esb.radio
+            .set_rx_addresses(RxAddresses::all())
+            .set_shortcuts(Shortcuts::ADDRESS_RSSISTART);
+        configure_esb(&mut esb, &self.config);
+
+        drop(self.uarte.write_str("Starting ...\n"));
+        self.config.print(self.uarte);
+
+        self.leds.green.on();
+        self.leds.blue.off();
+        self.timer.start(LED_INTERVAL);
+
+        start_rx(&mut esb, &self.config, self.leds, self.uarte);
+        loop {
+            match esb.wait_rx() {
+                Ok(()) => {
+                    self.leds.blue.invert();
+                    let packet = esb.get_last_received_packet().unwrap();
+                    let buf = esb.get_rx_buffer();
+                    let payload_length = match self.config.protocol {
+                        EsbProtocol::DynamicPayloadLength(_) => usize::from(packet.length),
+                        EsbProtocol::FixedPayloadLength(length) => usize::from(length),
+                    };
+                    match self.config.output {
+                        Output::Text => print_packet(&packet, &buf[..HEADER_LENGTH + payload_length], self.uarte),
+                        Output::Pcap => {
+                            let address = self.config.address(packet.address.value() as usize);
+                            let packet = Packet {
+                                timestamp: self.clock.extend(self.timestamps.get_last().address),
+                                frequency: self.config.frequency,
+                                rssi: -(esb.radio.get_rssi_sample() as i8),
+                                logical_address: Some(packet.address),
+                                address: &address,
+                                length: packet.length,
+                                pid: packet.pid,
+                                no_ack: packet.no_ack,
+                                crc_ok: packet.crc_ok,
+                                payload: &buf[HEADER_LENGTH..HEADER_LENGTH + payload_length],
+                            };
+                            write_pcap_packet(&packet, self.uarte);
+                        },
+                    }
+                    start_rx(&mut esb, &self.config, self.leds, self.uarte);
+                },
+                Err(nb::Error::WouldBlock) => {},
+                Err(nb::Error::Other(error)) => {
+                    self.leds.green.off();
+                    self.leds.red.on();
+                    print_error(&error, &self.config, self.uarte);
+                    drop(block!(esb.reset()));
+                    start_rx(&mut esb, &self.config, self.leds, self.uarte);
+                }
+            }
+
+            if let Some(line) = self.commands.poll() {
+                match block!(esb.abort()) {
+                    Ok(()) => {},
+                    // Only a reset brings the driver back from an error
+                    Err(EsbError::ResetRequired) => drop(block!(esb.reset())),
+                    Err(error) => {
+                        print_error(&error, &self.config, self.uarte);
+                        drop(block!(esb.reset()));
+                    },
+                }
+                if execute(line, &mut self.config, self.uarte) {
+                    if self.config.sniffer_mode != SnifferMode::Esb {
+                        let (radio, buffer1, buffer2) = esb.free();
+                        return (radio, (buffer1.unwrap(), buffer2.unwrap()));
+                    }
+                    configure_esb(&mut esb, &self.config);
+                }
+                start_rx(&mut esb, &self.config, self.leds, self.uarte);
+            }
+
+            if let Ok(()) = self.timer.wait() {
+                self.leds.blue.invert();
+                // Keep the clock going when there are no packets
+                self.clock.extend(self.timestamps.now());
+                self.timer.start(LED_INTERVAL);
+            }
         }
-        else {
-            board.leds.green.on();
-            board.leds.red.off();
-            if let Err(error) = block!(esb.wait_rx()) {
-                board.leds.green.off();
-                board.leds.red.on();
-                drop(board.uart_daplink.write_fmt(format_args!("Error: {:?}\n", error)));
+    }
+
+    /// Print the frames to any address
+    fn run_promiscuous<'r, LFOSC, LFSTAT>(&mut self, radio: Radio<'r, LFOSC, LFSTAT>, buffers: Buffers<'r>)
+                                          -> (Radio<'r, LFOSC, LFSTAT>, Buffers<'r>) {
+
+        let (raw_buffer, buffer2) = buffers;
+        configure_radio(&radio, &self.config);
+        // The commands keep the protocol within what the promiscuous receiver supports
+        let mut promiscuous = Promiscuous::new(radio, self.config.protocol, self.config.crc, raw_buffer).unwrap();
+
+        // Sample the RSSI of every frame
+        promiscuous.radio.set_shortcuts(promiscuous.radio.get_shortcuts() | Shortcuts::ADDRESS_RSSISTART);
+
+        drop(self.uarte.write_str("Starting promiscuous ...\n"));
+        self.config.print(self.uarte);
+
+        self.timer.start(LED_INTERVAL);
+        drop(promiscuous.start_rx());
+        loop {
+            match promiscuous.wait_rx() {
+                Ok(candidate) => {
+                    self.leds.blue.invert();
+                    match self.config.output {
+                        Output::Text => print_candidate(&candidate, promiscuous.get_payload(), self.uarte),
+                        Output::Pcap => {
+                            let packet = Packet {
+                                timestamp: self.clock.extend(self.timestamps.get_last().address),
+                                frequency: self.config.frequency,
+                                rssi: -(promiscuous.radio.get_rssi_sample() as i8),
+                                logical_address: None,
+                                address: candidate.address(),
+                                length: candidate.pcf.length,
+                                pid: candidate.pcf.pid,
+                                no_ack: candidate.pcf.no_ack,
+                                crc_ok: true,
+                                payload: promiscuous.get_payload(),
+                            };
+                            write_pcap_packet(&packet, self.uarte);
+                        },
+                    }
+                    drop(promiscuous.start_rx());
+                },
+                Err(nb::Error::WouldBlock) => {},
+                Err(nb::Error::Other(error)) => {
+                    self.leds.red.on();
+                    print_error(&error, &self.config, self.uarte);
+                    disable_radio(&promiscuous.radio);
+                    drop(promiscuous.start_rx());
+                }
             }
-            else {
-                board.leds.blue.invert();
-                let packet = esb.get_last_received_packet().unwrap();
-                let buf = esb.get_rx_buffer();
-                print_packet(&packet, buf, &mut board.uart_daplink);
+
+            if let Some(line) = self.commands.poll() {
+                disable_radio(&promiscuous.radio);
+                if execute(line, &mut self.config, self.uarte) {
+                    if self.config.sniffer_mode != SnifferMode::Promiscuous {
+                        let (radio, raw_buffer) = promiscuous.free();
+                        return (radio, (raw_buffer.unwrap(), buffer2));
+                    }
+                    // The addresses are the ones of the promiscuous mode
+                    promiscuous.radio
+                        .set_mode(self.config.mode)
+                        .set_frequency(self.config.frequency);
+                    if let Err(error) = promiscuous.set_protocol(self.config.protocol, self.config.crc) {
+                        print_error(&error, &self.config, self.uarte);
+                    }
+                }
+                drop(promiscuous.start_rx());
+            }
+
+            // Keep the clock going when there are no packets
+            if let Ok(()) = self.timer.wait() {
+                self.clock.extend(self.timestamps.now());
+                self.timer.start(LED_INTERVAL);
             }
         }
+    }
+
+    /// Sweep the channels listening to any address, and report the addresses found on each channel
+    fn run_discovery<'r, LFOSC, LFSTAT>(&mut self, radio: Radio<'r, LFOSC, LFSTAT>, buffers: Buffers<'r>)
+                                        -> (Radio<'r, LFOSC, LFSTAT>, Buffers<'r>) {
+
+        let (raw_buffer, buffer2) = buffers;
+        configure_radio(&radio, &self.config);
+        // The commands keep the protocol within what the promiscuous receiver supports
+        let mut promiscuous = Promiscuous::new(radio, self.config.protocol, self.config.crc, raw_buffer).unwrap();
+        let mut discovery = Discovery::new();
+
+        drop(self.uarte.write_str("Starting discovery ...\n"));
+        self.config.print(self.uarte);
+
+        loop {
+            promiscuous.radio.set_frequency(discovery.frequency());
+            self.timer.start(DWELL_INTERVAL);
+            drop(promiscuous.start_rx());
+            while self.timer.wait().is_err() {
+                match promiscuous.wait_rx() {
+                    Ok(candidate) => {
+                        discovery.record(candidate.address());
+                        drop(promiscuous.start_rx());
+                    },
+                    Err(nb::Error::WouldBlock) => {},
+                    Err(nb::Error::Other(_)) => {
+                        disable_radio(&promiscuous.radio);
+                        drop(promiscuous.start_rx());
+                    },
+                }
+            }
+            disable_radio(&promiscuous.radio);
+            // Keep the clock going, as there is no other tick
+            self.clock.extend(self.timestamps.now());
 
-        if let Ok(()) = timer.wait() {
-            board.leds.blue.invert();
-            timer.start(LED_INTERVAL);
+            if let Some(line) = self.commands.poll() {
+                if execute(line, &mut self.config, self.uarte) {
+                    if self.config.sniffer_mode != SnifferMode::Discovery {
+                        let (radio, raw_buffer) = promiscuous.free();
+                        return (radio, (raw_buffer.unwrap(), buffer2));
+                    }
+                    promiscuous.radio.set_mode(self.config.mode);
+                    if let Err(error) = promiscuous.set_protocol(self.config.protocol, self.config.crc) {
+                        print_error(&error, &self.config, self.uarte);
+                    }
+                }
+            }
+
+            if discovery.next(DWELL_INTERVAL) {
+                self.leds.blue.invert();
+                discovery.report(&self.config, self.uarte);
+            }
         }
     }
 }
 
+/// Parse and apply a command, and echo the configuration.
+/// Returns whether the configuration needs to be applied to the radio.
+fn execute(line: &[u8], config: &mut Config, uarte: &mut Uarte<UARTE0>) -> bool {
+    let result = Command::parse(line).and_then(|command| {
+        command.check(config)?;
+        Ok(command)
+    });
+    match result {
+        Ok(command) => {
+            command.apply(config);
+            config.print(uarte);
+        },
+        Err(error) => drop(uarte.write_fmt(format_args!("Command error: {:?}\n", error))),
+    }
+    // The host tool finds its way back to the packets after the text with a new pcap header
+    if let Output::Pcap = config.output {
+        drop(uarte.write(&pcap::global_header()));
+    }
+    result.is_ok()
+}
+
+fn configure_radio<LFOSC, LFSTAT>(radio: &Radio<'_, LFOSC, LFSTAT>, config: &Config) {
+    radio
+        .set_mode(config.mode)
+        .set_frequency(config.frequency)
+        .set_base_addresses(BaseAddresses::from_same_four_bytes(config.base_address))
+        .set_prefixes(config.prefixes);
+}
+
+/// The radio needs to be in standby
+fn configure_esb<LFOSC, LFSTAT>(esb: &mut Esb<'_, LFOSC, LFSTAT>, config: &Config) {
+    configure_radio(&esb.radio, config);
+    // The buffers fit any protocol that can be configured
+    esb.set_protocol(config.protocol).unwrap();
+    match config.crc {
+        Crc::Disabled => esb.set_crc_disabled(),
+        Crc::OneByte => esb.set_crc_8bits(),
+        Crc::TwoBytes => esb.set_crc_16bits(),
+    };
+}
+
+fn rx_config(config: &Config) -> RxConfig {
+    let rx_config = RxConfig::default().with_skip_ack(true);
+    // The captures keep the packets with a wrong CRC, flagged, so Wireshark can show them
+    match config.output {
+        Output::Text => rx_config,
+        Output::Pcap => rx_config.with_crc_errors(true),
+    }
+}
+
+fn start_rx<LFOSC, LFSTAT>(esb: &mut Esb<'_, LFOSC, LFSTAT>, config: &Config, leds: &mut Leds, uarte: &mut Uarte<UARTE0>) {
+    if let Err(error) = esb.start_rx(rx_config(config)) {
+        leds.green.off();
+        leds.red.on();
+        print_error(&error, config, uarte);
+        drop(block!(esb.reset()));
+    }
+    else {
+        leds.green.on();
+        leds.red.off();
+    }
+}
+
+fn disable_radio<LFOSC, LFSTAT>(radio: &Radio<'_, LFOSC, LFSTAT>) {
+    if !radio.is_disabled() {
+        radio.disable();
+        drop(block!(radio.wait_disabled()));
+    }
+}
+
+/// Errors are only printed as text, not to break the pcap captures
+fn print_error<E: core::fmt::Debug>(error: &E, config: &Config, uarte: &mut Uarte<UARTE0>) {
+    if let Output::Text = config.output {
+        drop(uarte.write_fmt(format_args!("Error: {:?}\n", error)));
+    }
+}
+
+fn write_pcap_packet(packet: &Packet, uarte: &mut Uarte<UARTE0>) {
+    drop(uarte.write(&pcap::record_header(packet)));
+    if !packet.payload.is_empty() {
+        drop(uarte.write(packet.payload));
+    }
+}
+
+fn print_candidate(candidate: &Candidate, payload: &[u8], uarte: &mut Uarte<UARTE0>) {
+    drop(uarte.write_str("["));
+    for b in candidate.address().iter() {
+        drop(uarte.write_fmt(format_args!("{:02x}", *b)));
+    }
+    let no_ack = if candidate.pcf.no_ack { 1 } else { 0 };
+    drop(uarte.write_fmt(format_args!(" {:02} {} {}] ",
+                                      candidate.pcf.length,
+                                      candidate.pcf.pid,
+                                      no_ack)));
+    for b in payload.iter() {
+        drop(uarte.write_fmt(format_args!("{:02x} ", *b)));
+    }
+    drop(uarte.write_char('\n'));
+}
+
 fn print_packet(packet: &RxPacket, buf: &[u8], uarte: &mut Uarte<UARTE0>) {
     let header = ((buf[0] as u16) << 8) | (buf[1] as u16);
     let buf = &buf[2..];
@@ -0,0 +1,138 @@
+//! Capture in the pcap format, to analyse the packets with Wireshark
+//!
+//! The capture starts with the pcap global header, followed by a record for each packet.
+//! The records use the `LINKTYPE_USER0` link type, with a pseudo header before the payload,
+//! all the fields in little endian:
+//!
+//! ```text
+//! 0   version          u8    1
+//! 1   flags            u8    CRC ok (0x01), no ack (0x02), logical address known (0x04)
+//! 2   frequency        u16   MHz
+//! 4   rssi             i8    dBm
+//! 5   logical address  u8
+//! 6   address length   u8
+//! 7   address          [u8; 5], base address followed by the prefix
+//! 12  length           u8    from the PCF
+//! 13  pid              u8    from the PCF
+//! 14  payload
+//! ```
+//!
+//! The timestamps come from the radio timer, in microseconds since the sniffer started.
+//! The `sniffer-pcap` host tool moves them to the host clock, relative to the first packet it receives.
+
+use nrf52_radio::frequency::Frequency;
+use nrf52_radio::logical_address::LogicalAddress;
+
+const MAGIC: u32 = 0xa1b2_c3d4;
+const VERSION_MAJOR: u16 = 2;
+const VERSION_MINOR: u16 = 4;
+const LINKTYPE_USER0: u32 = 147;
+
+pub const GLOBAL_HEADER_LENGTH: usize = 24;
+pub const RECORD_HEADER_LENGTH: usize = 16;
+pub const PSEUDO_HEADER_LENGTH: usize = 14;
+
+const PSEUDO_HEADER_VERSION: u8 = 1;
+const MAX_ADDRESS_LENGTH: usize = 5;
+const MAX_PAYLOAD_LENGTH: usize = 255;
+const SNAPLEN: u32 = (PSEUDO_HEADER_LENGTH + MAX_PAYLOAD_LENGTH) as u32;
+
+const FLAG_CRC_OK: u8 = 0x01;
+const FLAG_NO_ACK: u8 = 0x02;
+const FLAG_LOGICAL_ADDRESS: u8 = 0x04;
+
+pub struct Packet<'a> {
+    /// Microseconds since the sniffer started
+    pub timestamp: u64,
+    pub frequency: Frequency,
+    pub rssi: i8,
+    pub logical_address: Option<LogicalAddress>,
+    pub address: &'a [u8],
+    pub length: u8,
+    pub pid: u8,
+    pub no_ack: bool,
+    pub crc_ok: bool,
+    pub payload: &'a [u8],
+}
+
+pub fn global_header() -> [u8; GLOBAL_HEADER_LENGTH] {
+    let mut header = [0u8; GLOBAL_HEADER_LENGTH];
+    header[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    header[4..6].copy_from_slice(&VERSION_MAJOR.to_le_bytes());
+    header[6..8].copy_from_slice(&VERSION_MINOR.to_le_bytes());
+    // thiszone and sigfigs are left to 0
+    header[16..20].copy_from_slice(&SNAPLEN.to_le_bytes());
+    header[20..24].copy_from_slice(&LINKTYPE_USER0.to_le_bytes());
+    header
+}
+
+/// Record header followed by the pseudo header, the payload needs to be sent right after it
+pub fn record_header(packet: &Packet) -> [u8; RECORD_HEADER_LENGTH + PSEUDO_HEADER_LENGTH] {
+    let payload_length = packet.payload.len().min(MAX_PAYLOAD_LENGTH);
+    let length = (PSEUDO_HEADER_LENGTH + payload_length) as u32;
+    let seconds = (packet.timestamp / 1_000_000) as u32;
+    let micros = (packet.timestamp % 1_000_000) as u32;
+
+    let mut header = [0u8; RECORD_HEADER_LENGTH + PSEUDO_HEADER_LENGTH];
+    header[0..4].copy_from_slice(&seconds.to_le_bytes());
+    header[4..8].copy_from_slice(&micros.to_le_bytes());
+    header[8..12].copy_from_slice(&length.to_le_bytes());
+    header[12..16].copy_from_slice(&length.to_le_bytes());
+
+    let pseudo = &mut header[RECORD_HEADER_LENGTH..];
+    let mut flags = 0;
+    if packet.crc_ok {
+        flags |= FLAG_CRC_OK;
+    }
+    if packet.no_ack {
+        flags |= FLAG_NO_ACK;
+    }
+    if packet.logical_address.is_some() {
+        flags |= FLAG_LOGICAL_ADDRESS;
+    }
+    let address_length = packet.address.len().min(MAX_ADDRESS_LENGTH);
+    pseudo[0] = PSEUDO_HEADER_VERSION;
+    pseudo[1] = flags;
+    pseudo[2..4].copy_from_slice(&packet.frequency.mhz().to_le_bytes());
+    pseudo[4] = packet.rssi as u8;
+    pseudo[5] = packet.logical_address.map_or(0, |address| address.value() as u8);
+    pseudo[6] = address_length as u8;
+    pseudo[7..7 + address_length].copy_from_slice(&packet.address[..address_length]);
+    pseudo[12] = packet.length;
+    pseudo[13] = packet.pid;
+    header
+}
+
+/// Extends the 32 bits timer, that wraps around every 71 minutes, to 64 bits.
+/// It needs to be called at least once every 35 minutes, half the period, to not miss a wrap around,
+/// so it is also called on a regular tick and not only on packets.
+/// A time older than the last one, such as a packet timestamp read after a tick, is not taken for a wrap around.
+pub struct Clock {
+    high: u32,
+    last: u32,
+}
+
+impl Clock {
+    /// Start from the current time of the timer
+    pub fn new(now: u32) -> Self {
+        Clock {
+            high: 0,
+            last: now,
+        }
+    }
+
+    pub fn extend(&mut self, time: u32) -> u64 {
+        let last = u64::from(self.high) << 32 | u64::from(self.last);
+        let elapsed = time.wrapping_sub(self.last);
+        if elapsed < 1 << 31 {
+            if time < self.last {
+                self.high += 1;
+            }
+            self.last = time;
+            last + u64::from(elapsed)
+        }
+        else {
+            last.saturating_sub(u64::from(self.last.wrapping_sub(time)))
+        }
+    }
+}